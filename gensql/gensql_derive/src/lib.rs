@@ -47,6 +47,8 @@ const TABLE_ATTR_NOT_FIELD: &str = "not_field";
 const TABLE_ATTR_IGNORE: &str = "ignore";
 const SERDE_ATTR: &str = "serde";
 const SERDE_ATTR_FLATTEN: &str = "flatten";
+const SERDE_ATTR_RENAME: &str = "rename";
+const DOC_ATTR: &str = "doc";
 
 struct TagField<'a> {
     id: bool,
@@ -127,6 +129,49 @@ pub fn table(attr: TokenStream, item: TokenStream) -> TokenStream {
         ));
     }
 
+    // JSON Schema生成函数, 用于生成接口文档
+    {
+        let flatten_props_quote = all_fields
+            .iter()
+            .filter(|f| f.serde_flatten && !f.ignore)
+            .map(|f| {
+                let ty = &f.field.ty;
+                quote! {
+                    let mut inner = <#ty>::json_schema();
+                    if let Some(serde_json::Value::Object(m)) = inner.get_mut("properties").map(serde_json::Value::take) {
+                        props.extend(m);
+                    }
+                }
+            });
+        let props_quote = all_fields
+            .iter()
+            .filter(|f| !f.serde_flatten)
+            .map(|f| {
+                let name = get_json_name(f.field);
+                let schema = get_type_schema(&f.field.ty);
+                let desc = get_doc(f.field).map(|doc| {
+                    quote!(schema["description"] = serde_json::Value::from(#doc);)
+                });
+                quote! {
+                    let mut schema = serde_json::json!(#schema);
+                    #desc
+                    props.insert(String::from(#name), schema);
+                }
+            });
+
+        out.extend(quote! {
+            impl #struct_name {
+                /// 对象的JSON Schema, 用于生成接口文档
+                pub fn json_schema() -> serde_json::Value {
+                    let mut props = serde_json::Map::new();
+                    #(#flatten_props_quote)*
+                    #(#props_quote)*
+                    serde_json::json!({ "type": "object", "properties": props })
+                }
+            }
+        });
+    }
+
     // 增删改查函数实现
     if table_name.is_some() && id_field.is_some() {
         let table_name = table_name.as_ref().map_or("", |s| s.as_str());
@@ -566,3 +611,101 @@ fn get_fields_array<'a>(fields: &'a [TagField], only_table: bool) -> Vec<&'a Ide
         .map(|f| f.field.ident.as_ref().unwrap())
         .collect()
 }
+
+/// 获取字段序列化为json时的名称, 优先使用 #[serde(rename = "xxx")], 否则转换为驼峰格式
+fn get_json_name(field: &Field) -> String {
+    for attr in &field.attrs {
+        if !attr.path().is_ident(SERDE_ATTR) {
+            continue;
+        }
+        let nested = attr
+            .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            .unwrap();
+        for meta in nested {
+            if let Meta::NameValue(nv) = meta {
+                if nv.path.is_ident(SERDE_ATTR_RENAME) {
+                    if let Expr::Lit(syn::ExprLit {lit: syn::Lit::Str(ref s), ..}) = nv.value {
+                        return s.value();
+                    }
+                }
+            }
+        }
+    }
+
+    // 与serde的rename_all = "camelCase"规则保持一致
+    let name = field.ident.as_ref().unwrap().to_string();
+    let mut result = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            result.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// 获取字段的文档注释, 多行注释使用换行符连接
+fn get_doc(field: &Field) -> Option<String> {
+    let lines: Vec<_> = field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident(DOC_ATTR))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(syn::MetaNameValue {
+                value: Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(s), .. }),
+                ..
+            }) => Some(s.value().trim().to_owned()),
+            _ => None,
+        })
+        .collect();
+
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+/// 根据字段类型生成JSON Schema, 无法识别的类型生成空的Schema(允许任意值)
+fn get_type_schema(ty: &syn::Type) -> proc_macro2::TokenStream {
+    let seg = match ty {
+        syn::Type::Reference(r) => return get_type_schema(&r.elem),
+        syn::Type::Path(p) => p.path.segments.last(),
+        _ => None,
+    };
+    let Some(seg) = seg else {
+        return quote!({});
+    };
+
+    // 泛型类型的第一个类型参数, 如Vec<T>中的T
+    let inner = match &seg.arguments {
+        syn::PathArguments::AngleBracketed(args) => args.args.iter().find_map(|v| match v {
+            syn::GenericArgument::Type(t) => Some(t),
+            _ => None,
+        }),
+        _ => None,
+    };
+
+    match (seg.ident.to_string().as_str(), inner) {
+        ("String" | "str", _) => quote!({ "type": "string" }),
+        ("bool", _) => quote!({ "type": "boolean" }),
+        ("i8" | "i16" | "i32" | "u8" | "u16" | "u32", _) => {
+            quote!({ "type": "integer", "format": "int32" })
+        }
+        ("i64" | "u64" | "isize" | "usize", _) => quote!({ "type": "integer", "format": "int64" }),
+        ("f32", _) => quote!({ "type": "number", "format": "float" }),
+        ("f64", _) => quote!({ "type": "number", "format": "double" }),
+        ("LocalTime", _) => quote!({ "type": "string", "example": "2023-06-15 08:00:00" }),
+        ("Option" | "Box" | "Arc" | "Rc", Some(t)) => get_type_schema(t),
+        ("Vec" | "VecDeque" | "HashSet" | "BTreeSet", Some(t)) => {
+            let items = get_type_schema(t);
+            quote!({ "type": "array", "items": #items })
+        }
+        _ => quote!({}),
+    }
+}
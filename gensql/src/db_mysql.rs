@@ -2,7 +2,9 @@
 // author: kiven
 // slince 2023-08-24
 
+use std::future::Future;
 use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 use futures::future::BoxFuture;
 use futures::FutureExt;
@@ -13,7 +15,7 @@ pub use mysql_async::{
     prelude::{FromRow, FromValue, ToValue, StatementLike},
     Column, FromRowError, FromValueError, Row, Value,
 };
-use mysql_async::{Pool, TxOpts};
+use mysql_async::{Opts, Pool, TxOpts};
 
 pub type DbResult<T> = Result<T, DbError>;
 
@@ -25,6 +27,19 @@ pub struct DbConfig<'a> {
     pub db: &'a str,
 }
 
+/// 连接池使用情况
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolStats {
+    /// 连接池允许的最大连接数
+    pub max_size: usize,
+    /// 当前正在使用的连接数
+    pub in_use: usize,
+    /// 累计成功获取连接的次数
+    pub acquired: u64,
+    /// 累计获取连接失败的次数
+    pub errors: u64,
+}
+
 pub trait FastFromRow {
     fn fast_from_row(index_vec: &[i32], row: Row) -> Self;
     fn fast_map_index(columns: &[&[u8]]) -> Vec<i32>;
@@ -108,37 +123,58 @@ pub trait Queryable {
         I::IntoIter: Send;
 }
 
-pub struct Conn(mysql_async::Conn);
-pub struct Transaction<'a>(mysql_async::Transaction<'a>);
+pub struct Conn(mysql_async::Conn, ConnGuard);
+/// 从连接池直接开启的事务持有连接占用标记, 从Conn开启的事务由Conn持有
+pub struct Transaction<'a>(mysql_async::Transaction<'a>, Option<ConnGuard>);
+
+/// 连接占用标记, 创建时增加正在使用的连接数, 释放时减少
+struct ConnGuard;
 
 static mut DB_POOL: MaybeUninit<Pool> = MaybeUninit::uninit();
 #[cfg(debug_assertions)]
 static mut INITED: bool = false;
 
+static POOL_MAX_SIZE: AtomicUsize = AtomicUsize::new(0);
+static POOL_IN_USE: AtomicUsize = AtomicUsize::new(0);
+static POOL_ACQUIRED: AtomicU64 = AtomicU64::new(0);
+static POOL_ERRORS: AtomicU64 = AtomicU64::new(0);
+
 /// 初始化连接池(必须在程序开始时调用)
 pub fn init_pool(cfg: DbConfig) -> DbResult<()> {
     let port = cfg.port.parse::<u32>().unwrap_or(3306);
     let url = format!("mysql://{}:{}@{}:{}/{}", cfg.user, cfg.pass, cfg.host, port, cfg.db);
-    init_db_pool(Pool::from_url(url)?);
+    let opts = Opts::from_url(&url)?;
+    POOL_MAX_SIZE.store(opts.pool_opts().constraints().max(), Ordering::Relaxed);
+    init_db_pool(Pool::new(opts));
     Ok(())
 }
 
+/// 获取连接池使用情况
+pub fn pool_stats() -> PoolStats {
+    PoolStats {
+        max_size: POOL_MAX_SIZE.load(Ordering::Relaxed),
+        in_use: POOL_IN_USE.load(Ordering::Relaxed),
+        acquired: POOL_ACQUIRED.load(Ordering::Relaxed),
+        errors: POOL_ERRORS.load(Ordering::Relaxed),
+    }
+}
+
 /// 从连接池中获取连接
 pub async fn get_conn() -> DbResult<Conn> {
-    let c = get_db_pool().get_conn().await?;
-    Ok(Conn(c))
+    let (c, guard) = acquire(get_db_pool().get_conn()).await?;
+    Ok(Conn(c, guard))
 }
 
 /// 从连接池中开启事务并返回事务对象
 pub async fn start_transaction() -> DbResult<Transaction<'static>> {
-    let trans = get_db_pool().start_transaction(TxOpts::new()).await?;
-    Ok(Transaction(trans))
+    let (trans, guard) = acquire(get_db_pool().start_transaction(TxOpts::new())).await?;
+    Ok(Transaction(trans, Some(guard)))
 }
 
 // mysql连接测试
 pub async fn try_connect() -> DbResult<()> {
-    let mut conn = get_db_pool().get_conn().await?;
-    QA::ping(&mut conn).await
+    let mut conn = get_conn().await?;
+    QA::ping(&mut conn.0).await
 }
 
 /// 执行sql, 返回受影响的记录数
@@ -148,9 +184,9 @@ where
     P: Into<Params> + Send,
 {
     // inner_exec_sql(&mut inner_get_conn().await?, stmt, params).await
-    let mut conn = get_db_pool().get_conn().await?;
-    QA::exec_drop(&mut conn, stmt, params).await?;
-    Ok(conn.affected_rows() as u32)
+    let mut conn = get_conn().await?;
+    QA::exec_drop(&mut conn.0, stmt, params).await?;
+    Ok(conn.0.affected_rows() as u32)
 }
 
 /// 插入记录专用sql, 返回受影响的记录数和新记录的id(如果没有新纪录id则返回0)
@@ -159,10 +195,10 @@ where
     S: StatementLike,
     P: Into<Params> + Send,
 {
-    let mut conn = get_db_pool().get_conn().await?;
-    QA::exec_drop(&mut conn, stmt, params).await?;
-    let count = conn.affected_rows() as u32;
-    let new_id = conn.last_insert_id().unwrap_or(0) as u32;
+    let mut conn = get_conn().await?;
+    QA::exec_drop(&mut conn.0, stmt, params).await?;
+    let count = conn.0.affected_rows() as u32;
+    let new_id = conn.0.last_insert_id().unwrap_or(0) as u32;
     Ok((count, new_id))
 }
 
@@ -173,8 +209,8 @@ where
     P: Into<Params> + Send,
     T: FromRow + Send + 'static,
 {
-    let mut conn = get_db_pool().get_conn().await?;
-    QA::exec_first(&mut conn, stmt, params).await
+    let mut conn = get_conn().await?;
+    QA::exec_first(&mut conn.0, stmt, params).await
 }
 
 /// 执行查询多条记录的sql, 返回记录列表
@@ -184,8 +220,8 @@ where
     P: Into<Params> + Send,
     T: FromRow + Send + 'static,
 {
-    let mut conn = get_db_pool().get_conn().await?;
-    QA::exec(&mut conn, stmt, params).await
+    let mut conn = get_conn().await?;
+    QA::exec(&mut conn.0, stmt, params).await
 }
 
 /// 执行查询多条记录的sql, 返回记录列表
@@ -196,8 +232,8 @@ where
     F: FnMut(Row) -> U + Send,
     U: Send,
 {
-    let mut conn = get_db_pool().get_conn().await?;
-    QA::exec_map(&mut conn, stmt, params, f).await
+    let mut conn = get_conn().await?;
+    QA::exec_map(&mut conn.0, stmt, params, f).await
 }
 
 /// 执行给定查询, 将返回的结果合并到给定的变量
@@ -209,8 +245,8 @@ where
     F: FnMut(U, Row) -> U + Send,
     U: Send,
 {
-    let mut conn = get_db_pool().get_conn().await?;
-    QA::exec_fold(&mut conn, stmt, params, init, f).await
+    let mut conn = get_conn().await?;
+    QA::exec_fold(&mut conn.0, stmt, params, init, f).await
 }
 
 /// 执行查询多条记录的sql, 返回记录列表(首次转换结果时记录列对应的序号，加快处理速度)
@@ -221,8 +257,8 @@ where
     P: Into<Params> + Send,
     U: FastFromRow + Send,
 {
-    let mut conn = get_db_pool().get_conn().await?;
-    _sql_query_fast(&mut conn, stmt, params).await
+    let mut conn = get_conn().await?;
+    _sql_query_fast(&mut conn.0, stmt, params).await
 }
 
 /// 使用参数流批量执行给定查询
@@ -234,8 +270,8 @@ where
     I: IntoIterator<Item = P> + Send,
     I::IntoIter: Send,
 {
-    let mut conn = get_db_pool().get_conn().await?;
-    QA::exec_batch(&mut conn, stmt, params_iter).await
+    let mut conn = get_conn().await?;
+    QA::exec_batch(&mut conn.0, stmt, params_iter).await
 }
 
 impl Queryable for Conn {
@@ -354,6 +390,7 @@ impl Conn {
     pub async fn start_transaction(&mut self) -> DbResult<Transaction<'_>> {
         Ok(Transaction(
             (&mut self.0).start_transaction(TxOpts::new()).await?,
+            None,
         ))
     }
 }
@@ -480,6 +517,36 @@ impl Transaction<'_> {
     }
 }
 
+impl ConnGuard {
+    fn new() -> Self {
+        POOL_IN_USE.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        POOL_IN_USE.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 从连接池获取连接(或开启事务), 同时记录连接池的使用情况
+async fn acquire<T, F>(f: F) -> DbResult<(T, ConnGuard)>
+where
+    F: Future<Output = DbResult<T>>,
+{
+    match f.await {
+        Ok(v) => {
+            POOL_ACQUIRED.fetch_add(1, Ordering::Relaxed);
+            Ok((v, ConnGuard::new()))
+        }
+        Err(e) => {
+            POOL_ERRORS.fetch_add(1, Ordering::Relaxed);
+            Err(e)
+        }
+    }
+}
+
 fn init_db_pool(pool: Pool) {
     unsafe {
         #[cfg(debug_assertions)]
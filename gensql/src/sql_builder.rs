@@ -31,6 +31,18 @@ pub trait GeneratorSql {
     fn sql_params(&mut self) -> (&mut Vec<u8>, &mut Vec<Value>);
 }
 
+/// 可复用的查询条件过滤器, 用于将通用的过滤规则(如数据权限)注入到不同的查询语句中
+pub trait SqlFilter {
+    /// 追加过滤条件
+    ///
+    /// Arguments:
+    ///
+    /// * `w`: 查询条件
+    /// * `table`: 要过滤的表名或表别名
+    ///
+    fn apply<T: GeneratorSql>(&self, w: WhereSql<T>, table: &str) -> WhereSql<T>;
+}
+
 #[derive(Default)]
 struct BaseSql {
     pub(crate) sql: Vec<u8>,
@@ -1388,6 +1400,18 @@ impl<T: GeneratorSql> WhereSql<T> {
         self
     }
 
+    /// 使用过滤器追加查询条件
+    ///
+    /// Arguments:
+    ///
+    /// * `table`: 要过滤的表名或表别名
+    /// * `filter`: 过滤器
+    ///
+    #[inline]
+    pub fn filter<F: SqlFilter>(self, table: &str, filter: &F) -> Self {
+        filter.apply(self, table)
+    }
+
 }
 
 impl<T: ToValue> From<T> for InsertValue {
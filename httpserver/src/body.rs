@@ -0,0 +1,171 @@
+//! http response body, either a complete buffer or a stream of chunks fed through a channel
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::{anyhow, Error, Result};
+use http_body_util::Full;
+use hyper::body::{Body as HttpBody, Bytes, Frame, SizeHint};
+use tokio::sync::mpsc;
+
+/// http response body
+pub struct Body {
+    kind: Kind,
+}
+
+enum Kind {
+    Full(Full<Bytes>),
+    Channel(mpsc::Receiver<Result<Bytes>>),
+}
+
+/// The sending half of a streaming body created by [`Body::channel`]
+pub struct BodySender {
+    tx: mpsc::Sender<Result<Bytes>>,
+}
+
+impl Body {
+    /// Create an empty body
+    pub fn empty() -> Self {
+        Self::from(Bytes::new())
+    }
+
+    /// Create a streaming body, the data written through the sender is sent to the client
+    /// chunk by chunk, and the body ends when the sender is dropped
+    ///
+    /// Arguments:
+    ///
+    /// * `buffer`: the maximum number of chunks waiting to be sent
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use httpserver::Body;
+    ///
+    /// let (tx, body) = Body::channel(4);
+    /// tokio::spawn(async move {
+    ///     for i in 0..10 {
+    ///         if tx.send(format!("{i}\n")).await.is_err() {
+    ///             break;
+    ///         }
+    ///     }
+    /// });
+    /// let res = hyper::Response::new(body);
+    /// ```
+    pub fn channel(buffer: usize) -> (BodySender, Body) {
+        let (tx, rx) = mpsc::channel(buffer);
+        let body = Body {
+            kind: Kind::Channel(rx),
+        };
+        (BodySender { tx }, body)
+    }
+}
+
+impl BodySender {
+    /// Send a chunk of data, waiting while the buffer is full,
+    /// returns an error if the client has disconnected
+    pub async fn send(&self, data: impl Into<Bytes>) -> Result<()> {
+        match self.tx.send(Ok(data.into())).await {
+            Ok(_) => Ok(()),
+            #[cfg(not(feature = "english"))]
+            Err(_) => Err(anyhow!("客户端已断开连接")),
+            #[cfg(feature = "english")]
+            Err(_) => Err(anyhow!("the client has disconnected")),
+        }
+    }
+
+    /// Abort the response, the connection is closed without finishing the body,
+    /// so that the client can tell the content is incomplete
+    pub async fn abort(self, err: Error) {
+        let _ = self.tx.send(Err(err)).await;
+    }
+}
+
+impl HttpBody for Body {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>>>> {
+        match &mut self.get_mut().kind {
+            Kind::Full(body) => Pin::new(body).poll_frame(cx).map_err(|e| match e {}),
+            Kind::Channel(rx) => rx.poll_recv(cx).map(|v| v.map(|r| r.map(Frame::data))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.kind {
+            Kind::Full(body) => body.is_end_stream(),
+            Kind::Channel(_) => false,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.kind {
+            Kind::Full(body) => body.size_hint(),
+            Kind::Channel(_) => SizeHint::default(),
+        }
+    }
+}
+
+impl From<Full<Bytes>> for Body {
+    fn from(body: Full<Bytes>) -> Self {
+        Body {
+            kind: Kind::Full(body),
+        }
+    }
+}
+
+macro_rules! impl_from_full {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Body {
+                fn from(value: $t) -> Self {
+                    Body::from(Full::from(value))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_full!(Bytes, Vec<u8>, String, &'static str, &'static [u8]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn test_full() {
+        let body = Body::from("hello");
+        assert_eq!(body.size_hint().exact(), Some(5));
+        let data = body.collect().await.unwrap().to_bytes();
+        assert_eq!(&data[..], b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_channel() {
+        let (tx, body) = Body::channel(2);
+        tokio::spawn(async move {
+            for s in ["a", "b", "c"] {
+                tx.send(s).await.unwrap();
+            }
+        });
+        assert_eq!(body.size_hint().exact(), None);
+        let data = body.collect().await.unwrap().to_bytes();
+        assert_eq!(&data[..], b"abc");
+    }
+
+    #[tokio::test]
+    async fn test_abort() {
+        let (tx, body) = Body::channel(2);
+        tokio::spawn(async move {
+            tx.send("a").await.unwrap();
+            tx.abort(anyhow!("failed")).await;
+        });
+        assert!(body.collect().await.is_err());
+    }
+}
@@ -0,0 +1,252 @@
+//! 客户端真实ip解析, 只信任来自可信代理的转发请求头(Forwarded、X-Forwarded-For、X-Real-IP)
+use std::{
+    fmt::{self, Display},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Error};
+use hyper::HeaderMap;
+
+/// RFC 7239 转发请求头
+pub const FORWARDED: &str = "Forwarded";
+/// 事实标准的转发请求头, 格式: 客户端, 代理1, 代理2
+pub const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+/// nginx常用的转发请求头, 值为代理看到的对端ip
+pub const X_REAL_IP: &str = "X-Real-IP";
+
+/// CIDR格式的网段, 如 `10.0.0.0/8`、`fd00::/8`, 不带前缀长度时表示单个ip
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    /// 判断ip是否属于该网段, ipv4映射的ipv6地址(::ffff:a.b.c.d)按ipv4处理
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => prefix_eq(
+                u32::from(net) as u128,
+                u32::from(ip) as u128,
+                self.prefix,
+                32,
+            ),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(u128::from(net), u128::from(ip), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .with_context(|| format!("invalid ip address: {s}"))?;
+        let addr = addr.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .parse()
+                .with_context(|| format!("invalid prefix length: {s}"))?,
+            None => max,
+        };
+        if prefix > max {
+            bail!("prefix length out of range: {s}");
+        }
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// 解析客户端的真实ip
+///
+/// 对端不是可信代理时直接使用对端ip, 否则按 Forwarded > X-Forwarded-For > X-Real-IP
+/// 的优先级读取转发链, 从右往左跳过可信代理, 返回第一个不可信的地址
+///
+/// Arguments:
+///
+/// * `headers`: 请求头
+/// * `peer`: tcp连接的对端ip
+/// * `trusted`: 可信代理网段列表
+pub(crate) fn resolve(headers: &HeaderMap, peer: IpAddr, trusted: &[IpNet]) -> IpAddr {
+    let peer = peer.to_canonical();
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    let chain = forwarded_chain(headers);
+    let mut ip = peer;
+    for node in chain.iter().rev() {
+        // 无法识别的节点(如unknown或混淆标识)之前的地址都不可信, 使用最后一个可信代理的地址
+        match node {
+            Some(v) => ip = *v,
+            None => break,
+        }
+        if !is_trusted(&ip) {
+            break;
+        }
+    }
+
+    ip
+}
+
+/// 读取转发链, 按从客户端到最近代理的顺序排列, 无法解析的节点为None
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+
+    if headers.contains_key(FORWARDED) {
+        return values(FORWARDED)
+            .map(|v| parse_forwarded_for(v).and_then(parse_node))
+            .collect();
+    }
+    if headers.contains_key(X_FORWARDED_FOR) {
+        return values(X_FORWARDED_FOR).map(parse_node).collect();
+    }
+    values(X_REAL_IP).take(1).map(parse_node).collect()
+}
+
+/// 获取Forwarded请求头单个元素中的for参数, 如 `for=192.0.2.60;proto=http`
+fn parse_forwarded_for(element: &str) -> Option<&str> {
+    element.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if key.trim().eq_ignore_ascii_case("for") {
+            Some(value.trim().trim_matches('"'))
+        } else {
+            None
+        }
+    })
+}
+
+/// 解析节点地址, 支持 `ip`、`ipv4:port`、`[ipv6]`、`[ipv6]:port` 格式
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip.to_canonical());
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip().to_canonical());
+    }
+
+    let ip = node.strip_prefix('[')?.strip_suffix(']')?;
+    ip.parse::<IpAddr>().ok().map(|v| v.to_canonical())
+}
+
+/// 比较两个地址的前prefix位是否相同
+fn prefix_eq(a: u128, b: u128, prefix: u8, bits: u8) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - prefix;
+    (a >> shift) == (b >> shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn nets(list: &[&str]) -> Vec<IpNet> {
+        list.iter().map(|v| v.parse().unwrap()).collect()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ipnet() {
+        let net: IpNet = "10.0.0.0/8".parse().unwrap();
+        assert!(net.contains(&ip("10.1.2.3")));
+        assert!(net.contains(&ip("::ffff:10.1.2.3")));
+        assert!(!net.contains(&ip("11.0.0.1")));
+
+        let net: IpNet = "fd00::/8".parse().unwrap();
+        assert!(net.contains(&ip("fd12::1")));
+        assert!(!net.contains(&ip("fe80::1")));
+
+        let net: IpNet = "::1".parse().unwrap();
+        assert_eq!(net.to_string(), "::1/128");
+        assert!(net.contains(&ip("::1")));
+
+        let net: IpNet = "0.0.0.0/0".parse().unwrap();
+        assert!(net.contains(&ip("8.8.8.8")));
+        assert!(!net.contains(&ip("2001:db8::1")));
+
+        assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+        assert!("abc/8".parse::<IpNet>().is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let trusted = nets(&["127.0.0.1", "10.0.0.0/8"]);
+        let mut headers = HeaderMap::new();
+        headers.insert(X_REAL_IP, HeaderValue::from_static("1.1.1.1"));
+        headers.insert(
+            X_FORWARDED_FOR,
+            HeaderValue::from_static("6.6.6.6, 2.2.2.2, 10.0.0.2"),
+        );
+
+        // 不可信的对端伪造的请求头被忽略
+        assert_eq!(resolve(&headers, ip("3.3.3.3"), &trusted), ip("3.3.3.3"));
+        // 从右往左跳过可信代理, 客户端伪造的最左侧地址被忽略
+        assert_eq!(resolve(&headers, ip("127.0.0.1"), &trusted), ip("2.2.2.2"));
+
+        headers.remove(X_FORWARDED_FOR);
+        assert_eq!(resolve(&headers, ip("127.0.0.1"), &trusted), ip("1.1.1.1"));
+
+        headers.insert(
+            FORWARDED,
+            HeaderValue::from_static(
+                r#"for=192.0.2.60;proto=http, For="[2001:db8:cafe::17]:4711""#,
+            ),
+        );
+        assert_eq!(
+            resolve(&headers, ip("10.0.0.1"), &trusted),
+            ip("2001:db8:cafe::17")
+        );
+
+        headers.insert(
+            FORWARDED,
+            HeaderValue::from_static("for=unknown, for=10.0.0.3"),
+        );
+        assert_eq!(resolve(&headers, ip("10.0.0.1"), &trusted), ip("10.0.0.3"));
+
+        assert_eq!(
+            resolve(&HeaderMap::new(), ip("::ffff:127.0.0.1"), &trusted),
+            ip("127.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_parse_node() {
+        assert_eq!(parse_node("1.2.3.4:80"), Some(ip("1.2.3.4")));
+        assert_eq!(parse_node("[::1]:80"), Some(ip("::1")));
+        assert_eq!(parse_node("[::1]"), Some(ip("::1")));
+        assert_eq!(parse_node("2001:db8::1"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("_hidden"), None);
+    }
+}
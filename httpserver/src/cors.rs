@@ -0,0 +1,378 @@
+//! CORS (cross-origin resource sharing) middleware configured by builder,
+//! supports origin patterns, credentials, exposed headers and per-route-prefix policies
+use hyper::{
+    header::{self, HeaderMap, HeaderValue},
+    Method, StatusCode,
+};
+
+use crate::{log_debug, Body, HttpContext, HttpMiddleware, HttpResponse, Next, Response};
+
+/// default allowed http methods
+const DEFAULT_METHODS: &str = "GET, POST, OPTIONS";
+/// preflight responses vary with these request headers
+const PREFLIGHT_VARY: &str =
+    "Origin, Access-Control-Request-Method, Access-Control-Request-Headers";
+
+/// CORS policy, no origin is allowed by default
+#[derive(Clone, Debug)]
+pub struct CorsPolicy {
+    origins: Vec<String>,
+    any_origin: bool,
+    methods: HeaderValue,
+    headers: Option<HeaderValue>,
+    expose_headers: Option<HeaderValue>,
+    credentials: bool,
+    max_age: Option<HeaderValue>,
+}
+
+/// CORS middleware, must be registered before the authentication middleware,
+/// so that preflight requests which carry no token can be answered
+pub struct CorsMiddleware {
+    default: CorsPolicy,
+    routes: Vec<(String, CorsPolicy)>,
+}
+
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CorsPolicy {
+    /// Create a policy which allows no origin, the allowed methods are `GET, POST, OPTIONS`
+    pub fn new() -> Self {
+        Self {
+            origins: Vec::new(),
+            any_origin: false,
+            methods: HeaderValue::from_static(DEFAULT_METHODS),
+            headers: None,
+            expose_headers: None,
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// allow requests from the origin
+    ///
+    /// Arguments:
+    ///
+    /// * `pattern`: `*` allows any origin, `*` inside the pattern matches any characters,
+    ///   e.g. `https://*.example.com`, `http://localhost:*`
+    pub fn allow_origin(mut self, pattern: &str) -> Self {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        if pattern == "*" {
+            self.any_origin = true;
+        } else if !pattern.is_empty() {
+            self.origins.push(pattern);
+        }
+        self
+    }
+
+    /// allow requests from all the origins, see [`allow_origin`](Self::allow_origin)
+    pub fn allow_origins<I, S>(self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        patterns
+            .into_iter()
+            .fold(self, |policy, v| policy.allow_origin(v.as_ref()))
+    }
+
+    /// set the allowed http methods
+    ///
+    /// # Panics
+    ///
+    /// Panics if a method contains invalid header characters
+    pub fn allow_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        if let Some(v) = header_list(methods) {
+            self.methods = v;
+        }
+        self
+    }
+
+    /// set the allowed request headers, the headers requested by preflight are allowed by default
+    ///
+    /// # Panics
+    ///
+    /// Panics if a header name contains invalid header characters
+    pub fn allow_headers<I, S>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.headers = header_list(headers);
+        self
+    }
+
+    /// set the response headers which can be read by browser scripts
+    ///
+    /// # Panics
+    ///
+    /// Panics if a header name contains invalid header characters
+    pub fn expose_headers<I, S>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.expose_headers = header_list(headers);
+        self
+    }
+
+    /// allow requests with cookies or authorization headers, it is ignored when any origin
+    /// is allowed, because browsers reject credentials with the wildcard origin
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.credentials = allow;
+        self
+    }
+
+    /// set how long the preflight result can be cached by browsers, in seconds
+    pub fn max_age(mut self, secs: u32) -> Self {
+        self.max_age = Some(HeaderValue::from(secs));
+        self
+    }
+
+    /// check whether requests from the origin are allowed
+    pub fn is_origin_allowed(&self, origin: &str) -> bool {
+        if self.any_origin {
+            return true;
+        }
+        let origin = origin.to_ascii_lowercase();
+        self.origins.iter().any(|p| match_pattern(p, &origin))
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        origin.to_str().is_ok_and(|v| self.is_origin_allowed(v))
+    }
+
+    fn allow_origin_value(&self, origin: &HeaderValue) -> HeaderValue {
+        if self.any_origin {
+            HeaderValue::from_static("*")
+        } else {
+            origin.clone()
+        }
+    }
+
+    fn with_credentials(&self) -> bool {
+        self.credentials && !self.any_origin
+    }
+}
+
+impl CorsMiddleware {
+    /// Create CORS middleware
+    ///
+    /// Arguments:
+    ///
+    /// * `default`: policy for the requests which match no route policy
+    pub fn new(default: CorsPolicy) -> Self {
+        Self {
+            default,
+            routes: Vec::new(),
+        }
+    }
+
+    /// set policy for the requests whose path starts with prefix, the longest prefix wins
+    ///
+    /// Arguments:
+    ///
+    /// * `prefix`: path prefix, including the context path, e.g. `/api/public/`
+    /// * `policy`: CORS policy
+    pub fn route(mut self, prefix: &str, policy: CorsPolicy) -> Self {
+        self.routes.push((String::from(prefix), policy));
+        self.routes.sort_by_key(|v| std::cmp::Reverse(v.0.len()));
+        self
+    }
+
+    fn policy(&self, path: &str) -> &CorsPolicy {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .map_or(&self.default, |(_, policy)| policy)
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpMiddleware for CorsMiddleware {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        let policy = self.policy(ctx.req.uri().path());
+        let origin = ctx.req.headers().get(header::ORIGIN).cloned();
+
+        // OPTIONS requests are answered directly, preflight requests get CORS headers
+        if ctx.req.method() == Method::OPTIONS {
+            let req_headers = ctx.req.headers();
+            return match &origin {
+                Some(origin) if req_headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD) => {
+                    preflight(policy, ctx.id, origin, req_headers)
+                }
+                _ => Ok(hyper::Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .header(header::ALLOW, policy.methods.clone())
+                    .body(Body::empty())?),
+            };
+        }
+
+        // convert errors to responses here, otherwise browsers cannot read error messages
+        // because the responses generated by the error handler have no CORS headers
+        let (id, error_handler) = (ctx.id, next.error_handler);
+        let mut res = match next.run(ctx).await {
+            Ok(res) => res,
+            Err(e) => error_handler(id, e),
+        };
+
+        let headers = res.headers_mut();
+        if !policy.any_origin {
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+        if let Some(origin) = origin.filter(|v| policy.allows(v)) {
+            set_allow_headers(headers, policy, &origin);
+            if let Some(v) = &policy.expose_headers {
+                headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, v.clone());
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+/// answer the preflight request, the response has no CORS headers if the origin is not allowed
+fn preflight(
+    policy: &CorsPolicy,
+    id: u32,
+    origin: &HeaderValue,
+    req_headers: &HeaderMap,
+) -> HttpResponse {
+    let mut res = Response::new(Body::empty());
+    let headers = res.headers_mut();
+    headers.insert(header::VARY, HeaderValue::from_static(PREFLIGHT_VARY));
+
+    if !policy.allows(origin) {
+        #[cfg(not(feature = "english"))]
+        log_debug!(id, "跨域预检请求的来源不在允许列表中: {:?}", origin);
+        #[cfg(feature = "english")]
+        log_debug!(
+            id,
+            "origin of the preflight request is not allowed: {:?}",
+            origin
+        );
+        *res.status_mut() = StatusCode::FORBIDDEN;
+        return Ok(res);
+    }
+
+    set_allow_headers(headers, policy, origin);
+    headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, policy.methods.clone());
+    let allow_headers = policy
+        .headers
+        .as_ref()
+        .or_else(|| req_headers.get(header::ACCESS_CONTROL_REQUEST_HEADERS));
+    if let Some(v) = allow_headers {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, v.clone());
+    }
+    if let Some(v) = &policy.max_age {
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, v.clone());
+    }
+    *res.status_mut() = StatusCode::NO_CONTENT;
+
+    Ok(res)
+}
+
+fn set_allow_headers(headers: &mut HeaderMap, policy: &CorsPolicy, origin: &HeaderValue) {
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        policy.allow_origin_value(origin),
+    );
+    if policy.with_credentials() {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+}
+
+/// join the non-empty items with `, `, returns None if there is no item
+fn header_list<I, S>(items: I) -> Option<HeaderValue>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let list = items
+        .into_iter()
+        .map(|v| v.as_ref().trim().to_owned())
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if list.is_empty() {
+        return None;
+    }
+    match HeaderValue::from_str(&list) {
+        Ok(v) => Some(v),
+        Err(_) => panic!("invalid header value: {list}"),
+    }
+}
+
+/// match the lowercase origin against the pattern, `*` matches any characters
+fn match_pattern(pattern: &str, origin: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match origin.strip_prefix(first) {
+        Some(v) => v,
+        None => return false,
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    match parts.split_last() {
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(i) => rest = &rest[i + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.ends_with(last)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_pattern() {
+        assert!(match_pattern("https://a.com", "https://a.com"));
+        assert!(!match_pattern("https://a.com", "https://a.com.evil.com"));
+        assert!(match_pattern("https://*.a.com", "https://x.y.a.com"));
+        assert!(!match_pattern("https://*.a.com", "https://a.com"));
+        assert!(!match_pattern("https://*.a.com", "https://evila.com"));
+        assert!(!match_pattern("https://*.a.com", "http://x.a.com"));
+        assert!(match_pattern("http://localhost:*", "http://localhost:8080"));
+        assert!(match_pattern("http://*.a.com:*", "http://x.a.com:80"));
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = CorsPolicy::new()
+            .allow_origins("https://A.com, https://*.b.com".split(','))
+            .allow_credentials(true);
+        assert!(policy.is_origin_allowed("https://a.com"));
+        assert!(policy.is_origin_allowed("https://X.b.com"));
+        assert!(!policy.is_origin_allowed("https://c.com"));
+        assert!(!policy.is_origin_allowed("null"));
+        assert!(policy.with_credentials());
+
+        let policy = policy.allow_origin("*");
+        assert!(policy.is_origin_allowed("https://c.com"));
+        assert!(!policy.with_credentials());
+
+        let cors = CorsMiddleware::new(CorsPolicy::new())
+            .route("/api/", CorsPolicy::new().allow_origin("https://a.com"))
+            .route("/api/public/", CorsPolicy::new().allow_origin("*"));
+        assert!(cors.policy("/api/public/x").any_origin);
+        assert!(cors.policy("/api/user").is_origin_allowed("https://a.com"));
+        assert!(!cors.policy("/other").is_origin_allowed("https://a.com"));
+    }
+}
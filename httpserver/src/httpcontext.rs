@@ -1,4 +1,4 @@
-use std::{borrow::Cow, collections::HashMap, net::{Ipv4Addr, SocketAddr}, str::FromStr};
+use std::{borrow::Cow, collections::HashMap, net::{IpAddr, SocketAddr}, str::FromStr};
 
 use anyhow::Result;
 use http::{HeaderMap, HeaderValue};
@@ -26,6 +26,8 @@ pub struct HttpContext {
     pub path_len: u32,
     /// http request client ip address
     pub addr: SocketAddr,
+    /// client real ip, resolved from the forwarding headers of trusted proxies
+    pub ip: IpAddr,
     /// http request ID (each request ID is unique)
     pub id: u32,
     /// current login user ID (parsed from token, not logged in is empty)
@@ -286,9 +288,9 @@ impl HttpContext {
         }
     }
 
-    /// 获取客户端的真实ip, 获取优先级为X-Real-IP > X-Forwarded-For > socketaddr
-    pub fn remote_ip(&self) -> Ipv4Addr {
-        Self::pri_remote_ip(&self.req.headers(), &self.addr)
+    /// 获取客户端的真实ip, 只有对端为可信代理时才使用转发请求头中的地址
+    pub fn remote_ip(&self) -> IpAddr {
+        self.ip
     }
 
     /// 获取http头部
@@ -383,32 +385,6 @@ impl HttpContext {
         None
     }
 
-    /// 获取客户端的真实ip, 获取优先级为X-Real-IP > X-Forwarded-For > socketaddr
-    pub(self) fn pri_remote_ip(headers: &HeaderMap<HeaderValue>, addr: &SocketAddr) -> Ipv4Addr {
-        if let Some(ip) = headers.get("X-Real-IP") {
-            if let Ok(ip) = ip.to_str() {
-                if let Ok(ip) = ip.parse() {
-                    return ip;
-                }
-            }
-        }
-
-        if let Some(ip) = headers.get("X-Forwarded-For") {
-            if let Ok(ip) = ip.to_str() {
-                if let Some(ip) = ip.split(',').next() {
-                    if let Ok(ip) = ip.parse() {
-                        return ip;
-                    }
-                }
-            }
-        }
-
-        match addr.ip() {
-            std::net::IpAddr::V4(ip) => ip,
-            _ => std::net::Ipv4Addr::new(0, 0, 0, 0),
-        }
-    }
-
     /// 获取http头部
     pub(self) fn pri_header<'a>(id: u32, headers: &'a HeaderMap<HeaderValue>, key: &str) -> Option<Cow<'a, str>> {
         match headers.get(key) {
@@ -438,6 +414,8 @@ pub struct WsContext {
     pub path_len: u32,
     /// http request client ip address
     pub addr: SocketAddr,
+    /// client real ip, resolved from the forwarding headers of trusted proxies
+    pub ip: IpAddr,
     /// http request ID (each request ID is unique)
     pub id: u32,
     pub websocket: hyper_tungstenite::HyperWebsocket,
@@ -478,9 +456,9 @@ impl WsContext {
         }
     }
 
-    /// 获取客户端的真实ip, 获取优先级为X-Real-IP > X-Forwarded-For > socketaddr
-    pub fn remote_ip(&self) -> Ipv4Addr {
-        HttpContext::pri_remote_ip(&self.req.headers, &self.addr)
+    /// 获取客户端的真实ip, 只有对端为可信代理时才使用转发请求头中的地址
+    pub fn remote_ip(&self) -> IpAddr {
+        self.ip
     }
 
     /// 获取http头部
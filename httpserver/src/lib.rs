@@ -1,21 +1,35 @@
 //! 简单的http server库，适用于RESTFul的api服务
 //!
 //! 特性：
-//!   1. 只支持http/1.1
+//!   1. 支持http/1.1及http/2, 启用tls特性时支持https, 通过ALPN协商协议
 //!   2. 不支持websocket
 //!   3. 不支持文件上传
 //!   4. 支持中间件
+//!   5. 支持W3C traceparent及X-Request-Id请求追踪
+//!   6. 支持根据已注册的接口生成OpenAPI 3文档
+//!   7. 支持按路径前缀配置的跨域访问(CORS)策略
+//!   8. 支持通过通道逐块发送的流式回复
+mod body;
 mod cancel;
+mod clientip;
+mod cors;
 mod httpcontext;
 mod httperror;
 mod macros;
 mod middleware;
+mod openapi;
 mod resp;
+#[cfg(feature = "tls")]
+mod tls;
+pub mod trace;
 
 use anyhow::{Error, Result};
 use http_body_util::{BodyExt, Full};
-use hyper::{body::Incoming, server::conn::http1, service};
-use hyper_util::rt::TokioIo;
+use hyper::{body::Incoming, service};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+};
 use std::{
     collections::HashMap,
     convert::Infallible,
@@ -23,14 +37,24 @@ use std::{
     net::SocketAddr,
     sync::{atomic::{AtomicU32, Ordering}, Arc},
 };
-use tokio::net::{TcpListener, TcpStream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+};
 
+pub use body::{Body, BodySender};
 pub use cancel::{CancelManager, CancelSender, new_cancel};
+pub use clientip::IpNet;
+pub use cors::{CorsMiddleware, CorsPolicy};
 pub use hyper::body::Bytes;
-pub use middleware::{AccessLog, CorsMiddleware, HttpMiddleware};
+pub use middleware::{AccessLog, HttpMiddleware, JsonAccessLog, ACCESS_LOG_TARGET};
+pub use openapi::ApiDoc;
 pub use resp::{ApiResult, Resp};
 pub use httpcontext::{HttpContext, GKind, GValue};
 pub use httperror::HttpError;
+pub use trace::TraceContext;
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
 
 #[cfg(feature = "websocket")]
 pub use httpcontext::WsContext;
@@ -42,7 +66,7 @@ pub const APPLICATION_JSON: &'static str = "applicatoin/json;charset=UTF-8";
 
 // Simplified declaration
 pub type Request = hyper::Request<Full<Bytes>>;
-pub type Response = hyper::Response<Full<Bytes>>;
+pub type Response = hyper::Response<Body>;
 pub type HttpResponse = Result<Response>;
 pub type BoxHttpHandler = Box<dyn HttpHandler>;
 #[cfg(feature = "websocket")]
@@ -78,6 +102,7 @@ pub trait WsHandler: Send + Sync + 'static {
 pub struct Next<'a> {
     pub endpoint: &'a dyn HttpHandler,
     pub next_middleware: &'a [Arc<dyn HttpMiddleware>],
+    pub error_handler: fn(u32, Error) -> Response,
 }
 
 /// 路由匹配模式
@@ -96,11 +121,15 @@ pub struct HttpServer {
     count:              AtomicU32,                      // 当前连接总数
     context_path:       String,                         // 上下文路径
     router:             Router,                         // 路由表
+    api_docs:           HashMap<String, ApiDoc>,        // 接口文档
     middlewares:        Vec<Arc<dyn HttpMiddleware>>,   // 中间件
     default_handler:    BoxHttpHandler,                 // 缺省处理函数
     error_handler:      fn(u32, Error) -> Response,     // 错误处理函数
     fuzzy_find:         FuzzyFind,                      // 路径匹配模式
+    trusted_proxies:    Vec<IpNet>,                     // 可信代理网段
     cancel_manager:     Option<CancelManager>,          // 进程退出标志
+    #[cfg(feature = "tls")]
+    tls:                Option<Arc<TlsConfig>>,         // tls证书配置
     #[cfg(feature = "websocket")]
     ws_router:          WsRouter,
 }
@@ -148,7 +177,11 @@ impl<'a> Next<'a> {
                 self.next_middleware = next;
                 current.handle(ctx, self).await
             }
-            None => (self.endpoint).handle(ctx).await,
+            None => {
+                // 记录经过中间件处理后的登录用户id, 供访问日志使用
+                trace::set_uid(&ctx.uid);
+                (self.endpoint).handle(ctx).await
+            }
         }
     }
 }
@@ -161,11 +194,15 @@ impl HttpServer {
             count:              AtomicU32::new(0),
             context_path:       String::new(),
             router:             HashMap::new(),
+            api_docs:           HashMap::new(),
             middlewares:        Vec::<Arc<dyn HttpMiddleware>>::new(),
             default_handler:    Box::new(&Self::handle_not_found),
             error_handler:      Self::handle_error,
             fuzzy_find:         FuzzyFind::None,
+            trusted_proxies:    Vec::new(),
             cancel_manager:     None,
+            #[cfg(feature = "tls")]
+            tls:                None,
             #[cfg(feature = "websocket")]
             ws_router:          HashMap::new(),
         }
@@ -219,6 +256,17 @@ impl HttpServer {
         self.fuzzy_find = fuzzy_find;
     }
 
+    /// set trusted proxies, the forwarding headers (Forwarded, X-Forwarded-For, X-Real-IP)
+    /// are only honored when the peer address belongs to one of them
+    ///
+    /// Arguments:
+    ///
+    /// * `proxies`: trusted proxy networks
+    ///
+    pub fn set_trusted_proxies(&mut self, proxies: Vec<IpNet>) {
+        self.trusted_proxies = proxies;
+    }
+
     /// set default function when no matching api function is found
     ///
     /// Arguments:
@@ -249,6 +297,15 @@ impl HttpServer {
         self.router.insert(real_path, Box::new(handler));
     }
 
+    /// check whether an api function is registered for the request path
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: request path, including the context path
+    pub fn has_route(&self, path: &str) -> bool {
+        self.find_http_handler(path).0.is_some()
+    }
+
     /// register middleware
     pub fn set_middleware<T: HttpMiddleware>(&mut self, middleware: T) -> Arc<T> {
         let middleware = Arc::new(middleware);
@@ -261,6 +318,18 @@ impl HttpServer {
         self.cancel_manager = Some(cancel);
     }
 
+    /// enable https, the tls certificate can be reloaded at runtime through the returned object
+    ///
+    /// Arguments:
+    ///
+    /// * `tls`: tls certificate configuration
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&mut self, tls: TlsConfig) -> Arc<TlsConfig> {
+        let tls = Arc::new(tls);
+        self.tls = Some(tls.clone());
+        tls
+    }
+
     /// register websocket handle
     #[cfg(feature = "websocket")]
     pub fn reg_websocket(&mut self, path: &str, handler: impl WsHandler) {
@@ -296,8 +365,7 @@ impl HttpServer {
 
         loop {
             let (tcp, addr) = listener.accept().await?;
-            let io = TokioIo::new(tcp);
-            tokio::spawn(Self::on_accept(srv.clone(), addr, io));
+            tokio::spawn(srv.clone().accept(tcp, addr));
         }
     }
 
@@ -318,8 +386,7 @@ impl HttpServer {
                 tokio::select! {
                     res = listener.accept() => {
                         let (tcp, addr) = res?;
-                        let io = TokioIo::new(tcp);
-                        tokio::spawn(Self::on_accept(self.clone(), addr, io));
+                        tokio::spawn(self.clone().accept(tcp, addr));
                     }
                     _ = cancel.cancelled() => {
                         cancel.finish();
@@ -334,13 +401,36 @@ impl HttpServer {
         } else {
             loop {
                 let (tcp, addr) = listener.accept().await?;
-                let io = TokioIo::new(tcp);
-                tokio::spawn(self.clone().on_accept(addr, io));
+                tokio::spawn(self.clone().accept(tcp, addr));
             }
         }
     }
 
-    async fn on_accept(self: Arc<HttpServer>, addr: SocketAddr, io: TokioIo<TcpStream>) {
+    // 处理新建立的连接, 启用tls时先完成tls握手
+    async fn accept(self: Arc<HttpServer>, tcp: TcpStream, addr: SocketAddr) {
+        #[cfg(feature = "tls")]
+        if let Some(acceptor) = self.tls.as_ref().map(|v| v.acceptor()) {
+            match tokio::time::timeout(tls::HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+                Ok(Ok(stream)) => self.on_accept(addr, TokioIo::new(stream)).await,
+                #[cfg(not(feature = "english"))]
+                Ok(Err(e)) => log::debug!("tls握手失败, 客户端: {addr}, 错误: {e:?}"),
+                #[cfg(feature = "english")]
+                Ok(Err(e)) => log::debug!("tls handshake failed, client: {addr}, error: {e:?}"),
+                #[cfg(not(feature = "english"))]
+                Err(_) => log::debug!("tls握手超时, 客户端: {addr}"),
+                #[cfg(feature = "english")]
+                Err(_) => log::debug!("tls handshake timed out, client: {addr}"),
+            }
+            return;
+        }
+
+        self.on_accept(addr, TokioIo::new(tcp)).await
+    }
+
+    async fn on_accept<I>(self: Arc<HttpServer>, addr: SocketAddr, io: TokioIo<I>)
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
         self.count.fetch_add(1, std::sync::atomic::Ordering::Release);
         let id = Self::step_id(&self.id);
 
@@ -367,29 +457,47 @@ impl HttpServer {
                 let next = Next {
                     endpoint,
                     next_middleware: &srv.middlewares,
+                    error_handler: srv.error_handler,
                 };
 
+                // 解析请求的追踪信息及客户端真实ip
+                let trace = TraceContext::from_headers(req.headers());
+                let ip = clientip::resolve(req.headers(), addr.ip(), &srv.trusted_proxies);
+
                 // 读取请求体(body)
                 let (req, body) = match Self::rebuild_req(req, id).await {
                     Ok(v) => v,
-                    Err(e) => return Ok::<_, Infallible>((srv.error_handler)(id, e)),
+                    Err(e) => {
+                        let mut resp = (srv.error_handler)(id, e);
+                        Self::set_trace_headers(&mut resp, &trace);
+                        return Ok::<_, Infallible>(resp);
+                    }
                 };
 
                 let (uid, attrs) = (String::new(), None);
-                let ctx = HttpContext { req, body, path_len, addr, id, uid, attrs };
+                let ctx = HttpContext { req, body, path_len, addr, ip, id, uid, attrs };
 
-                let resp = match next.run(ctx).await {
-                    Ok(resp) => resp,
-                    Err(e) => (srv.error_handler)(id, e),
-                };
+                // 在追踪信息的作用域中处理请求, 使处理过程中可以获取追踪信息
+                let mut resp = trace::scope(trace.clone(), async {
+                    match next.run(ctx).await {
+                        Ok(resp) => resp,
+                        Err(e) => (srv.error_handler)(id, e),
+                    }
+                }).await;
+
+                // 在回复中返回追踪信息
+                Self::set_trace_headers(&mut resp, &trace);
 
                 Ok::<_, Infallible>(resp)
             }
         };
 
-        let conn = http1::Builder::new().serve_connection(io, service::service_fn(srv_fn));
+        // 根据连接前言自动识别http/1.1及http/2, tls连接由ALPN协商结果决定
+        let builder = auto::Builder::new(TokioExecutor::new());
+        #[cfg(not(feature = "websocket"))]
+        let conn = builder.serve_connection(io, service::service_fn(srv_fn));
         #[cfg(feature = "websocket")]
-        let conn = conn.with_upgrades();
+        let conn = builder.serve_connection_with_upgrades(io, service::service_fn(srv_fn));
         tokio::pin!(conn);
 
         if let Some(cancel_manager) = &self.cancel_manager {
@@ -437,6 +545,17 @@ impl HttpServer {
         log::trace!("close http connection, remaining connections: {}", count - 1);
     }
 
+    /// 在回复头中写入追踪信息(traceparent及X-Request-Id)
+    fn set_trace_headers(resp: &mut Response, trace: &TraceContext) {
+        let headers = resp.headers_mut();
+        if let Ok(v) = hyper::header::HeaderValue::from_str(&trace.traceparent()) {
+            headers.insert(trace::TRACEPARENT, v);
+        }
+        if let Ok(v) = hyper::header::HeaderValue::from_str(&trace.request_id) {
+            headers.insert(trace::X_REQUEST_ID, v);
+        }
+    }
+
     fn step_id(id: &AtomicU32) -> u32 {
         let curr_id = id.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         if curr_id == 0 {
@@ -560,7 +679,7 @@ impl HttpServer {
                 log_error!(id, "错误处理函数异常: {e:?}");
                 #[cfg(feature = "english")]
                 log_error!(id, "handle_error except: {e:?}");
-                let body = Full::from("internal server error");
+                let body = Body::from("internal server error");
                 let mut res = hyper::Response::new(body);
                 *res.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
                 res
@@ -573,7 +692,7 @@ impl HttpServer {
     }
 
     #[cfg(feature = "websocket")]
-    fn on_websocket(&self, id: u32, mut req: hyper::Request<Incoming>, addr: SocketAddr) -> hyper::Response<Full<Bytes>> {
+    fn on_websocket(&self, id: u32, mut req: hyper::Request<Incoming>, addr: SocketAddr) -> Response {
         let path = req.uri().path();
         let (endpoint, path_len) = self.find_ws_handler(path);
 
@@ -600,8 +719,9 @@ impl HttpServer {
             }
         };
 
+        let ip = clientip::resolve(req.headers(), addr.ip(), &self.trusted_proxies);
         let (req, _) = req.into_parts();
-        let ctx = WsContext {req, id, path_len, addr, websocket};
+        let ctx = WsContext {req, id, path_len, addr, ip, websocket};
 
         // Spawn a task to handle the websocket connection.
         tokio::spawn(async move {
@@ -614,7 +734,7 @@ impl HttpServer {
         });
 
         // Return the response so the spawned future can continue.
-        response
+        response.map(Body::from)
     }
 
     fn fix_path_of_reg(mut path: &str) -> String {
@@ -37,7 +37,7 @@ macro_rules! http_error {
 /// ## Example
 /// ```rust
 /// use anyhow::Result;
-/// use httpserver::{HttpContext, Response, register_apis};
+/// use httpserver::{ApiDoc, HttpContext, Response, register_apis};
 ///
 /// async fn ping(ctx: HttpContext) -> Result<Response> { todo!() }
 /// async fn login(ctx: HttpContext) -> Result<Response> { todo!() }
@@ -45,14 +45,20 @@ macro_rules! http_error {
 /// let mut srv = HttpServer::new(true);
 /// register_apis!(srv, "/api",
 ///     "/ping": apis::ping,
-///     "/login": apis::login,
+///     "/login": apis::login => ApiDoc::new("login"),
 /// );
 /// ```
 #[macro_export]
 macro_rules! register_apis {
-    ($server:expr, $base:expr, $($path:literal : $handler:expr,)+) => {
+    (@reg $server:expr, $path:expr, $handler:expr) => {
+        $server.register($path, $handler);
+    };
+    (@reg $server:expr, $path:expr, $handler:expr, $doc:expr) => {
+        $server.register_with_doc($path, $handler, $doc);
+    };
+    ($server:expr, $base:expr, $($path:literal : $handler:expr $(=> $doc:expr)?,)+) => {
         $(
-            $server.register(&format!("{}{}", $base, $path), $handler);
+            $crate::register_apis!(@reg $server, &format!("{}{}", $base, $path), $handler $(, $doc)?);
         )*
     };
 }
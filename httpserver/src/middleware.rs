@@ -1,11 +1,14 @@
-use http_body_util::{BodyExt, Full};
-use hyper::{body::Bytes, header::HeaderValue};
+use http_body_util::BodyExt;
+use hyper::body::{Body as _, Bytes};
 
 use crate::{
-    log_debug, log_error, log_info, log_trace, if_else, HttpContext, HttpResponse,
+    log_debug, log_error, log_info, log_trace, if_else, trace, Body, HttpContext, HttpResponse,
     Next, Response, CONTENT_TYPE
 };
 
+/// json格式访问日志使用的日志target, 便于单独配置日志级别或输出
+pub const ACCESS_LOG_TARGET: &str = "access";
+
 /// middleware interface
 #[async_trait::async_trait]
 pub trait HttpMiddleware: Send + Sync + 'static {
@@ -14,8 +17,8 @@ pub trait HttpMiddleware: Send + Sync + 'static {
 
 /// Log middleware，访问日志中间件
 pub struct AccessLog;
-/// Cors middleware，跨域访问中间件
-pub struct CorsMiddleware;
+/// Json log middleware，json格式的访问日志中间件, 每个请求输出一行json对象, 便于日志采集
+pub struct JsonAccessLog;
 
 #[async_trait::async_trait]
 impl HttpMiddleware for AccessLog {
@@ -79,10 +82,14 @@ impl HttpMiddleware for AccessLog {
         // 记录回复结果日志
         if log::log_enabled!(log::Level::Trace) {
             if let Ok(r) = res {
-                let (parts, body) = r.into_parts();
-                let body: Bytes = body.collect().await.unwrap().to_bytes();
-                log_trace!(id, "[RESP] {}", std::str::from_utf8(&body).unwrap());
-                res = Ok(Response::from_parts(parts, Full::from(body)));
+                let (parts, mut body) = r.into_parts();
+                // 流式回复的长度未知, 不读取其内容, 避免缓存全部内容
+                if body.size_hint().exact().is_some() {
+                    let data: Bytes = body.collect().await.unwrap().to_bytes();
+                    log_trace!(id, "[RESP] {}", std::str::from_utf8(&data).unwrap());
+                    body = Body::from(data);
+                }
+                res = Ok(Response::from_parts(parts, body));
             }
         }
         res
@@ -90,33 +97,45 @@ impl HttpMiddleware for AccessLog {
 }
 
 #[async_trait::async_trait]
-impl HttpMiddleware for CorsMiddleware {
+impl HttpMiddleware for JsonAccessLog {
     async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
-        use hyper::header::{
-                ALLOW,
-                ACCESS_CONTROL_ALLOW_HEADERS,
-                ACCESS_CONTROL_ALLOW_METHODS,
-                ACCESS_CONTROL_ALLOW_ORIGIN
+        let start = std::time::Instant::now();
+        let ip = ctx.remote_ip();
+        let id = ctx.id;
+        let method = ctx.req.method().to_string();
+        let path = String::from(ctx.req.uri().path());
+
+        let res = next.run(ctx).await;
+
+        // 处理失败的请求由错误处理函数生成回复, 此处按500记录
+        let (status, bytes, error) = match &res {
+            Ok(r) => (r.status().as_u16(), r.body().size_hint().exact().unwrap_or(0), None),
+            Err(e) => (500, 0, Some(e.to_string())),
         };
+        let failed = error.is_some() || status >= 500;
+        let trace = trace::current();
+        // latency单位为毫秒
+        let record = serde_json::json!({
+            "id": id,
+            "requestId": trace.as_ref().map(|v| v.request_id.as_str()),
+            "traceId": trace.as_ref().map(|v| v.trace_id.as_str()),
+            "spanId": trace.as_ref().map(|v| v.span_id.as_str()),
+            "method": method,
+            "path": path,
+            "status": status,
+            "latency": start.elapsed().as_secs_f64() * 1000.0,
+            "uid": trace::uid(),
+            "ip": ip.to_string(),
+            "bytes": bytes,
+            "error": error,
+        });
 
-        let is_options = ctx.req.method() == hyper::Method::OPTIONS;
-        let allow_host = HeaderValue::from_str("*").unwrap();
-        // options请求，无需处理，直接返回
-        if is_options {
-            Ok(
-                hyper::Response::builder()
-                    .header(ACCESS_CONTROL_ALLOW_HEADERS, allow_host.clone())
-                    .header(ACCESS_CONTROL_ALLOW_METHODS, allow_host.clone())
-                    .header(ACCESS_CONTROL_ALLOW_ORIGIN, allow_host.clone())
-                    .header(ALLOW, HeaderValue::from_str("GET,HEAD,OPTIONS").unwrap())
-                    .body(Full::<Bytes>::new(Bytes::new()))?)
+        if failed {
+            log::error!(target: ACCESS_LOG_TARGET, "{record}");
         } else {
-            let mut res = next.run(ctx).await?;
-            let h = res.headers_mut();
-            h.append(ACCESS_CONTROL_ALLOW_HEADERS, allow_host.clone());
-            h.append(ACCESS_CONTROL_ALLOW_METHODS, allow_host.clone());
-            h.append(ACCESS_CONTROL_ALLOW_ORIGIN, allow_host.clone());
-            Ok(res)
+            log::info!(target: ACCESS_LOG_TARGET, "{record}");
         }
+
+        res
     }
 }
@@ -0,0 +1,167 @@
+//! OpenAPI 3 document generation from registered api functions
+use serde_json::{json, Map, Value};
+
+use crate::HttpServer;
+
+/// OpenAPI specification version
+pub const OPENAPI_VERSION: &str = "3.0.3";
+
+const API_RESULT_REF: &str = "#/components/schemas/ApiResult";
+const DEFAULT_METHOD: &str = "post";
+
+/// api document info, attached to an api function on registration
+#[derive(Clone, Debug)]
+pub struct ApiDoc {
+    summary: String,
+    method: &'static str,
+    request: Option<Value>,
+    response: Option<Value>,
+}
+
+impl ApiDoc {
+    /// Create an api document, the default http method is `post`
+    ///
+    /// Arguments:
+    ///
+    /// * `summary`: short description of the api
+    pub fn new(summary: &str) -> Self {
+        Self {
+            summary: String::from(summary),
+            method: DEFAULT_METHOD,
+            request: None,
+            response: None,
+        }
+    }
+
+    /// set http method, lowercase, e.g. `get`, `post`
+    pub fn method(mut self, method: &'static str) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// set json schema of the request body
+    pub fn request(mut self, schema: Value) -> Self {
+        self.request = Some(schema);
+        self
+    }
+
+    /// set json schema of the `data` field in the ApiResult response
+    pub fn response(mut self, schema: Value) -> Self {
+        self.response = Some(schema);
+        self
+    }
+
+    fn operation(&self, tag: &str) -> Value {
+        let mut op = Map::new();
+        op.insert("tags".to_owned(), json!([tag]));
+        if !self.summary.is_empty() {
+            op.insert("summary".to_owned(), Value::from(self.summary.as_str()));
+        }
+        if let Some(req) = &self.request {
+            let body = json!({
+                "required": true,
+                "content": { "application/json": { "schema": req } },
+            });
+            op.insert("requestBody".to_owned(), body);
+        }
+
+        // the response data is wrapped in the ApiResult envelope
+        let schema = match &self.response {
+            Some(data) => json!({
+                "allOf": [
+                    { "$ref": API_RESULT_REF },
+                    { "type": "object", "properties": { "data": data } },
+                ]
+            }),
+            None => json!({ "$ref": API_RESULT_REF }),
+        };
+        let resp = json!({
+            "200": {
+                "description": "OK",
+                "content": { "application/json": { "schema": schema } },
+            }
+        });
+        op.insert("responses".to_owned(), resp);
+
+        Value::Object(op)
+    }
+}
+
+impl HttpServer {
+    /// register api function for path, and attach an api document to it
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: api path
+    /// * `handler`: handle of api function
+    /// * `doc`: api document
+    pub fn register_with_doc(&mut self, path: &str, handler: impl crate::HttpHandler, doc: ApiDoc) {
+        let real_path = Self::fix_path_of_reg(path);
+        self.api_docs.insert(real_path.clone(), doc);
+        self.router.insert(real_path, Box::new(handler));
+    }
+
+    /// Generate an OpenAPI 3 document for all registered api functions,
+    /// api functions without a document only have the common ApiResult response
+    ///
+    /// Arguments:
+    ///
+    /// * `title`: document title
+    /// * `version`: api version
+    pub fn openapi(&self, title: &str, version: &str) -> Value {
+        let prefix = self
+            .context_path
+            .strip_suffix('/')
+            .unwrap_or(&self.context_path);
+        let default_doc = ApiDoc::new("");
+
+        let mut paths = Map::new();
+        for path in self.router.keys() {
+            let doc = self.api_docs.get(path).unwrap_or(&default_doc);
+            // use the parent directory of the path as tag, e.g. `/sys/user/list` => `user`
+            let parent = path
+                .trim_end_matches('/')
+                .rsplit_once('/')
+                .map_or("", |v| v.0);
+            let tag = parent
+                .rsplit('/')
+                .next()
+                .filter(|v| !v.is_empty())
+                .unwrap_or("default");
+            let mut item = Map::new();
+            item.insert(doc.method.to_owned(), doc.operation(tag));
+            paths.insert(format!("{prefix}{path}"), Value::Object(item));
+        }
+
+        json!({
+            "openapi": OPENAPI_VERSION,
+            "info": { "title": title, "version": version },
+            "paths": paths,
+            "components": {
+                "schemas": { "ApiResult": api_result_schema() },
+            },
+        })
+    }
+}
+
+/// json schema of the universal api result envelope
+fn api_result_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["code"],
+        "properties": {
+            "code": {
+                "type": "integer",
+                "format": "int32",
+                "description": "result code, 200 represents success",
+            },
+            "message": {
+                "type": "string",
+                "description": "error message, only present on failure",
+            },
+            "data": {
+                "description": "result data, only present on success",
+            },
+        },
+    })
+}
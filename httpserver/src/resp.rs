@@ -3,11 +3,10 @@
 use std::fmt::Display;
 
 use anyhow::Context;
-use http_body_util::Full;
 use hyper::body::Bytes;
 use serde::{Deserialize, Serialize};
 
-use crate::{HttpResponse, APPLICATION_JSON, CONTENT_TYPE};
+use crate::{Body, HttpResponse, APPLICATION_JSON, CONTENT_TYPE};
 
 /// Universal API interface returns data format
 #[derive(Serialize, Deserialize, Debug)]
@@ -148,7 +147,7 @@ impl Resp {
             hyper::Response::builder()
                 .status(status)
                 .header(CONTENT_TYPE, APPLICATION_JSON)
-                .body(Full::new(body.into()))?
+                .body(Body::from(body.into()))?
         )
     }
 
@@ -174,7 +173,7 @@ impl Resp {
             hyper::Response::builder()
                 .status(status)
                 .header(CONTENT_TYPE, APPLICATION_JSON)
-                .body(Full::from(body))?
+                .body(Body::from(body))?
         )
 
     }
@@ -204,7 +203,7 @@ impl Resp {
         Ok(
             hyper::Response::builder()
                 .header(CONTENT_TYPE, APPLICATION_JSON)
-                .body(Full::from(body.into()))?
+                .body(Body::from(body.into()))?
         )
     }
 
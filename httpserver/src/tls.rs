@@ -0,0 +1,79 @@
+//! TLS support based on rustls, certificates can be reloaded without restarting the service
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use tokio_rustls::{
+    rustls::{
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+/// ALPN protocol identifiers, prefer http/2
+const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+/// Maximum time allowed for a client to complete the TLS handshake,
+/// so that idle or slow connections do not hold a task and a socket forever
+pub(crate) const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS certificate configuration
+pub struct TlsConfig {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    acceptor: RwLock<TlsAcceptor>,
+}
+
+impl TlsConfig {
+    /// Create TLS configuration from PEM format certificate chain file and private key file
+    ///
+    /// Arguments:
+    ///
+    /// * `cert_file`: PEM certificate chain file, the server certificate comes first
+    /// * `key_file`: PEM private key file (PKCS#1, PKCS#8 or SEC1)
+    pub fn new<P: AsRef<Path>>(cert_file: P, key_file: P) -> Result<Self> {
+        let (cert_file, key_file) = (cert_file.as_ref().to_owned(), key_file.as_ref().to_owned());
+        let acceptor = RwLock::new(load_acceptor(&cert_file, &key_file)?);
+        Ok(Self {
+            cert_file,
+            key_file,
+            acceptor,
+        })
+    }
+
+    /// Reload certificate and private key from files, the new certificate only applies to
+    /// new connections, the original certificate is kept when loading fails
+    pub fn reload(&self) -> Result<()> {
+        let acceptor = load_acceptor(&self.cert_file, &self.key_file)?;
+        *self.acceptor.write().unwrap() = acceptor;
+        Ok(())
+    }
+
+    pub(crate) fn acceptor(&self) -> TlsAcceptor {
+        self.acceptor.read().unwrap().clone()
+    }
+}
+
+fn load_acceptor(cert_file: &Path, key_file: &Path) -> Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(cert_file)
+        .with_context(|| format!("open certificate file {} fail", cert_file.display()))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("parse certificate file {} fail", cert_file.display()))?;
+    if certs.is_empty() {
+        bail!("no certificate found in {}", cert_file.display());
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .with_context(|| format!("parse private key file {} fail", key_file.display()))?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("certificate and private key mismatch")?;
+    config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|v| v.to_vec()).collect();
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
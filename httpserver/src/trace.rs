@@ -0,0 +1,232 @@
+//! 请求追踪, 支持W3C traceparent及X-Request-Id请求头的解析与传递
+use std::{
+    cell::RefCell,
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use hyper::HeaderMap;
+
+/// W3C trace context 请求头
+pub const TRACEPARENT: &str = "traceparent";
+/// 请求id请求头
+pub const X_REQUEST_ID: &str = "X-Request-Id";
+
+const TRACEPARENT_VERSION: &str = "00";
+const TRACE_ID_LEN: usize = 32;
+const SPAN_ID_LEN: usize = 16;
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// 请求的追踪信息
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    /// 追踪id, 32位小写16进制字符串, 同一调用链上的所有请求共享
+    pub trace_id: String,
+    /// 本服务处理该请求时生成的span id, 16位小写16进制字符串
+    pub span_id: String,
+    /// 调用方的span id, 请求未携带traceparent时为None
+    pub parent_id: Option<String>,
+    /// 追踪标志, 最低位为采样标志
+    pub flags: u8,
+    /// 请求id, 来自X-Request-Id请求头, 请求未携带时使用追踪id
+    pub request_id: String,
+}
+
+struct RequestScope {
+    trace: TraceContext,
+    uid: RefCell<String>,
+}
+
+tokio::task_local! {
+    static CURRENT: RequestScope;
+}
+
+impl TraceContext {
+    /// 从请求头中解析追踪信息, 请求头不存在或格式错误时生成新的追踪信息
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let parent = headers
+            .get(TRACEPARENT)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_traceparent);
+
+        let (trace_id, parent_id, flags) = match parent {
+            Some((trace_id, parent_id, flags)) => (trace_id, Some(parent_id), flags),
+            None => (gen_hex_id(TRACE_ID_LEN), None, 1),
+        };
+
+        let request_id = headers
+            .get(X_REQUEST_ID)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| is_valid_request_id(v))
+            .map_or_else(|| trace_id.clone(), String::from);
+
+        Self {
+            trace_id,
+            span_id: gen_hex_id(SPAN_ID_LEN),
+            parent_id,
+            flags,
+            request_id,
+        }
+    }
+
+    /// 生成traceparent请求头的值, 以当前span id作为下游服务的parent id
+    pub fn traceparent(&self) -> String {
+        format!(
+            "{TRACEPARENT_VERSION}-{}-{}-{:02x}",
+            self.trace_id, self.span_id, self.flags
+        )
+    }
+}
+
+/// 获取当前请求的追踪信息, 不在http请求处理过程中调用时返回None
+pub fn current() -> Option<TraceContext> {
+    CURRENT.try_with(|v| v.trace.clone()).ok()
+}
+
+/// 在指定的追踪信息作用域中执行异步任务
+pub(crate) async fn scope<F: Future>(trace: TraceContext, f: F) -> F::Output {
+    let scope = RequestScope {
+        trace,
+        uid: RefCell::new(String::new()),
+    };
+    CURRENT.scope(scope, f).await
+}
+
+/// 记录当前请求的登录用户id, 供访问日志使用
+pub(crate) fn set_uid(uid: &str) {
+    let _ = CURRENT.try_with(|v| {
+        let mut cur = v.uid.borrow_mut();
+        cur.clear();
+        cur.push_str(uid);
+    });
+}
+
+/// 获取当前请求的登录用户id, 未登录时返回空字符串
+pub(crate) fn uid() -> String {
+    CURRENT
+        .try_with(|v| v.uid.borrow().clone())
+        .unwrap_or_default()
+}
+
+/// 解析traceparent, 格式: 版本-追踪id-父span id-标志, 返回(追踪id, 父span id, 标志)
+fn parse_traceparent(value: &str) -> Option<(String, String, u8)> {
+    let mut iter = value.trim().split('-');
+    let version = iter.next()?;
+    let trace_id = iter.next()?;
+    let parent_id = iter.next()?;
+    let flags = iter.next()?;
+
+    // 版本00不允许有多余的字段, 版本ff无效
+    if version.len() != 2 || !is_lower_hex(version) || version == "ff" {
+        return None;
+    }
+    if version == TRACEPARENT_VERSION && iter.next().is_some() {
+        return None;
+    }
+    if !is_valid_id(trace_id, TRACE_ID_LEN) || !is_valid_id(parent_id, SPAN_ID_LEN) {
+        return None;
+    }
+    if flags.len() != 2 || !is_lower_hex(flags) {
+        return None;
+    }
+
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    Some((trace_id.to_owned(), parent_id.to_owned(), flags))
+}
+
+/// 校验id是否为指定长度的小写16进制字符串, 且不能全部为0
+fn is_valid_id(id: &str, len: usize) -> bool {
+    id.len() == len && is_lower_hex(id) && id.bytes().any(|b| b != b'0')
+}
+
+fn is_lower_hex(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// 请求id只允许可见的ascii字符, 避免日志注入
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// 生成指定长度的随机16进制id
+fn gen_hex_id(len: usize) -> String {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+
+    let mut id = String::with_capacity(len + 16);
+    while id.len() < len {
+        // RandomState每次创建时使用不同的随机密钥, 可以作为简单的随机数生成器
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(SEQ.fetch_add(1, Ordering::Relaxed));
+        let n = hasher.finish();
+        if n != 0 {
+            id.push_str(&format!("{n:016x}"));
+        }
+    }
+    id.truncate(len);
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const PARENT_ID: &str = "00f067aa0ba902b7";
+
+    #[test]
+    fn test_parse_traceparent() {
+        let v = format!("00-{TRACE_ID}-{PARENT_ID}-01");
+        assert_eq!(
+            parse_traceparent(&v),
+            Some((TRACE_ID.to_owned(), PARENT_ID.to_owned(), 1))
+        );
+
+        // 未来版本允许有多余的字段
+        let v = format!("01-{TRACE_ID}-{PARENT_ID}-00-extra");
+        assert!(parse_traceparent(&v).is_some());
+
+        for v in [
+            format!("00-{TRACE_ID}-{PARENT_ID}-01-extra"),
+            format!("ff-{TRACE_ID}-{PARENT_ID}-01"),
+            format!("00-{}-{PARENT_ID}-01", "0".repeat(32)),
+            format!("00-{TRACE_ID}-{}-01", "0".repeat(16)),
+            format!("00-{}-{PARENT_ID}-01", TRACE_ID.to_uppercase()),
+            format!("00-{TRACE_ID}-{PARENT_ID}"),
+            String::from("garbage"),
+        ] {
+            assert_eq!(parse_traceparent(&v), None, "{v}");
+        }
+    }
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        let v = format!("00-{TRACE_ID}-{PARENT_ID}-01");
+        headers.insert(TRACEPARENT, HeaderValue::from_str(&v).unwrap());
+        headers.insert(X_REQUEST_ID, HeaderValue::from_static("req-123"));
+
+        let tc = TraceContext::from_headers(&headers);
+        assert_eq!(tc.trace_id, TRACE_ID);
+        assert_eq!(tc.parent_id.as_deref(), Some(PARENT_ID));
+        assert_eq!(tc.request_id, "req-123");
+        assert!(is_valid_id(&tc.span_id, SPAN_ID_LEN));
+        assert_ne!(tc.span_id, PARENT_ID);
+        assert_eq!(tc.traceparent(), format!("00-{TRACE_ID}-{}-01", tc.span_id));
+
+        let tc = TraceContext::from_headers(&HeaderMap::new());
+        assert!(is_valid_id(&tc.trace_id, TRACE_ID_LEN));
+        assert_eq!(tc.parent_id, None);
+        assert_eq!(tc.request_id, tc.trace_id);
+    }
+
+    #[test]
+    fn test_gen_hex_id() {
+        let a = gen_hex_id(TRACE_ID_LEN);
+        let b = gen_hex_id(TRACE_ID_LEN);
+        assert!(is_valid_id(&a, TRACE_ID_LEN));
+        assert_ne!(a, b);
+    }
+}
@@ -1,19 +1,51 @@
 //! 当前登录用户相关接口
+use std::{iter::Peekable, net::IpAddr};
+
 use crate::{
-    entities::{sys_menu::SysMenu, sys_role::SysRole, sys_user::SysUser},
-    services::uri,
-    utils::{audit, bits, md5_crypt},
+    entities::{
+        sys_menu::SysMenu,
+        sys_role::SysRole,
+        sys_user::{self, AccountType, SysUser},
+        sys_user_mfa::SysUserMfa,
+        sys_user_role::SysUserRole,
+    },
+    services::{
+        code_sender::{self, CodeSender},
+        mfa,
+        password_policy::PasswordPolicy,
+        session::{self, SessionInfo},
+        uri,
+    },
+    utils::{
+        audit, bits, code_tree,
+        ip::ip_bucket,
+        password,
+        time::{gen_time_desc, unix_timestamp},
+    },
     AppConf,
 };
 use anyhow_ext::Result;
 use httpserver::{fail_if, http_bail, http_error, HttpContext, HttpResponse, Resp};
 use localtime::LocalTime;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 /// 允许验证码尝试的最大次数
 const MAX_FAIL_COUNT: u8 = 3;
 /// 验证码发送一次的有效时长(单位: 秒)
 const AUTH_CODE_TTL: u32 = 180;
+/// 验证码的长度
+const AUTH_CODE_LEN: usize = 6;
+/// 同一目标两次发送验证码的最小间隔(单位: 秒)
+const RESEND_INTERVAL: u64 = 60;
+/// 同一目标在统计周期内允许发送验证码的最大次数
+const MAX_SEND_COUNT: u64 = 10;
+/// 同一用户在统计周期内允许发送验证码的最大次数(不区分目标)
+const MAX_USER_SEND_COUNT: u64 = 20;
+/// 同一客户端ip在统计周期内允许发送验证码的最大次数(不区分用户及目标)
+const MAX_IP_SEND_COUNT: u64 = 30;
+/// 发送次数的统计周期(单位: 秒)
+const SEND_COUNT_TTL: i64 = 3600;
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -61,6 +93,7 @@ pub async fn get(ctx: HttpContext) -> HttpResponse {
     struct Res {
         user_id: u32,
         role_id: u32,
+        role_ids: Vec<u32>,
         username: String,
         nickname: String,
         mobile: Option<String>,
@@ -81,10 +114,12 @@ pub async fn get(ctx: HttpContext) -> HttpResponse {
     let sys_role = SysRole::select_by_id(role_id)
         .await?
         .ok_or_else(|| http_error!("角色[{role_id}]不存在"))?;
+    let role_ids = SysUserRole::select_role_ids(user_id).await?;
 
     Resp::ok(&Res {
         user_id: sys_user.user_id.unwrap(),
         role_id,
+        role_ids,
         username: sys_user.username.unwrap(),
         nickname: sys_user.nickname.unwrap(),
         mobile: sys_user.mobile,
@@ -151,23 +186,13 @@ pub async fn change_password(ctx: HttpContext) -> HttpResponse {
         .ok_or_else(|| http_error!("用户[{user_id}]不存在"))?;
 
     // 校验口令是否正确
-    let checked = md5_crypt::verify(&param.old_password, sys_user.password.as_ref().unwrap())
+    let checked = password::verify_async(&param.old_password, sys_user.password.as_ref().unwrap())
+        .await
         .map_err(|_| http_error!("无法校验口令"))?;
     fail_if!(!checked, "旧密码不正确");
-    // 生成新的加密口令
-    let pwd =
-        md5_crypt::encrypt(&param.new_password).map_err(|_| http_error!("无法生成加密口令"))?;
-
-    let sys_user = SysUser {
-        user_id: Some(user_id),
-        password: Some(pwd),
-        updated_time: Some(LocalTime::now()),
-        ..Default::default()
-    };
-    let mut audit_data = sys_user.clone();
-
-    // 写入数据库
-    sys_user.update_by_id().await?;
+    // 按口令策略校验新口令后写入数据库
+    let policy = PasswordPolicy::load().await?;
+    let mut audit_data = policy.change(user_id, &param.new_password).await?;
     // 写入审计日志
     audit_data.updated_time = None;
     audit::log_json(audit::ACCOUNT_UPD_PASSWORD, user_id, &audit_data);
@@ -237,6 +262,165 @@ pub async fn change_email(ctx: HttpContext) -> HttpResponse {
     Resp::ok_with_empty()
 }
 
+/// 开始绑定双因子认证, 返回TOTP密钥及otpauth链接(可使用tools/qrcode生成二维码)
+pub async fn mfa_enroll(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Res {
+        secret: String,
+        otpauth_uri: String,
+    }
+
+    let user_id = ctx.uid.parse().unwrap();
+    if let Some(v) = SysUserMfa::select_by_id(user_id).await? {
+        fail_if!(v.is_enabled(), "已启用双因子认证, 请先停用后再重新绑定");
+    }
+    let sys_user = SysUser::select_by_id(user_id)
+        .await?
+        .ok_or_else(|| http_error!("用户[{user_id}]不存在"))?;
+
+    let (secret, otpauth_uri) = mfa::enroll(user_id, sys_user.username.as_ref().unwrap()).await?;
+
+    Resp::ok(&Res {
+        secret,
+        otpauth_uri,
+    })
+}
+
+/// 确认绑定双因子认证, 成功后返回恢复码(仅返回1次)
+pub async fn mfa_confirm(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        code: String,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Res {
+        recovery_codes: Vec<String>,
+    }
+
+    let user_id = ctx.uid.parse().unwrap();
+    let param: Req = ctx.parse_json()?;
+    let user_mfa = SysUserMfa::select_by_id(user_id)
+        .await?
+        .ok_or_else(|| http_error!("请先绑定双因子认证"))?;
+    fail_if!(user_mfa.is_enabled(), "已启用双因子认证");
+
+    let recovery_codes = match mfa::confirm(user_mfa, param.code.trim()).await? {
+        Some(v) => v,
+        None => http_bail!("验证码错误"),
+    };
+
+    // 写入审计日志
+    audit::log(audit::ACCOUNT_MFA_ENABLE, user_id, String::new());
+
+    Resp::ok(&Res { recovery_codes })
+}
+
+/// 停用双因子认证, 需要提供口令及TOTP口令(或恢复码)
+pub async fn mfa_disable(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        password: String,
+        code: String,
+    }
+
+    let user_id = ctx.uid.parse().unwrap();
+    let param: Req = ctx.parse_json()?;
+    let sys_user = SysUser::select_by_id(user_id)
+        .await?
+        .ok_or_else(|| http_error!("用户[{user_id}]不存在"))?;
+    let user_mfa = SysUserMfa::select_by_id(user_id)
+        .await?
+        .ok_or_else(|| http_error!("尚未启用双因子认证"))?;
+
+    fail_if!(
+        mfa::is_required(user_id).await?,
+        "当前账号必须启用双因子认证, 不允许停用"
+    );
+
+    // 校验口令及验证码
+    let checked = password::verify_async(&param.password, sys_user.password.as_ref().unwrap())
+        .await
+        .map_err(|_| http_error!("无法校验口令"))?;
+    fail_if!(!checked, "密码不正确");
+    if user_mfa.is_enabled() {
+        fail_if!(!mfa::verify(&user_mfa, &param.code).await?, "验证码错误");
+    }
+
+    // 写入数据库
+    SysUserMfa::delete_by_id(user_id).await?;
+    // 写入审计日志
+    audit::log(audit::ACCOUNT_MFA_DISABLE, user_id, String::new());
+
+    Resp::ok_with_empty()
+}
+
+/// 获取当前账号的所有会话
+pub async fn sessions(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct SessionItem {
+        #[serde(flatten)]
+        inner: SessionInfo,
+        /// 是否当前请求使用的会话
+        current: bool,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Res {
+        sessions: Vec<SessionItem>,
+    }
+
+    let user_id = ctx.uid.parse().unwrap();
+    let curr_sid = current_session_id(&ctx);
+    let sessions = session::list(user_id)
+        .await
+        .into_iter()
+        .map(|mut v| {
+            v.token.clear();
+            v.family.clear();
+            let current = curr_sid.as_ref() == Some(&v.session_id);
+            SessionItem { inner: v, current }
+        })
+        .collect();
+
+    Resp::ok(&Res { sessions })
+}
+
+/// 注销当前账号的会话, 未指定会话id时注销除当前会话外的所有会话
+pub async fn revoke_sessions(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        session_id: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Res {
+        count: usize,
+    }
+
+    let user_id = ctx.uid.parse().unwrap();
+    let param: Req = ctx.parse_json_opt()?.unwrap_or(Req { session_id: None });
+    let curr_sid = current_session_id(&ctx);
+
+    let count = match &param.session_id {
+        Some(sid) => session::revoke(user_id, |v| &v.session_id == sid).await,
+        None => session::revoke(user_id, |v| curr_sid.as_ref() != Some(&v.session_id)).await,
+    };
+
+    // 写入审计日志
+    audit::log_json(audit::ACCOUNT_REVOKE_SESSION, user_id, &param);
+
+    Resp::ok(&Res { count })
+}
+
 /// 获取当前账号允许访问的菜单树
 pub async fn menus(ctx: HttpContext) -> HttpResponse {
     #[derive(Deserialize)]
@@ -268,6 +452,143 @@ pub async fn menus(ctx: HttpContext) -> HttpResponse {
     Resp::ok(&Res { menus })
 }
 
+/// 发送更换手机号码的验证码
+pub async fn send_mobile_code(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct Req {
+        mobile: String,
+    }
+
+    let user_id = ctx.uid.parse().unwrap();
+    let param: Req = ctx.parse_json()?;
+    fail_if!(
+        !matches!(
+            sys_user::check_account_type(&param.mobile),
+            AccountType::Mobile
+        ),
+        "手机号码格式错误"
+    );
+
+    send_auth_code(
+        uri::CK_MOBILE_AUTH_CODE,
+        &param.mobile,
+        user_id,
+        &ctx.remote_ip(),
+        code_sender::mobile_sender(),
+    )
+    .await?;
+
+    Resp::ok_with_empty()
+}
+
+/// 发送更换邮箱的验证码
+pub async fn send_email_code(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct Req {
+        email: String,
+    }
+
+    let user_id = ctx.uid.parse().unwrap();
+    let param: Req = ctx.parse_json()?;
+    fail_if!(
+        !matches!(
+            sys_user::check_account_type(&param.email),
+            AccountType::Email
+        ),
+        "电子邮箱格式错误"
+    );
+
+    send_auth_code(
+        uri::CK_EMAIL_AUTH_CODE,
+        &param.email,
+        user_id,
+        &ctx.remote_ip(),
+        code_sender::email_sender(),
+    )
+    .await?;
+
+    Resp::ok_with_empty()
+}
+
+/// 生成验证码并发送, 同一目标受重发间隔及发送次数的限制,
+/// 同时限制同一用户及同一客户端ip的发送次数, 避免更换目标绕过限制
+async fn send_auth_code(
+    key_type: &str,
+    target: &str,
+    user_id: u32,
+    ip: &IpAddr,
+    sender: &dyn CodeSender,
+) -> Result<()> {
+    // 目标已被其它账号使用时不允许发送
+    if let Some(user) = SysUser::select_by_account(target).await? {
+        fail_if!(user.user_id != Some(user_id), "该账号已被其它用户使用");
+    }
+
+    let redis_pre = &AppConf::get().redis_pre;
+    let key = format!("{redis_pre}:{key_type}:{target}");
+    let now = unix_timestamp();
+
+    // 校验重发间隔
+    if let Some(value) = uri::get::<String>(&key).await {
+        if let Ok(aci) = serde_json::from_str::<AuthCodeInfo>(&value) {
+            let elapsed = now.saturating_sub(aci.created_time);
+            if elapsed < RESEND_INTERVAL {
+                http_bail!("发送过于频繁, 请{}秒后再试", RESEND_INTERVAL - elapsed);
+            }
+        }
+    }
+
+    // 校验统计周期内客户端ip、用户及目标的发送次数
+    let ip_key = format!("{redis_pre}:{key_type}:ip:{}", ip_bucket(ip));
+    check_send_count(&ip_key, MAX_IP_SEND_COUNT).await?;
+    let user_key = format!("{redis_pre}:{key_type}:user:{user_id}");
+    check_send_count(&user_key, MAX_USER_SEND_COUNT).await?;
+    let count_key = format!("{redis_pre}:{key_type}:count:{target}");
+    check_send_count(&count_key, MAX_SEND_COUNT).await?;
+
+    let aci = AuthCodeInfo {
+        auth_code: gen_auth_code(),
+        try_count: 0,
+        created_time: now,
+    };
+    let value = serde_json::to_string(&aci)?;
+    uri::set(&key, &value, AUTH_CODE_TTL as u64).await;
+
+    if let Err(e) = sender.send(target, &aci.auth_code, AUTH_CODE_TTL).await {
+        log::error!("发送验证码失败: {e:?}");
+        uri::del(&key).await;
+        http_bail!("验证码发送失败, 请稍后再试");
+    }
+
+    Ok(())
+}
+
+/// 增加统计周期内的发送次数, 超过限制值时返回错误,
+/// 无法计数(redis异常)时同样拒绝发送, 避免限制失效
+async fn check_send_count(key: &str, max: u64) -> Result<()> {
+    let count = match uri::incr(key, 1).await {
+        Some(v) => v,
+        None => http_bail!("验证码发送失败, 请稍后再试"),
+    };
+    if count == 1 {
+        uri::expire(key, SEND_COUNT_TTL).await;
+    }
+    if count > max {
+        let ttl = uri::ttl(key).await.unwrap_or(SEND_COUNT_TTL);
+        http_bail!("发送次数超过限制, 请过{}后再试", gen_time_desc(ttl as u32));
+    }
+
+    Ok(())
+}
+
+/// 生成数字验证码
+fn gen_auth_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..AUTH_CODE_LEN)
+        .map(|_| char::from(b'0' + rng.gen_range(0..10)))
+        .collect()
+}
+
 async fn check_auth_code(key_type: &str, key: &str, code: &str) -> Result<()> {
     let key = format!("{}:{}:{}", AppConf::get().redis_pre, key_type, key);
     let value: String = match uri::get(&key).await {
@@ -313,54 +634,65 @@ fn filter_user_menus<'a>(menus: &'a [SysMenu], permits: &str) -> Vec<&'a SysMenu
     user_menus
 }
 
-/// 删除菜单列表中的菜单链接内容为"#"的菜单，该菜单项表示为有下级子菜单
+/// 递归删除菜单列表中没有子菜单且链接内容为"#"的菜单，该菜单项表示为有下级子菜单
 fn trim_empty_menu(menus: &mut Vec<LocalSysMenu>) {
-    // for menu in menus.iter_mut() {
-    //     if let Some(children) = menu.menus.as_mut() {
-    //         children.retain(|m| m.menu.menu_link.as_ref().unwrap() != "#");
-    //     }
-    // }
-    menus.retain(|m| {
-        if m.menu.menu_link.as_ref().unwrap() == "#" {
-            return match &m.menus {
-                Some(children) => !children.is_empty(),
-                None => false,
-            };
+    for menu in menus.iter_mut() {
+        if let Some(children) = menu.menus.as_mut() {
+            trim_empty_menu(children);
+            if children.is_empty() {
+                menu.menus = None;
+            }
         }
-        true
-    });
+    }
+
+    menus.retain(|m| m.menu.menu_link.as_ref().unwrap() != "#" || m.menus.is_some());
+}
+
+/// 将扁平化的菜单列表转换为树形结构, 上级菜单不在列表中的菜单项将被丢弃
+fn convert_to_tree(mut menus: Vec<&SysMenu>) -> Vec<LocalSysMenu> {
+    menus.sort_by(|a, b| a.menu_code.cmp(&b.menu_code));
+    build_sub_tree(&mut menus.into_iter().peekable(), "")
 }
 
-/// 将扁平化的菜单列表转换为树形结构
-fn convert_to_tree(menus: Vec<&SysMenu>) -> Vec<LocalSysMenu> {
+/// 递归构建指定菜单代码的子菜单树, 菜单列表需要按菜单代码排序
+fn build_sub_tree<'a, I: Iterator<Item = &'a SysMenu>>(
+    menus: &mut Peekable<I>,
+    parent_code: &str,
+) -> Vec<LocalSysMenu<'a>> {
     let mut tree_menus = Vec::new();
 
-    // 将扁平结构的菜单列表转成树结构
-    for menu in menus {
-        let menu_code_len = match &menu.menu_code {
-            Some(s) => s.len(),
-            None => 0,
-        };
-
-        let new_menu = LocalSysMenu { menu, menus: None };
-
-        match menu_code_len {
-            2 => tree_menus.push(new_menu),
-            4 => {
-                let idx = tree_menus.len() - 1;
-                let last = &mut tree_menus[idx];
-                if last.menus.is_none() {
-                    last.menus = Some(Vec::new());
-                }
-                last.menus.as_mut().unwrap().push(new_menu);
-            }
-            _ => {}
+    while let Some(&menu) = menus.peek() {
+        let menu_code = menu.menu_code.as_deref().unwrap_or_default();
+        // 已经不是当前菜单的下级菜单
+        if menu_code.len() <= parent_code.len() || !menu_code.starts_with(parent_code) {
+            break;
+        }
+
+        menus.next();
+        // 中间层级的菜单不在列表中(无访问权限), 丢弃该菜单项
+        if code_tree::parent_code(menu_code) != parent_code {
+            continue;
         }
+
+        let children = build_sub_tree(menus, menu_code);
+        tree_menus.push(LocalSysMenu {
+            menu,
+            menus: if children.is_empty() {
+                None
+            } else {
+                Some(children)
+            },
+        });
     }
 
     tree_menus
 }
 
+/// 获取当前请求令牌对应的会话id
+fn current_session_id(ctx: &HttpContext) -> Option<String> {
+    crate::auth::Authentication::get_token_from_header(ctx).and_then(|t| session::session_id(&t))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -438,4 +770,44 @@ mod tests {
             assert_eq!(check_data[i], sub_menus[i].menu.menu_code.as_ref().unwrap());
         }
     }
+
+    #[test]
+    fn test_account_multi_level_menus() {
+        let menus_vec = vec![
+            ("01", "系统设置", "#", -1),
+            ("0101", "权限管理", "#", -1),
+            ("010101", "用户管理", "/user", 0),
+            ("010102", "角色管理", "/role", 1),
+            ("0102", "日志管理", "#", 1),
+            ("010201", "操作日志", "/log", -1),
+            ("02", "空目录", "#", -1),
+            ("0201", "空子目录", "#", -1),
+        ];
+
+        let mut menus = Vec::new();
+        for menu in &menus_vec {
+            let item = super::SysMenu {
+                menu_code: Some(menu.0.to_string()),
+                menu_name: Some(menu.1.to_string()),
+                menu_link: Some(menu.2.to_string()),
+                permission_code: Some(menu.3),
+                ..Default::default()
+            };
+            menus.push(item);
+        }
+
+        // 无权限访问"0102"时, 其下级菜单"010201"也将被丢弃
+        let user_menus = super::filter_user_menus(&menus, "80");
+        let mut tree = super::convert_to_tree(user_menus);
+        super::trim_empty_menu(&mut tree);
+
+        assert_eq!(1, tree.len());
+        assert_eq!("01", tree[0].menu.menu_code.as_ref().unwrap());
+        let level2 = tree[0].menus.as_ref().unwrap();
+        assert_eq!(1, level2.len());
+        assert_eq!("0101", level2[0].menu.menu_code.as_ref().unwrap());
+        let level3 = level2[0].menus.as_ref().unwrap();
+        assert_eq!(1, level3.len());
+        assert_eq!("010101", level3[0].menu.menu_code.as_ref().unwrap());
+    }
 }
@@ -1,5 +1,9 @@
 //! 系统配置接口
-use crate::{entities::{sys_config::SysConfig, PageQuery}, utils::audit};
+use crate::{
+    entities::{sys_config::SysConfig, PageQuery},
+    services::tenant,
+    utils::audit,
+};
 use anyhow_ext::anyhow;
 use httpserver::{check_required, http_bail, HttpContext, HttpResponse, Resp};
 use localtime::LocalTime;
@@ -23,6 +27,7 @@ pub async fn get(ctx: HttpContext) -> HttpResponse {
     let rec = SysConfig::select_by_id(param.id)
         .await?
         .ok_or(anyhow!(super::REC_NOT_EXISTS))?;
+    tenant::check_owner(rec.tenant_id)?;
 
     Resp::ok(&rec)
 }
@@ -69,6 +74,7 @@ pub async fn update(ctx: HttpContext) -> HttpResponse {
         Some(v) => v,
         None => http_bail!("记录不存在"),
     };
+    tenant::check_owner(orig.tenant_id)?;
 
     param.updated_time = Some(LocalTime::now());
     let audit_data = audit::diff(
@@ -103,6 +109,7 @@ pub async fn del(ctx: HttpContext) -> HttpResponse {
         Some(v) => v,
         None => http_bail!("记录不存在"),
     };
+    tenant::check_owner(orig.tenant_id)?;
 
     // 写入数据库
     SysConfig::delete_with_notify(param.id).await?;
@@ -0,0 +1,232 @@
+//! 部门表接口
+use std::collections::HashSet;
+
+use crate::{
+    entities::{
+        sys_dept::{self, SysDept, SysDeptVo},
+        sys_user::SysUser,
+        PageData, PageQuery,
+    },
+    services::tenant,
+    utils::{
+        audit,
+        code_tree::{self, parent_code, CODE_LEN},
+    },
+};
+use anyhow_ext::{anyhow, Result};
+use httpserver::{check_required, http_bail, HttpContext, HttpResponse, Resp};
+use localtime::LocalTime;
+use serde::{Deserialize, Serialize};
+
+/// 记录列表
+pub async fn list(ctx: HttpContext) -> HttpResponse {
+    type Req = PageQuery<SysDept>;
+
+    let mut param: Req = ctx.parse_json()?;
+    // 指定部门代码时查询其直接下级部门
+    if let Some(dept_code) = param.inner.dept_code.as_mut() {
+        dept_code.push_str(&"_".repeat(CODE_LEN));
+    }
+    let pg = param.page_info();
+    let page_data = SysDept::select_page(param.inner, pg).await?;
+
+    Resp::ok(&page_data)
+}
+
+/// 获取单条记录
+pub async fn get(ctx: HttpContext) -> HttpResponse {
+    type Req = super::GetReq;
+
+    let param: Req = ctx.parse_json()?;
+    let rec = SysDept::select_by_id(param.id)
+        .await?
+        .ok_or(anyhow!(super::REC_NOT_EXISTS))?;
+    tenant::check_owner(rec.tenant_id)?;
+
+    Resp::ok(&rec)
+}
+
+/// 添加单条记录
+pub async fn insert(ctx: HttpContext) -> HttpResponse {
+    type Req = SysDeptVo;
+
+    let mut param: Req = ctx.parse_json()?;
+    check_required!(param.inner, dept_name);
+
+    let pdc = param.parent_dept_code.take().unwrap_or_default();
+    check_parent(&pdc).await?;
+    check_leader(param.inner.leader_id).await?;
+
+    let mut dept = param.inner;
+    dept.dept_id = None;
+    dept.dept_code = Some(gen_dept_code(&pdc).await?);
+    if dept.disabled.is_none() {
+        dept.disabled = Some(0);
+    }
+    dept.updated_time = Some(LocalTime::now());
+
+    let id = dept.clone().insert_with_notify().await?.1;
+
+    dept.dept_id = Some(id);
+    dept.updated_time = None;
+    audit::log_json(audit::DEPT_ADD, ctx.user_id(), &dept);
+
+    Resp::ok(&SysDept {
+        dept_id: Some(id),
+        ..Default::default()
+    })
+}
+
+/// 更新单条记录, 指定上级部门代码时将部门(及其所有下级部门)移动到新的上级部门下
+pub async fn update(ctx: HttpContext) -> HttpResponse {
+    type Req = SysDeptVo;
+
+    let mut param: Req = ctx.parse_json()?;
+    check_required!(param.inner, dept_id);
+
+    let orig = match SysDept::select_by_id(param.inner.dept_id.unwrap()).await? {
+        Some(v) => v,
+        None => http_bail!("部门不存在"),
+    };
+    tenant::check_owner(orig.tenant_id)?;
+    check_leader(param.inner.leader_id).await?;
+
+    let mut dept = param.inner;
+    dept.updated_time = Some(LocalTime::now());
+
+    // 上级部门变更时, 重新生成部门代码, 所有下级部门随之移动
+    let orig_code = orig.dept_code.clone().unwrap_or_default();
+    dept.dept_code = None;
+    if let Some(pdc) = param.parent_dept_code.take() {
+        if parent_code(&orig_code) != pdc {
+            if pdc.starts_with(&orig_code) {
+                http_bail!("上级部门不能是部门本身或其下级部门");
+            }
+            check_parent(&pdc).await?;
+            dept.dept_code = Some(gen_dept_code(&pdc).await?);
+        }
+    }
+
+    let dept_id = dept.dept_id;
+    let audit_diff = audit::diff(&dept, &orig, &[SysDept::DEPT_ID], &[SysDept::UPDATED_TIME]);
+    dept.update_with_descendant(&orig_code).await?;
+    audit::log_text(audit::DEPT_UPD, ctx.user_id(), audit_diff);
+
+    Resp::ok(&SysDept {
+        dept_id,
+        ..Default::default()
+    })
+}
+
+/// 删除记录, 部门存在下级部门或用户时不允许删除
+pub async fn del(ctx: HttpContext) -> HttpResponse {
+    type Req = super::GetReq;
+
+    let param: Req = ctx.parse_json()?;
+    let audit_data = match SysDept::select_by_id(param.id).await? {
+        Some(v) => v,
+        None => http_bail!("记录不存在"),
+    };
+    tenant::check_owner(audit_data.tenant_id)?;
+    if let Some(code) = &audit_data.dept_code {
+        if SysDept::select_descendant_count(code).await? > 0 {
+            http_bail!("请先删除该部门的下级部门");
+        }
+    }
+    if SysUser::select_count_by_dept(param.id).await? > 0 {
+        http_bail!("该部门下存在用户, 不能删除");
+    }
+
+    SysDept::delete_with_notify(param.id).await?;
+    audit::log_json(audit::DEPT_DEL, ctx.user_id(), &audit_data);
+
+    Resp::ok_with_empty()
+}
+
+/// 获取树形结构的部门列表
+pub async fn tree(_ctx: HttpContext) -> HttpResponse {
+    type Res = PageData<SysDeptVo>;
+
+    let depts = SysDept::select_all().await?;
+
+    Resp::ok(&Res::with_list(sys_dept::to_tree(&depts)))
+}
+
+/// 根据树形结构重新排列部门, 同时可调整部门的上下级关系
+pub async fn rearrange(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        depts: Vec<SysDeptVo>,
+    }
+
+    let param: Req = ctx.parse_json()?;
+
+    let mut list = Vec::new();
+    code_tree::tree_to_list(
+        &mut list,
+        "",
+        &param.depts,
+        "下级部门",
+        &|v: &SysDeptVo| v.depts.as_deref(),
+        &|v, dept_code| match v.inner.dept_id {
+            Some(dept_id) => Ok(SysDept {
+                dept_id: Some(dept_id),
+                dept_code: Some(dept_code),
+                ..Default::default()
+            }),
+            None => http_bail!("部门id不能为空"),
+        },
+    )?;
+
+    // 必须提交完整的部门树且部门不能重复, 避免未提交的部门与新的部门代码冲突
+    let ids: HashSet<_> = list.iter().filter_map(|v| v.dept_id).collect();
+    if ids.len() != list.len() {
+        http_bail!("部门不能重复");
+    }
+    let all_ids: HashSet<_> = SysDept::select_all()
+        .await?
+        .iter()
+        .filter_map(|v| v.dept_id)
+        .collect();
+    if ids != all_ids {
+        http_bail!("部门列表不一致, 请刷新后重试");
+    }
+
+    SysDept::batch_update_rearrange(&list).await?;
+    audit::log_json(audit::DEPT_REARRANGE, ctx.user_id(), &param);
+
+    Resp::ok_with_empty()
+}
+
+/// 校验上级部门是否存在, 空字符串表示顶级部门
+async fn check_parent(parent_dept_code: &str) -> Result<()> {
+    if !parent_dept_code.is_empty()
+        && !SysDept::select_all()
+            .await?
+            .iter()
+            .any(|v| v.dept_code.as_deref() == Some(parent_dept_code))
+    {
+        http_bail!("上级部门[{}]不存在", parent_dept_code);
+    }
+    Ok(())
+}
+
+/// 校验部门负责人是否存在
+async fn check_leader(leader_id: Option<u32>) -> Result<()> {
+    if let Some(id) = leader_id {
+        if id != 0 {
+            match SysUser::select_by_id(id).await? {
+                Some(user) if tenant::check_owner(user.tenant_id).is_ok() => {}
+                _ => http_bail!("部门负责人[{}]不存在", id),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 生成指定上级部门下新的下级部门代码
+async fn gen_dept_code(parent_dept_code: &str) -> Result<String> {
+    let max_code = SysDept::select_max_code(parent_dept_code).await?;
+    code_tree::next_code(parent_dept_code, max_code.as_deref(), "下级部门")
+}
@@ -7,6 +7,7 @@ use crate::{
         sys_dict::{SysDict, SysDictExt},
         PageData, PageQuery,
     },
+    services::tenant,
     utils::{audit, IntStr},
 };
 use httpserver::{
@@ -34,6 +35,7 @@ pub async fn get(ctx: HttpContext) -> HttpResponse {
     let rec = SysDict::select_by_id(param.id)
         .await?
         .ok_or(http_error!(super::REC_NOT_EXISTS.to_string()))?;
+    tenant::check_owner(rec.tenant_id)?;
 
     Resp::ok(&rec)
 }
@@ -78,6 +80,7 @@ pub async fn update(ctx: HttpContext) -> HttpResponse {
         Some(r) => r,
         None => http_bail!("记录不存在"),
     };
+    tenant::check_owner(orig.tenant_id)?;
 
     param.updated_time = Some(LocalTime::now());
     let audit_data = audit::diff(&param, &orig, &[SysDict::DICT_ID], &[SysDict::UPDATED_TIME]);
@@ -104,6 +107,7 @@ pub async fn del(ctx: HttpContext) -> HttpResponse {
         Some(v) => v,
         None => http_bail!("记录不存在"),
     };
+    tenant::check_owner(orig.tenant_id)?;
 
     // 删除记录
     SysDict::delete_with_notify(param.id).await?;
@@ -169,8 +173,9 @@ pub async fn permission_groups(ctx: HttpContext) -> HttpResponse {
     Resp::ok(&Res::with_list(list))
 }
 
-/// 重新排序dict的id值使其连续
+/// 重新排序dict的id值使其连续, 该操作影响所有租户, 只允许平台租户执行
 pub async fn resort(ctx: HttpContext) -> HttpResponse {
+    tenant::check_platform()?;
     log_debug!(ctx.id, "重新排序{}表", SysDict::TABLE_NAME);
     SysDict::resort(ctx.id).await?;
     Resp::ok_with_empty()
@@ -0,0 +1,139 @@
+//! 服务健康检查接口, 供kubernetes等容器平台的存活探针及就绪探针调用
+use std::{future::Future, time::Duration};
+
+use anyhow_ext::Result;
+use httpserver::{ApiResult, HttpContext, HttpResponse, Resp};
+use hyper::StatusCode;
+use localtime::LocalTime;
+use serde::Serialize;
+
+use crate::{
+    services::{mq, uri},
+    utils::rcall,
+    AppConf,
+};
+
+/// 单个组件检查的超时时间(单位: 秒)
+const CHECK_TIMEOUT: u64 = 3;
+const STATUS_UP: &str = "UP";
+const STATUS_DOWN: &str = "DOWN";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Component {
+    status: &'static str,
+    /// 检查耗时(单位: 毫秒)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency: Option<u64>,
+    /// 最近1次成功的时间
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_success: Option<LocalTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// 存活探针, 只要服务能够响应请求即认为存活, 不检查依赖的外部服务
+pub async fn live(_ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    struct Res {
+        status: &'static str,
+    }
+
+    Resp::ok(&Res { status: STATUS_UP })
+}
+
+/// 就绪探针, 检查数据库、redis、消息订阅及网关注册状态, 任意组件异常时返回503
+pub async fn ready(_ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    struct Res {
+        status: &'static str,
+        components: Components,
+    }
+
+    #[derive(Serialize)]
+    struct Components {
+        db: Component,
+        redis: Component,
+        mq: Component,
+        /// 未配置网关时不检查
+        #[serde(skip_serializing_if = "Option::is_none")]
+        gateway: Option<Component>,
+    }
+
+    let (db, redis) = tokio::join!(check(gensql::try_connect()), check(uri::ping()));
+    let components = Components {
+        db,
+        redis,
+        mq: check_mq(),
+        gateway: check_gateway(),
+    };
+
+    let is_ready = [&components.db, &components.redis, &components.mq]
+        .into_iter()
+        .chain(components.gateway.as_ref())
+        .all(|v| v.status == STATUS_UP);
+    let res = Res {
+        status: if is_ready { STATUS_UP } else { STATUS_DOWN },
+        components,
+    };
+
+    if is_ready {
+        Resp::ok(&res)
+    } else {
+        let ar = ApiResult {
+            code: StatusCode::SERVICE_UNAVAILABLE.as_u16() as u32,
+            message: Some(String::from("服务未就绪")),
+            data: Some(res),
+        };
+        Resp::resp(StatusCode::SERVICE_UNAVAILABLE, serde_json::to_vec(&ar)?)
+    }
+}
+
+/// 执行组件检查, 记录检查耗时, 超时视为检查失败
+async fn check<F, T, E>(f: F) -> Component
+where
+    F: Future<Output = Result<T, E>>,
+    E: std::fmt::Display,
+{
+    let start = std::time::Instant::now();
+    let error = match tokio::time::timeout(Duration::from_secs(CHECK_TIMEOUT), f).await {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(String::from("检查超时")),
+    };
+
+    Component {
+        status: if error.is_none() {
+            STATUS_UP
+        } else {
+            STATUS_DOWN
+        },
+        latency: Some(start.elapsed().as_millis() as u64),
+        last_success: None,
+        error,
+    }
+}
+
+fn check_mq() -> Component {
+    let connected = mq::is_connected();
+    Component {
+        status: if connected { STATUS_UP } else { STATUS_DOWN },
+        latency: None,
+        last_success: None,
+        error: (!connected).then(|| String::from("消息订阅连接已断开")),
+    }
+}
+
+fn check_gateway() -> Option<Component> {
+    if AppConf::get().gateway.is_empty() {
+        return None;
+    }
+
+    let (ok, last_success) = rcall::reg_status();
+    Some(Component {
+        status: if ok { STATUS_UP } else { STATUS_DOWN },
+        latency: None,
+        last_success: (last_success > 0).then(|| LocalTime::from_unix_timestamp(last_success)),
+        error: (!ok).then(|| String::from("向网关注册服务失败")),
+    })
+}
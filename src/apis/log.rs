@@ -0,0 +1,151 @@
+//! 审计日志接口
+use crate::{
+    entities::{
+        sys_log::{SysLog, SysLogExt, SysLogQuery},
+        PageQuery,
+    },
+    services::{
+        export::{self, Encoder, ExportFile},
+        tenant,
+    },
+    utils::sheet::{self, write_csv_field},
+};
+use anyhow_ext::{anyhow, Result};
+use httpserver::{http_bail, HttpContext, HttpResponse, Resp};
+use serde::Deserialize;
+
+const CSV_HEADER: &str = "日志id,操作类别,操作员id,操作员,操作时间,操作内容\r\n";
+
+/// 记录列表
+pub async fn list(ctx: HttpContext) -> HttpResponse {
+    type Req = PageQuery<SysLogQuery>;
+
+    let param: Req = ctx.parse_json()?;
+    let pg = param.page_info();
+    let page_data = SysLog::select_page(param.inner, pg).await?;
+
+    Resp::ok(&page_data)
+}
+
+/// 获取单条记录
+pub async fn get(ctx: HttpContext) -> HttpResponse {
+    type Req = super::GetReq;
+
+    let param: Req = ctx.parse_json()?;
+    let rec = SysLog::select_by_id(param.id)
+        .await?
+        .ok_or(anyhow!(super::REC_NOT_EXISTS))?;
+    tenant::check_owner(rec.tenant_id)?;
+
+    Resp::ok(&rec)
+}
+
+/// 导出符合条件的审计日志, 支持csv和json格式, 超过导出上限时只导出最早的部分记录,
+/// 并在回复头部X-Export-Truncated中标记
+pub async fn export(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        #[serde(flatten)]
+        query: SysLogQuery,
+        /// 导出格式: csv(缺省)/json
+        format: Option<String>,
+    }
+
+    let param: Req = ctx.parse_json()?;
+    let is_csv = match param.format.as_deref() {
+        None | Some("csv") => true,
+        Some("json") => false,
+        Some(f) => http_bail!("不支持的导出格式: {}", f),
+    };
+
+    let (content_type, file_name) = if is_csv {
+        (sheet::CSV_CONTENT_TYPE, "audit_log.csv")
+    } else {
+        (httpserver::APPLICATION_JSON, "audit_log.json")
+    };
+    let file = ExportFile {
+        file_name: file_name.to_owned(),
+        content_type,
+        total: SysLog::select_count(param.query.clone()).await?,
+    };
+
+    let query = param.query;
+    export::stream(
+        file,
+        LogEncoder::new(is_csv),
+        move |after_id, count| SysLog::select_batch(query.clone(), after_id, count),
+        |v: &SysLogExt| v.inner.log_id,
+    )
+}
+
+/// 审计日志导出文件编码器, json格式输出为数组
+struct LogEncoder {
+    is_csv: bool,
+    buf: Vec<u8>,
+    rows: usize,
+}
+
+impl LogEncoder {
+    fn new(is_csv: bool) -> Self {
+        let mut buf = Vec::with_capacity(64 * 1024);
+        if is_csv {
+            // 写入utf8 bom, 避免excel打开时中文乱码
+            buf.extend_from_slice(b"\xEF\xBB\xBF");
+            buf.extend_from_slice(CSV_HEADER.as_bytes());
+        } else {
+            buf.push(b'[');
+        }
+        Self {
+            is_csv,
+            buf,
+            rows: 0,
+        }
+    }
+}
+
+impl Encoder<SysLogExt> for LogEncoder {
+    fn write(&mut self, item: &SysLogExt) -> Result<()> {
+        if self.is_csv {
+            write_csv_row(&mut self.buf, item);
+        } else {
+            if self.rows > 0 {
+                self.buf.push(b',');
+            }
+            serde_json::to_writer(&mut self.buf, item)?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>> {
+        if !self.is_csv {
+            self.buf.push(b']');
+        }
+        Ok(self.buf)
+    }
+}
+
+fn write_csv_row(buf: &mut Vec<u8>, item: &SysLogExt) {
+    let log = &item.inner;
+    let mut num_buf = itoa::Buffer::new();
+
+    buf.extend_from_slice(num_buf.format(log.log_id.unwrap_or(0)).as_bytes());
+    buf.push(b',');
+    write_csv_field(buf, log.opera_type.as_deref().unwrap_or(""));
+    buf.push(b',');
+    buf.extend_from_slice(num_buf.format(log.user_id.unwrap_or(0)).as_bytes());
+    buf.push(b',');
+    write_csv_field(buf, item.username.as_deref().unwrap_or(""));
+    buf.push(b',');
+    if let Some(t) = &log.created_time {
+        buf.extend_from_slice(t.to_string().as_bytes());
+    }
+    buf.push(b',');
+    write_csv_field(buf, log.opera_text.as_deref().unwrap_or(""));
+    buf.extend_from_slice(b"\r\n");
+}
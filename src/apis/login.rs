@@ -1,22 +1,71 @@
 //! 用户登录相关接口
 use crate::{
     auth::{self, Authentication},
-    entities::sys_user,
-    services::mq,
-    utils::{self, audit, consts},
+    entities::{
+        sys_user::{self, DisabledType, SysUser},
+        sys_user_mfa::SysUserMfa,
+    },
+    services::{
+        jwt_keys,
+        login_lock::{self, LoginLock},
+        mfa, mq,
+        password_policy::PasswordPolicy,
+        refresh_token, session, tenant,
+    },
+    utils::{self, audit, consts, time::gen_time_desc},
     AppConf, AppGlobal,
 };
+use anyhow_ext::Result;
 use httpserver::{fail_if, http_bail, if_else, log_info, HttpContext, HttpResponse, Resp};
 use localtime::LocalTime;
 use serde::{Deserialize, Serialize};
 
+const USER_AGENT: &str = "User-Agent";
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct LoginRes {
     token: String,
+    /// 刷新令牌, 每次刷新后都会更换, 旧的刷新令牌立即失效
     key: String,
     expire: LocalTime,
+    /// 刷新令牌的过期时间
+    key_expire: LocalTime,
     user_id: u32,
+    /// 口令策略, 仅在登录时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password_policy: Option<PasswordPolicy>,
+    /// 首次绑定双因子认证时返回的恢复码, 仅显示1次
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recovery_codes: Option<Vec<String>>,
+}
+
+/// 需要进行双因子认证时, 登录接口返回的结果
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MfaPendingRes {
+    /// 需要进行双因子认证
+    mfa_required: bool,
+    /// 用户尚未绑定, 需要先调用mfaEnroll接口进行绑定
+    mfa_enroll: bool,
+    /// 待验证令牌, 用于mfaEnroll/mfaVerify接口
+    pending_token: String,
+    expire: LocalTime,
+}
+
+/// 口令已过期时, 登录接口返回的结果, 此时不签发令牌, 需要先调用changeExpiredPassword接口修改口令
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PasswordExpiredRes {
+    password_expired: bool,
+    /// 待验证令牌, 仅用于changeExpiredPassword接口
+    pending_token: String,
+    expire: LocalTime,
+    /// 口令策略, 用于提示用户设置符合要求的新口令
+    password_policy: PasswordPolicy,
+    /// 首次绑定双因子认证时返回的恢复码, 仅显示1次
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recovery_codes: Option<Vec<String>>,
 }
 
 /// 用户登录接口
@@ -28,8 +77,6 @@ pub async fn login(ctx: HttpContext) -> HttpResponse {
         password: String,
     }
 
-    type Res = LoginRes;
-
     let rid = ctx.id;
     let ip = ctx.remote_ip();
     let param: Req = ctx.parse_json()?;
@@ -41,29 +88,167 @@ pub async fn login(ctx: HttpContext) -> HttpResponse {
     let user = sys_user::user_login(&param.username, &param.password, &ip).await?;
     let user_id = user.user_id.unwrap();
 
-    // 生成登录返回结果
-    let token = sys_user::create_jwt_token(user_id)?;
-    let key = sys_user::create_refresh_token(&token, &AppConf::get().jwt_refresh);
-    let expire = {
-        let jwt_ttl = AppGlobal::get().jwt_ttl;
-        let exp = (utils::time::unix_timestamp() + jwt_ttl as u64) as i64;
-        LocalTime::from_unix_timestamp(exp)
+    // 已启用双因子认证或者角色要求必须启用时, 需要进行第二步验证
+    let enabled = match SysUserMfa::select_by_id(user_id).await? {
+        Some(v) => v.is_enabled(),
+        None => false,
     };
+    if enabled || mfa::is_required(user_id).await? {
+        let pending_token = mfa::create_pending(user_id, mfa::PendingStage::Mfa, !enabled).await;
+        let exp = (utils::time::unix_timestamp() + mfa::PENDING_TTL) as i64;
+        log_info!(
+            rid,
+            "用户[{}:{}]口令校验通过, 等待双因子认证",
+            param.username,
+            user_id
+        );
 
-    // 写入审计日志
-    let audit_data = serde_json::json!({
-        "userId": user_id,
-        "username": param.username.to_string(),
-        "ip": ip.to_string(),
-    });
-    audit::log_json(audit::LOGIN, user_id, &audit_data);
+        return Resp::ok(&MfaPendingRes {
+            mfa_required: true,
+            mfa_enroll: !enabled,
+            pending_token,
+            expire: LocalTime::from_unix_timestamp(exp),
+        });
+    }
 
-    // 发布用户登录消息
-    let chan = format!("{}:{}", AppConf::get().redis_pre, consts::CC_LOGIN);
-    mq::publish_async(chan, user_id.to_string());
-    log_info!(rid, "用户[{}:{}]登录成功", user.username.unwrap(), user.user_id.unwrap());
+    login_success(&ctx, &user, None).await
+}
+
+/// 登录第二步: 尚未绑定双因子认证的用户, 使用待验证令牌生成TOTP密钥
+pub async fn mfa_enroll(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        pending_token: String,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Res {
+        secret: String,
+        otpauth_uri: String,
+    }
+
+    let param: Req = ctx.parse_json()?;
+    let pending = match mfa::get_pending(&param.pending_token, mfa::PendingStage::Mfa).await {
+        Some(v) => v,
+        None => http_bail!("认证已失效, 请重新登录"),
+    };
+    fail_if!(!pending.enroll, "用户已绑定双因子认证");
+
+    let user = match SysUser::select_by_id(pending.user_id).await? {
+        Some(v) => v,
+        None => http_bail!("用户不存在"),
+    };
+    let (secret, otpauth_uri) =
+        mfa::enroll(pending.user_id, user.username.as_ref().unwrap()).await?;
+
+    Resp::ok(&Res {
+        secret,
+        otpauth_uri,
+    })
+}
+
+/// 登录第二步: 校验TOTP口令或恢复码, 成功后返回令牌
+pub async fn mfa_verify(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        pending_token: String,
+        code: String,
+    }
+
+    let ip = ctx.remote_ip();
+    let param: Req = ctx.parse_json()?;
+
+    fail_if!(param.code.is_empty(), "验证码不能为空");
+
+    let pending = match mfa::get_pending(&param.pending_token, mfa::PendingStage::Mfa).await {
+        Some(v) => v,
+        None => http_bail!("认证已失效, 请重新登录"),
+    };
+    let user_id = pending.user_id;
+    let user = match SysUser::select_by_id(user_id).await? {
+        Some(v) => v,
+        None => http_bail!("用户不存在"),
+    };
+    let username = user.username.as_deref().unwrap();
+    let user_mfa = match SysUserMfa::select_by_id(user_id).await? {
+        Some(v) => v,
+        None => http_bail!("尚未绑定双因子认证"),
+    };
+
+    // 验证失败次数过多时锁定, 该计数不受口令登录成功的影响
+    let lock = LoginLock::load().await?;
+    lock.check_mfa(username, &ip).await?;
+
+    // 首次绑定时需要确认绑定, 并生成恢复码
+    let (passed, recovery_codes) = if pending.enroll && !user_mfa.is_enabled() {
+        match mfa::confirm(user_mfa, &param.code).await? {
+            Some(codes) => {
+                audit::log(audit::ACCOUNT_MFA_ENABLE, user_id, String::new());
+                (true, Some(codes))
+            }
+            None => (false, None),
+        }
+    } else {
+        fail_if!(!user_mfa.is_enabled(), "尚未绑定双因子认证");
+        (mfa::verify(&user_mfa, &param.code).await?, None)
+    };
+
+    if !passed {
+        match lock.record_mfa_fail(username, &ip).await {
+            Some(0) => {
+                mfa::remove_pending(&param.pending_token).await;
+                http_bail!(
+                    "双因子认证已锁定, 请过{}后再进行登录",
+                    gen_time_desc(lock.lock_secs)
+                );
+            }
+            Some(remainder) => http_bail!("验证码错误, 您还可以尝试{}次", remainder),
+            None => http_bail!("验证码错误"),
+        }
+    }
+
+    login_lock::clear_mfa(username).await;
+    mfa::remove_pending(&param.pending_token).await;
 
-    Resp::ok(&Res { token, key, expire, user_id })
+    login_success(&ctx, &user, recovery_codes).await
+}
+
+/// 口令已过期时, 使用登录返回的待验证令牌修改口令, 成功后返回令牌
+pub async fn change_expired_password(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        pending_token: String,
+        new_password: String,
+    }
+
+    let param: Req = ctx.parse_json()?;
+
+    fail_if!(param.new_password.is_empty(), "新密码不能为空");
+
+    let stage = mfa::PendingStage::ChangePassword;
+    let pending = match mfa::get_pending(&param.pending_token, stage).await {
+        Some(v) => v,
+        None => http_bail!("认证已失效, 请重新登录"),
+    };
+    let user_id = pending.user_id;
+
+    // 按口令策略校验新口令后写入数据库
+    let policy = PasswordPolicy::load().await?;
+    let mut audit_data = policy.change(user_id, &param.new_password).await?;
+    mfa::remove_pending(&param.pending_token).await;
+    // 写入审计日志
+    audit_data.updated_time = None;
+    audit::log_json(audit::ACCOUNT_UPD_PASSWORD, user_id, &audit_data);
+
+    let user = match SysUser::select_by_id(user_id).await? {
+        Some(v) => v,
+        None => http_bail!("用户不存在"),
+    };
+    login_success(&ctx, &user, None).await
 }
 
 /// 退出登录
@@ -71,6 +256,12 @@ pub async fn logout(ctx: HttpContext) -> HttpResponse {
     if let Some(token) = Authentication::get_token_from_header(&ctx) {
         // 写入审计日志
         audit::log(audit::LOGOUT, ctx.user_id(), String::new());
+        // 删除会话登记, 同时注销对应的刷新令牌
+        if let Some(info) = session::remove(ctx.user_id(), &token).await {
+            if !info.family.is_empty() {
+                refresh_token::revoke_family(&info.family).await;
+            }
+        }
         // 发送用户登出通知消息
         let chan = format!("{}:{}", AppConf::get().redis_pre, consts::CC_LOGOUT);
         mq::publish_async(chan, token.to_string());
@@ -87,36 +278,64 @@ pub async fn refresh_token(ctx: HttpContext) -> HttpResponse {
         key: String,
     }
 
-    type Res = LoginRes;
+    let param: Req = ctx.parse_json()?;
+    fail_if!(param.key.is_empty(), "缺少刷新令牌");
 
-    let user_id: u32 = ctx.uid.parse().unwrap();
-    let token = match Authentication::get_token_from_header(&ctx) {
-        Some(s) => String::from(s),
-        None => http_bail!("缺少令牌"),
+    // 轮换刷新令牌, 无需提供访问令牌, 访问令牌过期后仍可刷新
+    let rotated = refresh_token::rotate(&param.key).await?;
+    let user_id = rotated.user_id;
+
+    // 校验用户状态
+    let user = SysUser::select_by_id(user_id).await?;
+    let user = match user {
+        Some(v) if v.disabled == Some(DisabledType::Normal as u8) => v,
+        _ => {
+            refresh_token::revoke_family(&rotated.family).await;
+            http_bail!("账号已被禁用");
+        }
     };
-    let param: Req = ctx.parse_json()?;
+    // 口令过期后不再续签, 用户需要重新登录并修改口令
+    let policy = PasswordPolicy::load().await?;
+    let expired = policy
+        .is_expired(user_id, user.created_time.as_ref())
+        .await?;
+    if expired {
+        refresh_token::revoke_family(&rotated.family).await;
+        http_bail!("口令已过期, 请重新登录");
+    }
 
-    // 校验key是否正确
-    let jwt_refresh_key = &AppConf::get().jwt_refresh;
-    let right_key = sys_user::create_refresh_token(&token, jwt_refresh_key);
-    fail_if!(param.key.as_str() != right_key.as_str(), "密钥错误");
+    // 旧的访问令牌由新令牌取代
+    session::remove(user_id, &rotated.access_token).await;
+    let chan = format!("{}:{}", AppConf::get().redis_pre, consts::CC_LOGOUT);
+    mq::publish_async(chan, rotated.access_token);
 
     // 生成返回结果
-    let token = sys_user::create_jwt_token(user_id)?;
-    let key = sys_user::create_refresh_token(&token, jwt_refresh_key);
-    let expire = (utils::time::unix_timestamp() + (AppGlobal::get().jwt_ttl as u64) * 60) as i64;
-    let expire = LocalTime::from_unix_timestamp(expire);
-    log_info!(ctx.id, "用户[{}]刷新令牌", user_id);
+    let mut res = issue_token(&ctx, &user, Some(rotated.family.clone())).await?;
+    log_info!(
+        ctx.id,
+        "用户[{}:{}]刷新令牌",
+        user.username.unwrap(),
+        user_id
+    );
 
     // 写入审计日志
-    audit::log(audit::REFRESH, ctx.user_id(), param.key.to_string());
+    audit::log(audit::REFRESH, user_id, rotated.family);
 
-    Resp::ok(&Res {
-        token,
-        key,
-        expire,
-        user_id,
-    })
+    res.password_policy = None;
+    Resp::ok(&res)
+}
+
+/// 获取令牌签名公钥集合(JWKS格式), 供其它服务离线校验令牌
+pub async fn jwks(_ctx: HttpContext) -> HttpResponse {
+    let body = match jwt_keys::get() {
+        Some(keys) => keys.jwks(),
+        None => serde_json::json!({ "keys": [] }),
+    };
+
+    Ok(hyper::Response::builder()
+        .header(httpserver::CONTENT_TYPE, httpserver::APPLICATION_JSON)
+        .header("Cache-Control", "public, max-age=3600")
+        .body(httpserver::Body::from(serde_json::to_vec(&body)?))?)
 }
 
 /// 鉴权接口, 提供给其它微服务调用本接口进行鉴权操作
@@ -127,12 +346,14 @@ pub async fn authenticate(ctx: HttpContext) -> HttpResponse {
     struct Req {
         path: Option<String>, // path/paths两个只需提供1个
         paths: Option<Vec<String>>,
+        method: Option<String>, // 请求方法, 为空时只匹配未限定请求方法的接口规则
     }
 
     #[derive(Serialize, Default)]
     #[serde(rename_all = "camelCase")]
     struct Res {
         user_id: Option<u32>,
+        tenant_id: Option<u32>, // 用户所属的租户id, 用户未登录时为空
         status: Option<u32>,
         statuses: Option<Vec<u32>>,
     }
@@ -148,13 +369,16 @@ pub async fn authenticate(ctx: HttpContext) -> HttpResponse {
     // user_id和token都为空的情况下, 表示用户未登录
     let uid = ctx.user_id();
     let user_id = if_else!(uid != 0, Some(uid), None);
+    let tenant_id = user_id.map(|_| tenant::from_ctx(&ctx));
     let uid_str = &ctx.uid;
+    let method = param.method.as_deref().unwrap_or_default();
 
     if let Some(path) = &param.path {
-        let status = auth_status(uid, auth.auth(ctx.id, uid_str, path).await);
+        let status = auth_status(uid, auth.auth(ctx.id, uid_str, method, path).await);
 
         return Resp::ok(&Res {
             user_id,
+            tenant_id,
             status: Some(status),
             statuses: None,
         });
@@ -163,12 +387,13 @@ pub async fn authenticate(ctx: HttpContext) -> HttpResponse {
     let paths = param.paths.unwrap();
     let mut statuses = Vec::with_capacity(paths.len());
     for path in paths.iter() {
-        let status = auth_status(uid, auth.auth(ctx.id, uid_str, path).await);
+        let status = auth_status(uid, auth.auth(ctx.id, uid_str, method, path).await);
         statuses.push(status);
     }
 
     Resp::ok(&Res {
         user_id,
+        tenant_id,
         status: None,
         statuses: Some(statuses),
     })
@@ -184,3 +409,97 @@ fn auth_status(user_id: u32, auth_result: bool) -> u32 {
         403
     }
 }
+
+/// 登录验证通过, 生成令牌, 写入审计日志并发布用户登录消息
+///
+/// 口令已过期时不签发令牌, 只返回用于修改口令的待验证令牌
+async fn login_success(
+    ctx: &HttpContext,
+    user: &SysUser,
+    recovery_codes: Option<Vec<String>>,
+) -> HttpResponse {
+    let rid = ctx.id;
+    let ip = ctx.remote_ip().to_string();
+    let user_id = user.user_id.unwrap();
+    let username = user.username.as_ref().unwrap();
+
+    // 检查口令是否已超过有效期
+    let policy = PasswordPolicy::load().await?;
+    let expired = policy
+        .is_expired(user_id, user.created_time.as_ref())
+        .await?;
+    if expired {
+        let stage = mfa::PendingStage::ChangePassword;
+        let pending_token = mfa::create_pending(user_id, stage, false).await;
+        let exp = (utils::time::unix_timestamp() + mfa::PENDING_TTL) as i64;
+        log_info!(
+            rid,
+            "用户[{}:{}]口令已过期, 等待修改口令",
+            username,
+            user_id
+        );
+
+        return Resp::ok(&PasswordExpiredRes {
+            password_expired: true,
+            pending_token,
+            expire: LocalTime::from_unix_timestamp(exp),
+            password_policy: policy,
+            recovery_codes,
+        });
+    }
+
+    // 生成登录返回结果
+    let mut res = issue_token(ctx, user, None).await?;
+    res.password_policy = Some(policy);
+    res.recovery_codes = recovery_codes;
+
+    // 写入审计日志
+    let audit_data = serde_json::json!({
+        "userId": user_id,
+        "username": username,
+        "ip": ip,
+    });
+    audit::log_json(audit::LOGIN, user_id, &audit_data);
+
+    // 发布用户登录消息
+    let chan = format!("{}:{}", AppConf::get().redis_pre, consts::CC_LOGIN);
+    mq::publish_async(chan, user_id.to_string());
+    log_info!(rid, "用户[{}:{}]登录成功", username, user_id);
+
+    Resp::ok(&res)
+}
+
+/// 签发访问令牌及刷新令牌, 并登记会话
+///
+/// Arguments:
+///
+/// * `ctx`: http请求上下文
+/// * `user`: 用户记录
+/// * `family`: 刷新令牌族, 为None时创建新的令牌族
+///
+async fn issue_token(ctx: &HttpContext, user: &SysUser, family: Option<String>) -> Result<LoginRes> {
+    let ag = AppGlobal::get();
+    let now = utils::time::unix_timestamp();
+    let user_id = user.user_id.unwrap();
+    let tenant_id = user.tenant_id.unwrap_or(tenant::PLATFORM_TENANT_ID);
+
+    let token = sys_user::create_jwt_token(user_id, tenant_id)?;
+    let (key, family) = refresh_token::issue(user_id, family, &token).await;
+    let expire = LocalTime::from_unix_timestamp((now + ag.jwt_ttl as u64) as i64);
+    let key_expire = LocalTime::from_unix_timestamp((now + ag.refresh_ttl as u64) as i64);
+
+    // 登记会话
+    let ip = ctx.remote_ip().to_string();
+    let user_agent = ctx.header(USER_AGENT).unwrap_or_default();
+    session::create(user_id, &token, &family, &ip, &user_agent).await;
+
+    Ok(LoginRes {
+        token,
+        key,
+        expire,
+        key_expire,
+        user_id,
+        password_policy: None,
+        recovery_codes: None,
+    })
+}
@@ -4,7 +4,11 @@ use crate::{
         sys_menu::{SysMenu, SysMenuExt, SysMenuVo, TOP_MENU_CODE},
         PageData, PageQuery,
     },
-    utils::audit,
+    services::tenant,
+    utils::{
+        audit,
+        code_tree::{self, parent_code, CODE_LEN},
+    },
 };
 use anyhow_ext::{anyhow, Result};
 use httpserver::{check_required, http_bail, HttpContext, HttpResponse, Resp};
@@ -21,7 +25,7 @@ pub async fn list(ctx: HttpContext) -> HttpResponse {
         if menu_code == TOP_MENU_CODE {
             menu_code.clear();
         }
-        menu_code.push_str("__");
+        menu_code.push_str(&"_".repeat(CODE_LEN));
     }
     let pg = param.page_info();
     let page_data = SysMenu::select_page(param.inner, pg).await?;
@@ -37,6 +41,7 @@ pub async fn get(ctx: HttpContext) -> HttpResponse {
     let rec = SysMenu::select_by_id(param.id)
         .await?
         .ok_or(anyhow!(super::REC_NOT_EXISTS))?;
+    tenant::check_owner(rec.tenant_id)?;
 
     Resp::ok(&rec)
 }
@@ -62,29 +67,8 @@ pub async fn insert(ctx: HttpContext) -> HttpResponse {
     param_base.menu_id = None;
     param_base.updated_time = Some(LocalTime::now());
 
-    let pmc = param_ext.parent_menu_code.as_ref().unwrap();
-    if param_base.menu_code.is_none() || !param_base.menu_code.as_ref().unwrap().starts_with(pmc) {
-        let max_code = SysMenu::select_max_code(pmc).await?;
-        let next_code = match max_code {
-            Some(v) => {
-                let s = &v[v.len() - 2..];
-                log::debug!("s = {s}");
-                let n = match s.parse::<u32>() {
-                    Ok(n) => n + 1,
-                    Err(e) => {
-                        log::error!("parse {s} to u32 error: {e:?}");
-                        return Resp::internal_server_error();
-                    }
-                };
-                if n >= 100 {
-                    return Resp::fail("子菜单项数量不能超过100");
-                }
-                format!("{n:02}")
-            }
-            None => "01".to_owned(),
-        };
-        param_base.menu_code = Some(format!("{pmc}{next_code}"));
-    }
+    let pmc = top_to_empty(param_ext.parent_menu_code.as_ref().unwrap());
+    param_base.menu_code = Some(gen_menu_code(pmc).await?);
 
     let id = param.inner.inner.clone().insert_with_notify().await?.1;
 
@@ -120,31 +104,20 @@ pub async fn update(ctx: HttpContext) -> HttpResponse {
         Some(v) => v,
         None => http_bail!("菜单不存在"),
     };
+    tenant::check_owner(audit_orig.tenant_id)?;
 
     param_base.updated_time = Some(LocalTime::now());
 
-    let pmc = param_ext.parent_menu_code.as_ref().unwrap();
-    if param_base.menu_code.is_none() || !param_base.menu_code.as_ref().unwrap().starts_with(pmc) {
-        let max_code = SysMenu::select_max_code(pmc).await?;
-        let next_code = match max_code {
-            Some(v) => {
-                let s = &v[v.len() - 2..];
-                log::debug!("s = {s}");
-                let n = match s.parse::<u32>() {
-                    Ok(n) => n + 1,
-                    Err(e) => {
-                        log::error!("parse {s} to u32 error: {e:?}");
-                        return Resp::internal_server_error();
-                    }
-                };
-                if n >= 100 {
-                    return Resp::fail("子菜单项数量不能超过100");
-                }
-                format!("{n:02}")
-            }
-            None => "01".to_owned(),
-        };
-        param_base.menu_code = Some(format!("{pmc}{next_code}"));
+    // 上级菜单变更时, 重新生成菜单代码, 所有下级菜单随之移动
+    let orig_code = audit_orig.menu_code.clone().unwrap_or_default();
+    let pmc = top_to_empty(param_ext.parent_menu_code.as_ref().unwrap());
+    if parent_code(&orig_code) != pmc {
+        if pmc.starts_with(&orig_code) {
+            http_bail!("上级菜单不能是菜单本身或其下级菜单");
+        }
+        param_base.menu_code = Some(gen_menu_code(pmc).await?);
+    } else {
+        param_base.menu_code = Some(orig_code.clone());
     }
 
     let menu_id = param_base.menu_id;
@@ -154,7 +127,11 @@ pub async fn update(ctx: HttpContext) -> HttpResponse {
         &[SysMenu::MENU_ID],
         &[SysMenu::UPDATED_TIME],
     );
+    let new_code = param_base.menu_code.clone().unwrap();
     SysMenu::update_with_notify(param.inner.inner).await?;
+    if new_code != orig_code {
+        SysMenu::update_descendant_code(&orig_code, &new_code).await?;
+    }
     audit::log_text(audit::MENU_UPD, ctx.user_id(), audit_diff);
 
     Resp::ok(&SysMenu {
@@ -172,6 +149,12 @@ pub async fn del(ctx: HttpContext) -> HttpResponse {
         Some(menu) => menu,
         None => http_bail!("记录不存在"),
     };
+    tenant::check_owner(audit_data.tenant_id)?;
+    if let Some(code) = &audit_data.menu_code {
+        if SysMenu::select_descendant_count(code).await? > 0 {
+            http_bail!("请先删除该菜单的下级菜单");
+        }
+    }
 
     SysMenu::delete_with_notify(param.id).await?;
     audit::log_json(audit::MENU_DEL, ctx.user_id(), &audit_data);
@@ -179,11 +162,11 @@ pub async fn del(ctx: HttpContext) -> HttpResponse {
     Resp::ok_with_empty()
 }
 
-/// 返回权限的树形结构
+/// 返回可作为上级菜单的目录菜单列表
 pub async fn top_level(_ctx: HttpContext) -> HttpResponse {
     type Res = PageData<SysMenu>;
 
-    let list = SysMenu::select_top_level(true).await?;
+    let list = SysMenu::select_parents().await?;
 
     Resp::ok(&Res::with_list(list))
 }
@@ -204,10 +187,8 @@ pub async fn tree(ctx: HttpContext) -> HttpResponse {
             Some(s) => s,
             None => return Resp::fail("菜单项有编码为空的记录"),
         };
-        let parent_code = &menu_code[0..menu_code.len() - 2];
-
         menu_map
-            .entry(parent_code)
+            .entry(parent_code(menu_code))
             .and_modify(|v| v.push(item))
             .or_insert_with(|| vec![item]);
     }
@@ -241,7 +222,20 @@ pub async fn rearrange(ctx: HttpContext) -> HttpResponse {
     let param: Req = ctx.parse_json()?;
 
     let mut list = Vec::new();
-    tree_to_list(&mut list, "", &param.menus);
+    code_tree::tree_to_list(
+        &mut list,
+        "",
+        &param.menus,
+        "子菜单项",
+        &|v: &SysMenuVo| v.menus.as_deref(),
+        &|v, menu_code| {
+            Ok(SysMenu {
+                menu_id: v.inner.inner.menu_id,
+                menu_code: Some(menu_code),
+                ..Default::default()
+            })
+        },
+    )?;
 
     SysMenu::batch_update_rearrange(param.client_type, &list).await?;
     audit::log_json(audit::MENU_REARRANGE, ctx.user_id(), &param);
@@ -277,18 +271,17 @@ fn build_tree<'a>(
     Ok(())
 }
 
-/// 递归调用，将树形结构转换为列表结构
-fn tree_to_list(list: &mut Vec<SysMenu>, parent_menu_code: &str, tree_menus: &[SysMenuVo]) {
-    for (i, item) in tree_menus.iter().enumerate() {
-        let menu_code = format!("{parent_menu_code}{:02}", i + 1);
-        list.push(SysMenu {
-            menu_id: item.inner.inner.menu_id,
-            menu_code: Some(menu_code.to_string()),
-            ..Default::default()
-        });
-
-        if let Some(menus) = &item.menus {
-            tree_to_list(list, &menu_code, menus);
-        }
+/// 虚拟的顶级菜单代码转换为空字符串
+fn top_to_empty(menu_code: &str) -> &str {
+    if menu_code == TOP_MENU_CODE {
+        ""
+    } else {
+        menu_code
     }
 }
+
+/// 生成指定上级菜单下新的子菜单代码
+async fn gen_menu_code(parent_menu_code: &str) -> Result<String> {
+    let max_code = SysMenu::select_max_code(parent_menu_code).await?;
+    code_tree::next_code(parent_menu_code, max_code.as_deref(), "子菜单项")
+}
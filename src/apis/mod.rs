@@ -2,11 +2,16 @@ pub mod account;
 pub mod api;
 pub mod config;
 pub mod debug;
+pub mod dept;
 pub mod dict;
+pub mod health;
+pub mod log;
 pub mod login;
 pub mod menu;
+pub mod openapi;
 pub mod permission;
 pub mod role;
+pub mod tenant;
 pub mod tools;
 pub mod user;
 
@@ -0,0 +1,110 @@
+//! OpenAPI 3 接口文档
+use httpserver::{ApiDoc, HttpContext, HttpResponse, HttpServer};
+use serde_json::{json, Value};
+
+use crate::utils::staticmut::StaticMut;
+
+/// 启动时生成的接口文档, 注册的接口在服务运行期间不会改变
+static mut OPENAPI_DOC: StaticMut<String> = StaticMut::new();
+
+/// 生成接口文档, 必须在所有接口注册完成后调用
+///
+/// Arguments:
+///
+/// * `srv`: http服务
+///
+pub fn init(srv: &HttpServer) {
+    let mut doc = srv.openapi(crate::APP_NAME, crate::APP_VER);
+
+    // 除匿名接口外, 其它接口都需要在请求头中携带Authorization: Bearer <token>
+    doc["components"]["securitySchemes"] = json!({
+        "bearerAuth": { "type": "http", "scheme": "bearer", "bearerFormat": "JWT" }
+    });
+    doc["security"] = json!([{ "bearerAuth": [] }]);
+
+    let doc = serde_json::to_string(&doc).expect("生成接口文档失败");
+    unsafe { OPENAPI_DOC.init(doc) }
+}
+
+/// 获取OpenAPI 3格式的接口文档
+pub async fn doc(_ctx: HttpContext) -> HttpResponse {
+    let doc = unsafe { OPENAPI_DOC.get() };
+
+    Ok(hyper::Response::builder()
+        .header(httpserver::CONTENT_TYPE, "application/json;charset=UTF-8")
+        .body(httpserver::Body::from(doc.clone()))?)
+}
+
+/// 分页查询接口的文档
+///
+/// Arguments:
+///
+/// * `summary`: 接口说明
+/// * `schema`: 查询条件及返回记录的JSON Schema
+///
+pub fn list(summary: &str, schema: Value) -> ApiDoc {
+    ApiDoc::new(summary)
+        .request(page_query_schema(schema.clone()))
+        .response(page_data_schema(schema))
+}
+
+/// 获取单条记录接口的文档
+pub fn get(summary: &str, schema: Value) -> ApiDoc {
+    ApiDoc::new(summary)
+        .request(get_req_schema())
+        .response(schema)
+}
+
+/// 新增或更新单条记录接口的文档, 返回的记录只包含主键
+pub fn save(summary: &str, schema: Value) -> ApiDoc {
+    ApiDoc::new(summary)
+        .request(schema.clone())
+        .response(schema)
+}
+
+/// 删除单条记录接口的文档
+pub fn del(summary: &str) -> ApiDoc {
+    ApiDoc::new(summary).request(get_req_schema())
+}
+
+/// 分页查询参数(PageQuery)的JSON Schema, 分页参数与查询条件位于同一层级
+fn page_query_schema(mut inner: Value) -> Value {
+    let page_props = json!({
+        "i": { "type": "integer", "format": "int32", "description": "页码, 从1开始" },
+        "p": { "type": "integer", "format": "int32", "description": "每页记录数" },
+        "a": {
+            "type": "integer",
+            "format": "int32",
+            "description": "记录总数, 首次查询时不传或传负数由服务端统计, 翻页时回传以避免重复统计",
+        },
+    });
+    if let (Some(props), Value::Object(page_props)) =
+        (inner["properties"].as_object_mut(), page_props)
+    {
+        props.extend(page_props);
+    }
+    inner["required"] = json!(["i", "p"]);
+    inner
+}
+
+/// 分页查询结果(PageData)的JSON Schema
+fn page_data_schema(item: Value) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "total": { "type": "integer", "format": "int32", "description": "记录总数" },
+            "list": { "type": "array", "items": item },
+        },
+    })
+}
+
+/// 按主键查询请求参数(GetReq)的JSON Schema
+fn get_req_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["id"],
+        "properties": {
+            "id": { "type": "integer", "format": "int32", "description": "记录id" },
+        },
+    })
+}
@@ -1,6 +1,10 @@
 //! 角色表接口
 use crate::{
-    entities::{sys_role::SysRole, PageData, PageQuery},
+    entities::{
+        sys_role::{DataScopeType, SysRole},
+        PageData, PageQuery,
+    },
+    services::tenant,
     utils::audit,
 };
 use anyhow_ext::anyhow;
@@ -26,6 +30,7 @@ pub async fn get(ctx: HttpContext) -> HttpResponse {
     let rec = SysRole::select_by_id(param.id)
         .await?
         .ok_or(anyhow!(super::REC_NOT_EXISTS))?;
+    tenant::check_owner(rec.tenant_id)?;
 
     Resp::ok(&rec)
 }
@@ -46,6 +51,11 @@ pub async fn insert(ctx: HttpContext) -> HttpResponse {
     if param.permissions.is_none() {
         param.permissions = Some("".to_owned());
     }
+    if let Some(ds) = param.data_scope {
+        if DataScopeType::from_u8(ds).is_none() {
+            http_bail!("数据权限范围[{}]不正确", ds);
+        }
+    }
     // 写入数据库
     let id = param.clone().insert_with_notify().await?.1;
 
@@ -71,6 +81,7 @@ pub async fn update(ctx: HttpContext) -> HttpResponse {
         Some(v) => v,
         None => http_bail!("记录不存在"),
     };
+    tenant::check_owner(orig.tenant_id)?;
 
     if param.role_type.is_none() {
         param.role_type = Some(String::with_capacity(0))
@@ -80,6 +91,11 @@ pub async fn update(ctx: HttpContext) -> HttpResponse {
     if param.permissions.is_none() {
         param.permissions = Some("".to_owned());
     }
+    if let Some(ds) = param.data_scope {
+        if DataScopeType::from_u8(ds).is_none() {
+            http_bail!("数据权限范围[{}]不正确", ds);
+        }
+    }
 
     let audit_data = audit::diff(&param, &orig, &[SysRole::ROLE_ID], &[SysRole::UPDATED_TIME]);
 
@@ -103,6 +119,7 @@ pub async fn del(ctx: HttpContext) -> HttpResponse {
         Some(v) => v,
         None => http_bail!("记录不存在"),
     };
+    tenant::check_owner(audit_data.tenant_id)?;
     SysRole::delete_with_notify(param.id).await?;
     audit::log_json(audit::ROLE_DEL, ctx.user_id(), &audit_data);
 
@@ -0,0 +1,138 @@
+//! 租户表接口, 只允许平台租户的用户访问
+use crate::{
+    entities::{sys_tenant::SysTenant, PageQuery},
+    services::tenant,
+    utils::audit,
+};
+use anyhow_ext::anyhow;
+use httpserver::{check_required, http_bail, HttpContext, HttpResponse, Resp};
+use localtime::LocalTime;
+
+/// 记录列表
+pub async fn list(ctx: HttpContext) -> HttpResponse {
+    type Req = PageQuery<SysTenant>;
+
+    tenant::check_platform()?;
+    let param: Req = ctx.parse_json()?;
+    let pg = param.page_info();
+    let page_data = SysTenant::select_page(param.inner, pg).await?;
+
+    Resp::ok(&page_data)
+}
+
+/// 获取单条记录
+pub async fn get(ctx: HttpContext) -> HttpResponse {
+    type Req = super::GetReq;
+
+    tenant::check_platform()?;
+    let param: Req = ctx.parse_json()?;
+    let rec = SysTenant::select_by_id(param.id)
+        .await?
+        .ok_or(anyhow!(super::REC_NOT_EXISTS))?;
+
+    Resp::ok(&rec)
+}
+
+/// 添加单条记录
+pub async fn insert(ctx: HttpContext) -> HttpResponse {
+    type Req = SysTenant;
+
+    tenant::check_platform()?;
+    let mut param: Req = ctx.parse_json()?;
+    check_required!(param, tenant_name);
+
+    param.tenant_id = None;
+    if param.disabled.is_none() {
+        param.disabled = Some(0);
+    }
+    param.updated_time = Some(LocalTime::now());
+    param.created_time = Some(LocalTime::now());
+
+    let mut audit_data = param.clone();
+
+    // 写入数据库
+    let id = param.insert_with_notify().await?.1;
+
+    // 写入审计日志
+    audit_data.tenant_id = Some(id);
+    audit_data.updated_time = None;
+    audit_data.created_time = None;
+    audit::log_json(audit::TENANT_ADD, ctx.user_id(), &audit_data);
+
+    Resp::ok(&SysTenant {
+        tenant_id: Some(id),
+        ..Default::default()
+    })
+}
+
+/// 更新单条记录
+pub async fn update(ctx: HttpContext) -> HttpResponse {
+    type Req = SysTenant;
+
+    tenant::check_platform()?;
+    let mut param: Req = ctx.parse_json()?;
+    check_required!(param, tenant_id);
+
+    let orig = match SysTenant::select_by_id(param.tenant_id.unwrap()).await? {
+        Some(v) => v,
+        None => http_bail!("记录不存在"),
+    };
+
+    // 禁止更新的字段, 状态通过disable接口修改
+    param.disabled = None;
+    param.created_time = None;
+    param.updated_time = Some(LocalTime::now());
+
+    let audit_data = audit::diff(
+        &param,
+        &orig,
+        &[SysTenant::TENANT_ID],
+        &[SysTenant::CREATED_TIME, SysTenant::UPDATED_TIME],
+    );
+
+    let tenant_id = param.tenant_id;
+    param.update_with_notify().await?;
+
+    audit::log_text(audit::TENANT_UPD, ctx.user_id(), audit_data);
+
+    Resp::ok(&SysTenant {
+        tenant_id,
+        ..Default::default()
+    })
+}
+
+/// 启用或禁用租户, 禁用后该租户的所有请求都将被拒绝
+pub async fn disable(ctx: HttpContext) -> HttpResponse {
+    type Req = SysTenant;
+
+    tenant::check_platform()?;
+    let param: Req = ctx.parse_json()?;
+    check_required!(param, tenant_id, disabled);
+
+    if param.tenant_id == Some(tenant::PLATFORM_TENANT_ID) {
+        http_bail!("不能修改平台租户的状态");
+    }
+    if SysTenant::select_by_id(param.tenant_id.unwrap())
+        .await?
+        .is_none()
+    {
+        http_bail!("记录不存在");
+    }
+
+    let rec = SysTenant {
+        tenant_id: param.tenant_id,
+        disabled: param.disabled,
+        updated_time: Some(LocalTime::now()),
+        ..Default::default()
+    };
+    let mut audit_data = rec.clone();
+
+    // 写入数据库
+    rec.update_with_notify().await?;
+
+    // 写入审计日志
+    audit_data.updated_time = None;
+    audit::log_json(audit::TENANT_UPD_STATUS, ctx.user_id(), &audit_data);
+
+    Resp::ok_with_empty()
+}
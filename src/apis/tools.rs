@@ -1,5 +1,5 @@
 //! 实用工具接口
-use crate::{utils::md5_crypt, AppGlobal};
+use crate::{auth::Authentication, utils::password, AppConf, AppGlobal};
 #[cfg(feature = "fast_qr")]
 use fast_qr::{
     convert::{image::ImageBuilder, Builder, Shape},
@@ -60,6 +60,27 @@ pub async fn status(ctx: HttpContext) -> HttpResponse {
     })
 }
 
+/// Prometheus监控指标, 以文本格式输出服务运行指标, 供Prometheus定时抓取
+///
+/// 本接口不经过接口权限校验, 抓取时需要在请求头中携带配置项metrics-token指定的令牌,
+/// 格式为 `Authorization: Bearer <token>`, 未配置令牌时拒绝所有访问
+pub async fn metrics(ctx: HttpContext) -> HttpResponse {
+    use crate::services::metrics;
+    use hyper::StatusCode;
+
+    let token = &AppConf::get().metrics_token;
+    let authorized = !token.is_empty()
+        && Authentication::get_token_from_header(&ctx)
+            .is_some_and(|t| token_eq(t.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return Resp::fail_with_status(StatusCode::UNAUTHORIZED, 401, "Unauthorized");
+    }
+
+    Ok(hyper::Response::builder()
+        .header(httpserver::CONTENT_TYPE, metrics::CONTENT_TYPE)
+        .body(httpserver::Body::from(metrics::render()))?)
+}
+
 /// 获取客户端ip
 pub async fn ip(ctx: HttpContext) -> HttpResponse {
     #[derive(Serialize)]
@@ -76,8 +97,6 @@ pub async fn ip(ctx: HttpContext) -> HttpResponse {
 /// 生成二维码
 #[cfg(feature = "fast_qr")]
 pub async fn qrcode(ctx: HttpContext) -> HttpResponse {
-    use http_body_util::{BodyExt, Full};
-
     #[derive(Deserialize, Serialize)]
     struct Req {
         text: String,
@@ -102,7 +121,7 @@ pub async fn qrcode(ctx: HttpContext) -> HttpResponse {
 
     Ok(hyper::Response::builder()
         .header(httpserver::CONTENT_TYPE, "image/png")
-        .body(Full::from(img).boxed())?)
+        .body(httpserver::Body::from(img))?)
 }
 
 #[cfg(not(feature = "fast_qr"))]
@@ -120,9 +139,14 @@ pub async fn create_password(ctx: HttpContext) -> HttpResponse {
     type Res = Req;
 
     let param: Req = ctx.parse_json()?;
-    let digest = md5_crypt::encrypt(&param.pass)?;
+    let digest = password::encrypt_async(&param.pass).await?;
 
     Resp::ok(&Res {
         pass: digest,
     })
 }
+
+/// 比较令牌是否相同, 比较耗时与相同前缀的长度无关, 避免通过响应时间猜测令牌
+fn token_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -1,26 +1,61 @@
 //! 用户表接口
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
 
 use crate::{
     entities::{
         sys_config::SysConfig,
-        sys_user::{DisabledType, SysUser},
+        sys_dept::SysDept,
+        sys_password_history::SysPasswordHistory,
+        sys_role::SysRole,
+        sys_user::{check_account_type, AccountType, DisabledType, SysUser, SysUserQuery},
+        sys_user_mfa::SysUserMfa,
+        sys_user_role::SysUserRole,
+        sys_user_state::SysUserState,
         PageQuery,
     },
-    utils::{audit, consts, md5_crypt},
+    services::{
+        data_scope::DataScope,
+        export::{self, Encoder, ExportFile},
+        login_lock,
+        password_policy::PasswordPolicy,
+        session, tenant,
+    },
+    utils::{
+        audit, consts, password,
+        sheet::{self, SheetFormat, SheetWriter},
+    },
 };
 use anyhow_ext::{Context, Result};
-use httpserver::{http_bail, HttpContext, HttpResponse, Resp};
+use httpserver::{fail_if, http_bail, HttpContext, HttpResponse, Resp};
 use localtime::LocalTime;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+/// 单次导入允许的最大记录数
+const IMPORT_MAX_ROWS: usize = 5000;
+/// 导出文件的标题行
+const EXPORT_HEADER: [&str; 9] = [
+    "用户id",
+    "用户名",
+    "昵称",
+    "手机号",
+    "电子邮件",
+    "角色id",
+    "部门id",
+    "禁用标志",
+    "创建时间",
+];
 
 /// 记录列表
 pub async fn list(ctx: HttpContext) -> HttpResponse {
-    type Req = PageQuery<SysUser>;
+    type Req = PageQuery<SysUserQuery>;
 
     let param: Req = ctx.parse_json()?;
     let pg = param.page_info();
-    let page_data = SysUser::select_page(param.inner, pg).await?;
+    let scope = DataScope::load(ctx.user_id()).await?;
+    let page_data = SysUser::select_page(param.inner, pg, &scope).await?;
 
     Resp::ok(&page_data)
 }
@@ -34,6 +69,7 @@ pub async fn get(ctx: HttpContext) -> HttpResponse {
 
     match rec {
         Some(mut rec) => {
+            check_scope(&ctx, &rec).await?;
             rec.password = None;
             Resp::ok(&rec)
         }
@@ -48,9 +84,18 @@ pub async fn insert(ctx: HttpContext) -> HttpResponse {
     let mut param: Req = ctx.parse_json()?;
 
     httpserver::check_required!(param, role_id, username);
+    check_disabled(param.disabled)?;
+    check_role(param.role_id.unwrap()).await?;
 
     param.user_id = None;
 
+    // 只能在数据权限范围内的部门中添加用户
+    check_dept(param.dept_id).await?;
+    let scope = DataScope::load(ctx.user_id()).await?;
+    if !scope.contains(param.dept_id, None) {
+        http_bail!("无权在该部门中添加用户");
+    }
+
     // 可选字段设置默认值
     if param.disabled.is_none() {
         param.disabled = Some(DisabledType::Normal as u8);
@@ -59,13 +104,19 @@ pub async fn insert(ctx: HttpContext) -> HttpResponse {
         param.nickname = param.username.clone();
     }
 
-    // 如果用户未指定密码，则使用缺省密码
-    let password = match &param.password {
-        Some(pwd) => Cow::Borrowed(pwd),
+    // 如果用户未指定密码，则使用缺省密码, 用户登录后需要立即修改, 指定密码时需要符合口令策略
+    let policy = PasswordPolicy::load().await?;
+    let use_default = param.password.is_none();
+    let plain_pwd = match &param.password {
+        Some(pwd) => {
+            policy.check_strength(pwd)?;
+            Cow::Borrowed(pwd)
+        }
         None => Cow::Owned(get_default_password().await?),
     };
     // 对口令进行加密
-    param.password = Some(md5_crypt::encrypt(&password)?);
+    let enc_pwd = password::encrypt_async(&plain_pwd).await?;
+    param.password = Some(enc_pwd.clone());
 
     // 必填字段缺省值
     param.updated_time = Some(LocalTime::now());
@@ -75,6 +126,7 @@ pub async fn insert(ctx: HttpContext) -> HttpResponse {
 
     // 写入数据库
     let id = param.insert_with_notify().await?.1;
+    policy.save_history(id, enc_pwd, use_default).await?;
 
     // 写入审计日志
     audit_data.user_id = Some(id);
@@ -102,6 +154,21 @@ pub async fn update(ctx: HttpContext) -> HttpResponse {
         Some(v) => v,
         None => http_bail!("记录不存在"),
     };
+    check_scope(&ctx, &orig).await?;
+    check_not_deleted(&orig)?;
+    check_disabled(param.disabled)?;
+    if let Some(role_id) = param.role_id {
+        if Some(role_id) != orig.role_id {
+            check_role(role_id).await?;
+        }
+    }
+    if param.dept_id.is_some() && param.dept_id != orig.dept_id {
+        check_dept(param.dept_id).await?;
+        let scope = DataScope::load(ctx.user_id()).await?;
+        if !scope.contains(param.dept_id, None) {
+            http_bail!("无权将用户移动到该部门");
+        }
+    }
 
     // 禁止更新的字段
     param.created_time = None;
@@ -109,10 +176,15 @@ pub async fn update(ctx: HttpContext) -> HttpResponse {
     // 自动更新字段
     param.updated_time = Some(LocalTime::now());
 
-    // 动态计算的字段
+    // 动态计算的字段, 修改口令时需要符合口令策略
+    let mut new_pwd = None;
     if let Some(pw) = &param.password {
         if !pw.is_empty() {
-            param.password = Some(md5_crypt::encrypt(pw)?);
+            let policy = PasswordPolicy::load().await?;
+            policy.check(orig.user_id.unwrap(), pw).await?;
+            let enc_pwd = password::encrypt_async(pw).await?;
+            param.password = Some(enc_pwd.clone());
+            new_pwd = Some((policy, enc_pwd));
         }
     }
 
@@ -127,6 +199,11 @@ pub async fn update(ctx: HttpContext) -> HttpResponse {
 
     // 写入数据库
     param.update_with_notify().await?;
+    if let Some((policy, enc_pwd)) = new_pwd {
+        policy
+            .save_history(orig.user_id.unwrap(), enc_pwd, false)
+            .await?;
+    }
 
     // 写入审计日志
     audit::log_text(audit::USER_UPD, ctx.user_id(), audit_data);
@@ -137,29 +214,73 @@ pub async fn update(ctx: HttpContext) -> HttpResponse {
     })
 }
 
-/// 删除记录
+/// 删除记录, 只将用户标记为已删除, 可以通过restore恢复, 通过purge彻底删除
 pub async fn del(ctx: HttpContext) -> HttpResponse {
     type Req = super::GetReq;
 
     let param: Req = ctx.parse_json()?;
-    let audit_data = match SysUser::select_by_id(param.id).await.dot()? {
+    let mut audit_data = match SysUser::select_by_id(param.id).await.dot()? {
         Some(v) => v,
         None => http_bail!("用户不存在"),
     };
+    check_scope(&ctx, &audit_data).await?;
+    check_not_deleted(&audit_data)?;
 
-    SysUser::delete_with_notify(param.id).await?;
+    SysUser::soft_delete_with_notify(param.id).await?;
+    session::revoke(param.id, |_| true).await;
     // 写入审计日志
+    audit_data.password = None;
     audit::log_json(audit::USER_DEL, ctx.user_id(), &audit_data);
 
     Resp::ok_with_empty()
 }
 
+/// 恢复已删除的用户
+pub async fn restore(ctx: HttpContext) -> HttpResponse {
+    type Req = super::GetReq;
+
+    let param: Req = ctx.parse_json()?;
+    let user = load_deleted_user(&ctx, param.id).await?;
+
+    SysUser::restore_with_notify(param.id).await?;
+    // 写入审计日志
+    let audit_data = serde_json::json!({
+        "userId": param.id,
+        "username": user.username,
+    });
+    audit::log_json(audit::USER_RESTORE, ctx.user_id(), &audit_data);
+
+    Resp::ok_with_empty()
+}
+
+/// 彻底删除已删除的用户及其关联数据, 删除后不可恢复, 用户名等账号可以被重新使用
+pub async fn purge(ctx: HttpContext) -> HttpResponse {
+    type Req = super::GetReq;
+
+    let param: Req = ctx.parse_json()?;
+    let mut audit_data = load_deleted_user(&ctx, param.id).await?;
+
+    SysUser::delete_with_notify(param.id).await?;
+    SysUserRole::delete_by_user(param.id).await?;
+    SysPasswordHistory::delete_by_user(param.id).await?;
+    SysUserMfa::delete_by_id(param.id).await?;
+    SysUserState::delete_by_id(param.id).await?;
+    // 写入审计日志
+    audit_data.password = None;
+    audit::log_json(audit::USER_PURGE, ctx.user_id(), &audit_data);
+
+    Resp::ok_with_empty()
+}
+
 /// 改变状态
 pub async fn disable(ctx: HttpContext) -> HttpResponse {
     type Req = SysUser;
 
     let param: Req = ctx.parse_json()?;
     httpserver::check_required!(param, user_id, disabled);
+    check_disabled(param.disabled)?;
+    let orig = load_user_in_scope(&ctx, param.user_id.unwrap()).await?;
+    check_not_deleted(&orig)?;
 
     let user = SysUser {
         user_id: param.user_id,
@@ -187,16 +308,25 @@ pub async fn resetpw(ctx: HttpContext) -> HttpResponse {
     let param: Req = ctx.parse_json()?;
     httpserver::check_required!(param, user_id);
 
+    // 指定的口令需要符合口令策略, 缺省口令不受限制, 但用户登录后需要立即修改
+    let policy = PasswordPolicy::load().await?;
+    let use_default = param.password.is_none();
+    let user_id = param.user_id.unwrap();
+    let orig = load_user_in_scope(&ctx, user_id).await?;
+    check_not_deleted(&orig)?;
     let pwd = match param.password {
-        Some(v) => v,
+        Some(v) => {
+            policy.check(user_id, &v).await?;
+            v
+        }
         None => get_default_password().await?,
     };
     // 加密后的口令
-    let enc_pwd = md5_crypt::encrypt(&pwd)?;
+    let enc_pwd = password::encrypt_async(&pwd).await?;
 
     let user = SysUser {
         user_id: param.user_id,
-        password: Some(enc_pwd),
+        password: Some(enc_pwd.clone()),
         updated_time: Some(LocalTime::now()),
         ..Default::default()
     };
@@ -204,6 +334,7 @@ pub async fn resetpw(ctx: HttpContext) -> HttpResponse {
 
     // 写入数据库
     SysUser::update_with_notify(user).await?;
+    policy.save_history(user_id, enc_pwd, use_default).await?;
 
     // 记录审计日志
     audit_data.updated_time = None;
@@ -226,13 +357,628 @@ pub async fn items(ctx: HttpContext) -> HttpResponse {
     }
 
     let param: Req = ctx.parse_json()?;
-    let recs = SysUser::select_by(param).await?;
+    let scope = DataScope::load(ctx.user_id()).await?;
+    let recs = SysUser::select_by(param, &scope).await?;
 
     Resp::ok(&Res { users: recs })
 }
 
+/// 获取用户拥有的角色列表(主角色及附加角色)
+pub async fn roles(ctx: HttpContext) -> HttpResponse {
+    type Req = super::GetReq;
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Res {
+        role_id: u32,
+        role_ids: Vec<u32>,
+    }
+
+    let param: Req = ctx.parse_json()?;
+    load_user_in_scope(&ctx, param.id).await?;
+    let (role_id, _) = match SysUser::select_role_and_updated(param.id).await? {
+        Some(v) => v,
+        None => http_bail!("用户不存在"),
+    };
+    let role_ids = SysUserRole::select_role_ids(param.id).await?;
+
+    Resp::ok(&Res { role_id, role_ids })
+}
+
+/// 设置用户的附加角色, 用户权限为主角色与所有附加角色权限的并集
+pub async fn assign_roles(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        user_id: u32,
+        role_ids: Vec<u32>,
+    }
+
+    let mut param: Req = ctx.parse_json()?;
+    param.role_ids.sort_unstable();
+    param.role_ids.dedup();
+
+    let orig = load_user_in_scope(&ctx, param.user_id).await?;
+    check_not_deleted(&orig)?;
+    for rid in param.role_ids.iter() {
+        check_role(*rid).await?;
+    }
+
+    // 写入数据库
+    SysUserRole::replace_roles(param.user_id, &param.role_ids).await?;
+
+    // 写入审计日志
+    audit::log_json(audit::USER_UPD_ROLES, ctx.user_id(), &param);
+
+    Resp::ok_with_empty()
+}
+
+/// 获取指定用户的所有会话
+pub async fn sessions(ctx: HttpContext) -> HttpResponse {
+    type Req = super::GetReq;
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Res {
+        sessions: Vec<session::SessionInfo>,
+    }
+
+    let param: Req = ctx.parse_json()?;
+    load_user_in_scope(&ctx, param.id).await?;
+    let mut sessions = session::list(param.id).await;
+    for item in sessions.iter_mut() {
+        item.token.clear();
+        item.family.clear();
+    }
+
+    Resp::ok(&Res { sessions })
+}
+
+/// 注销指定用户的会话, 未指定会话id时注销该用户的所有会话
+pub async fn revoke_sessions(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        user_id: u32,
+        session_id: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Res {
+        count: usize,
+    }
+
+    let param: Req = ctx.parse_json()?;
+    load_user_in_scope(&ctx, param.user_id).await?;
+    let count = match &param.session_id {
+        Some(sid) => session::revoke(param.user_id, |v| &v.session_id == sid).await,
+        None => session::revoke(param.user_id, |_| true).await,
+    };
+
+    // 写入审计日志
+    audit::log_json(audit::USER_REVOKE_SESSION, ctx.user_id(), &param);
+
+    Resp::ok(&Res { count })
+}
+
+/// 获取因登录失败次数过多而被锁定的账号及ip,
+/// ip的失败计数不区分租户, 只有平台管理员可以查看被锁定的ip
+pub async fn locked(_ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Res {
+        accounts: Vec<login_lock::LockedInfo>,
+        ips: Vec<login_lock::LockedInfo>,
+    }
+
+    let accounts = login_lock::list_locked_accounts().await?;
+    let ips = match tenant::check_platform() {
+        Ok(_) => login_lock::list_locked_ips().await?,
+        Err(_) => Vec::new(),
+    };
+
+    Resp::ok(&Res { accounts, ips })
+}
+
+/// 解除账号或ip的登录锁定, 只有平台管理员可以解除ip的锁定
+pub async fn unlock(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        account: Option<String>,
+        ip: Option<String>,
+    }
+
+    let param: Req = ctx.parse_json()?;
+    if param.account.is_none() && param.ip.is_none() {
+        http_bail!("账号和ip不能同时为空");
+    }
+    if param.ip.is_some() {
+        tenant::check_platform()?;
+    }
+
+    let mut unlocked = false;
+    if let Some(account) = &param.account {
+        unlocked |= login_lock::unlock_account(account).await;
+    }
+    if let Some(ip) = &param.ip {
+        unlocked |= login_lock::unlock_ip(ip).await;
+    }
+    if !unlocked {
+        return Resp::fail("账号或ip未被锁定");
+    }
+
+    // 写入审计日志
+    audit::log_json(audit::USER_UNLOCK, ctx.user_id(), &param);
+
+    Resp::ok_with_empty()
+}
+
+/// 从csv/xlsx文件批量导入用户, 请求体为文件内容, 文件第一行为列标题
+///
+/// url参数:
+///
+/// * `format`: 文件格式(csv/xlsx), 缺省时根据请求的Content-Type判断
+/// * `dryRun`: 为true时只校验数据, 不写入数据库
+///
+/// 任意一行校验失败时不导入任何记录, 返回每行的错误信息
+pub async fn import(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Res {
+        /// 文件中的记录数
+        total: usize,
+        /// 成功导入的记录数
+        imported: usize,
+        /// 校验失败的行及错误信息
+        errors: Vec<ImportError>,
+    }
+
+    let format = match ctx.get_url_param_str("format") {
+        Some(name) => SheetFormat::from_name(&name),
+        None => ctx
+            .header(httpserver::CONTENT_TYPE)
+            .and_then(|v| SheetFormat::from_content_type(&v)),
+    };
+    let format = match format {
+        Some(v) => v,
+        None => http_bail!("不支持的导入文件格式"),
+    };
+    let dry_run = ctx.get_url_param::<bool>("dryRun")?.unwrap_or(false);
+
+    let rows = sheet::read(format, &ctx.body)?;
+    let (header, rows) = match rows.split_first() {
+        Some(v) => v,
+        None => http_bail!("导入文件内容为空"),
+    };
+    fail_if!(
+        rows.len() > IMPORT_MAX_ROWS,
+        "单次最多导入{}个用户",
+        IMPORT_MAX_ROWS
+    );
+    let columns = ImportColumns::parse(header)?;
+
+    // 逐行校验, 行号从1开始, 第1行为标题行
+    let mut checker = ImportChecker::new(&ctx).await?;
+    let (mut users, mut errors) = (Vec::new(), Vec::new());
+    for (i, row) in rows.iter().enumerate() {
+        if sheet::is_empty_row(row) {
+            continue;
+        }
+        let mut row_errors = Vec::new();
+        let user = checker.check(&columns, row, &mut row_errors).await?;
+        if !row_errors.is_empty() {
+            errors.push(ImportError {
+                row: i + 2,
+                messages: row_errors,
+            });
+        }
+        users.extend(user);
+    }
+
+    let total = users.len() + errors.len();
+    fail_if!(total == 0, "导入文件中没有用户记录");
+
+    if dry_run || !errors.is_empty() {
+        return Resp::ok(&Res {
+            total,
+            imported: 0,
+            errors,
+        });
+    }
+
+    // 对口令进行加密, 未指定口令的用户使用缺省口令, 缺省口令只需加密一次,
+    // 加密运算耗时较长, 在阻塞线程池中进行, 避免阻塞其它请求
+    let default_pwd = password::encrypt_async(&get_default_password().await?).await?;
+    let plain_pwds: Vec<_> = users.iter().filter_map(|v| v.password.clone()).collect();
+    let mut enc_pwds = password::encrypt_batch_async(plain_pwds).await?.into_iter();
+    for user in users.iter_mut() {
+        user.password = match user.password {
+            Some(_) => enc_pwds.next(),
+            None => Some(default_pwd.clone()),
+        };
+    }
+
+    let usernames: Vec<_> = users.iter().map(|v| v.username.clone()).collect();
+    let ids = SysUser::batch_insert_with_notify(users, &default_pwd).await?;
+
+    // 写入审计日志
+    let audit_data = serde_json::json!({
+        "userIds": ids,
+        "usernames": usernames,
+    });
+    audit::log_json(audit::USER_IMPORT, ctx.user_id(), &audit_data);
+
+    Resp::ok(&Res {
+        total,
+        imported: ids.len(),
+        errors,
+    })
+}
+
+/// 导出符合条件的用户, 支持csv和xlsx格式, 导出的文件可以修改后重新导入,
+/// 超过导出上限时只导出用户id最小的部分记录, 并在回复头部X-Export-Truncated中标记
+pub async fn export(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Req {
+        #[serde(flatten)]
+        query: SysUserQuery,
+        /// 导出格式: csv(缺省)/xlsx
+        format: Option<String>,
+    }
+
+    let param: Req = ctx.parse_json()?;
+    let format = match param.format.as_deref() {
+        None => SheetFormat::Csv,
+        Some(f) => match SheetFormat::from_name(f) {
+            Some(v) => v,
+            None => http_bail!("不支持的导出格式: {}", f),
+        },
+    };
+
+    let mut writer = SheetWriter::new(format);
+    writer.write_row(&EXPORT_HEADER)?;
+
+    let scope = DataScope::load(ctx.user_id()).await?;
+    let file = ExportFile {
+        file_name: format!("users.{}", format.ext()),
+        content_type: format.content_type(),
+        total: SysUser::select_count(param.query.clone(), &scope).await?,
+    };
+
+    let query = param.query;
+    export::stream(
+        file,
+        UserEncoder(writer),
+        move |after_id, count| {
+            let (query, scope) = (query.clone(), scope.clone());
+            async move { SysUser::select_batch(query, &scope, after_id, count).await }
+        },
+        |v: &SysUser| v.user_id,
+    )
+}
+
+// 校验用户是否属于当前租户且在当前登录用户的数据权限范围内
+async fn check_scope(ctx: &HttpContext, user: &SysUser) -> Result<()> {
+    tenant::check_owner(user.tenant_id)?;
+    let scope = DataScope::load(ctx.user_id()).await?;
+    if !scope.contains(user.dept_id, user.user_id) {
+        http_bail!("无权操作该用户");
+    }
+    Ok(())
+}
+
+// 加载用户记录, 并校验用户是否在当前登录用户的数据权限范围内
+async fn load_user_in_scope(ctx: &HttpContext, user_id: u32) -> Result<SysUser> {
+    let user = match SysUser::select_by_id(user_id).await? {
+        Some(v) => v,
+        None => http_bail!("用户不存在"),
+    };
+    check_scope(ctx, &user).await?;
+    Ok(user)
+}
+
+// 加载已删除的用户记录, 并校验用户是否在当前登录用户的数据权限范围内
+async fn load_deleted_user(ctx: &HttpContext, user_id: u32) -> Result<SysUser> {
+    let user = load_user_in_scope(ctx, user_id).await?;
+    if user.disabled != Some(DisabledType::Deleted as u8) {
+        http_bail!("用户未被删除");
+    }
+    Ok(user)
+}
+
+// 校验用户是否已删除, 已删除的用户需要先恢复才能进行修改
+fn check_not_deleted(user: &SysUser) -> Result<()> {
+    if user.disabled == Some(DisabledType::Deleted as u8) {
+        http_bail!("用户已删除, 请先恢复该用户");
+    }
+    Ok(())
+}
+
+// 校验用户状态参数, 删除状态只能通过删除接口设置
+fn check_disabled(disabled: Option<u8>) -> Result<()> {
+    match disabled {
+        Some(v) if v != DisabledType::Normal as u8 && v != DisabledType::Disabled as u8 => {
+            http_bail!("用户状态[{}]无效", v)
+        }
+        _ => Ok(()),
+    }
+}
+
+// 校验角色是否存在且属于当前租户
+async fn check_role(role_id: u32) -> Result<()> {
+    if !is_tenant_role(role_id).await? {
+        http_bail!("角色[{}]不存在", role_id);
+    }
+    Ok(())
+}
+
+// 校验部门是否存在且属于当前租户, 部门id为0表示不属于任何部门
+async fn check_dept(dept_id: Option<u32>) -> Result<()> {
+    if let Some(id) = dept_id {
+        if id != 0
+            && !SysDept::select_all()
+                .await?
+                .iter()
+                .any(|v| v.dept_id == Some(id))
+        {
+            http_bail!("部门[{}]不存在", id);
+        }
+    }
+    Ok(())
+}
+
+// 判断角色是否存在且属于当前租户
+async fn is_tenant_role(role_id: u32) -> Result<bool> {
+    Ok(match SysRole::select_by_id(role_id).await? {
+        Some(role) => tenant::check_owner(role.tenant_id).is_ok(),
+        None => false,
+    })
+}
+
 // 从数据库配置表中加载默认密码
 async fn get_default_password() -> Result<String> {
     let pw = SysConfig::get_value(consts::cfg::CK_DEFAULT_PASSWORD).await?;
     Ok(pw.map_or(String::from(consts::DEFAULT_PASSWORD), |v| (*v).clone()))
 }
+
+// 生成导出文件中的一行数据, 列顺序与EXPORT_HEADER一致
+fn export_row(user: &SysUser) -> [String; 9] {
+    fn num(v: Option<u32>) -> String {
+        v.map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    [
+        num(user.user_id),
+        user.username.clone().unwrap_or_default(),
+        user.nickname.clone().unwrap_or_default(),
+        user.mobile.clone().unwrap_or_default(),
+        user.email.clone().unwrap_or_default(),
+        num(user.role_id),
+        num(user.dept_id),
+        num(user.disabled.map(u32::from)),
+        user.created_time
+            .as_ref()
+            .map(|v| v.to_string())
+            .unwrap_or_default(),
+    ]
+}
+
+/// 用户导出文件编码器
+struct UserEncoder(SheetWriter);
+
+impl Encoder<SysUser> for UserEncoder {
+    fn write(&mut self, item: &SysUser) -> Result<()> {
+        self.0.write_row(&export_row(item))
+    }
+
+    fn take(&mut self) -> Vec<u8> {
+        self.0.take()
+    }
+
+    fn finish(self) -> Result<Vec<u8>> {
+        self.0.finish()
+    }
+}
+
+/// 导入失败的行及错误信息
+#[derive(Serialize)]
+struct ImportError {
+    /// 行号, 从1开始, 第1行为标题行
+    row: usize,
+    messages: Vec<String>,
+}
+
+/// 导入文件中各字段所在的列序号
+struct ImportColumns {
+    username: usize,
+    nickname: Option<usize>,
+    mobile: Option<usize>,
+    email: Option<usize>,
+    role_id: usize,
+    dept_id: Option<usize>,
+    password: Option<usize>,
+}
+
+impl ImportColumns {
+    /// 解析标题行, 标题可以使用导出文件的中文标题或字段名, 不认识的列将被忽略
+    fn parse(header: &[String]) -> Result<Self> {
+        let find = |label: &str, name: &str| {
+            header
+                .iter()
+                .position(|v| v == label || v.eq_ignore_ascii_case(name))
+        };
+
+        let username = find("用户名", "username");
+        let role_id = find("角色id", "roleId");
+        let (username, role_id) = match (username, role_id) {
+            (Some(u), Some(r)) => (u, r),
+            _ => http_bail!("导入文件必须包含[用户名]及[角色id]列"),
+        };
+
+        Ok(Self {
+            username,
+            nickname: find("昵称", "nickname"),
+            mobile: find("手机号", "mobile"),
+            email: find("电子邮件", "email"),
+            role_id,
+            dept_id: find("部门id", "deptId"),
+            password: find("口令", "password"),
+        })
+    }
+}
+
+/// 导入数据校验器, 缓存校验过程中需要重复使用的数据
+struct ImportChecker {
+    policy: PasswordPolicy,
+    scope: DataScope,
+    /// 当前租户的所有部门id
+    dept_ids: HashSet<u32>,
+    /// 已校验过的角色id及其是否有效
+    roles: HashMap<u32, bool>,
+    /// 导入文件中已出现的账号(用户名/手机号/电子邮件), 用于检查文件内的重复记录
+    accounts: HashSet<String>,
+}
+
+impl ImportChecker {
+    async fn new(ctx: &HttpContext) -> Result<Self> {
+        Ok(Self {
+            policy: PasswordPolicy::load().await?,
+            scope: DataScope::load(ctx.user_id()).await?,
+            dept_ids: SysDept::select_all()
+                .await?
+                .iter()
+                .filter_map(|v| v.dept_id)
+                .collect(),
+            roles: HashMap::new(),
+            accounts: HashSet::new(),
+        })
+    }
+
+    /// 校验一行数据, 校验失败的原因写入errors, 校验通过时返回待导入的用户(口令未加密)
+    ///
+    /// Arguments:
+    ///
+    /// * `cols`: 各字段所在的列序号
+    /// * `row`: 行数据
+    /// * `errors`: 校验失败的错误信息
+    ///
+    async fn check(
+        &mut self,
+        cols: &ImportColumns,
+        row: &[String],
+        errors: &mut Vec<String>,
+    ) -> Result<Option<SysUser>> {
+        let cell = |idx: Option<usize>| {
+            idx.and_then(|i| row.get(i))
+                .filter(|v| !v.is_empty())
+                .cloned()
+        };
+
+        let username = cell(Some(cols.username));
+        let nickname = cell(cols.nickname).or_else(|| username.clone());
+        let mobile = cell(cols.mobile);
+        let email = cell(cols.email);
+        let password = cell(cols.password);
+
+        // 校验账号格式及是否重复
+        match &username {
+            Some(v) if matches!(check_account_type(v), AccountType::Username) => {
+                self.check_account(v, errors).await?
+            }
+            Some(v) => errors.push(format!("用户名[{v}]不能是手机号或电子邮件格式")),
+            None => errors.push(String::from("用户名不能为空")),
+        }
+        if let Some(v) = &mobile {
+            match check_account_type(v) {
+                AccountType::Mobile => self.check_account(v, errors).await?,
+                _ => errors.push(format!("手机号[{v}]格式错误")),
+            }
+        }
+        if let Some(v) = &email {
+            match check_account_type(v) {
+                AccountType::Email => self.check_account(v, errors).await?,
+                _ => errors.push(format!("电子邮件[{v}]格式错误")),
+            }
+        }
+
+        // 校验角色是否存在
+        let role_id = match cell(Some(cols.role_id)).map(|v| v.parse::<u32>()) {
+            Some(Ok(id)) => {
+                let valid = match self.roles.get(&id) {
+                    Some(v) => *v,
+                    None => {
+                        let v = is_tenant_role(id).await?;
+                        self.roles.insert(id, v);
+                        v
+                    }
+                };
+                if !valid {
+                    errors.push(format!("角色[{id}]不存在"));
+                }
+                Some(id)
+            }
+            Some(Err(_)) => {
+                errors.push(String::from("角色id格式错误"));
+                None
+            }
+            None => {
+                errors.push(String::from("角色id不能为空"));
+                None
+            }
+        };
+
+        // 只能在数据权限范围内的部门中添加用户
+        let dept_id = match cell(cols.dept_id).map(|v| v.parse::<u32>()) {
+            Some(Ok(id)) => Some(id),
+            Some(Err(_)) => {
+                errors.push(String::from("部门id格式错误"));
+                None
+            }
+            None => None,
+        };
+        if let Some(id) = dept_id.filter(|v| *v != 0 && !self.dept_ids.contains(v)) {
+            errors.push(format!("部门[{id}]不存在"));
+        } else if !self.scope.contains(dept_id, None) {
+            errors.push(format!("无权在部门[{}]中添加用户", dept_id.unwrap_or(0)));
+        }
+
+        // 指定口令时需要符合口令策略
+        if let Some(pwd) = &password {
+            if let Err(e) = self.policy.check_strength(pwd) {
+                errors.push(e.to_string());
+            }
+        }
+
+        if !errors.is_empty() {
+            return Ok(None);
+        }
+
+        let now = LocalTime::now();
+        Ok(Some(SysUser {
+            role_id,
+            dept_id,
+            disabled: Some(DisabledType::Normal as u8),
+            username,
+            password,
+            nickname,
+            mobile,
+            email,
+            updated_time: Some(now.clone()),
+            created_time: Some(now),
+            ..Default::default()
+        }))
+    }
+
+    /// 校验账号在导入文件及数据库中是否重复
+    async fn check_account(&mut self, account: &str, errors: &mut Vec<String>) -> Result<()> {
+        if !self.accounts.insert(account.to_owned()) {
+            errors.push(format!("账号[{account}]在导入文件中重复"));
+        } else if SysUser::select_by_account(account).await?.is_some() {
+            errors.push(format!("账号[{account}]已存在"));
+        }
+        Ok(())
+    }
+}
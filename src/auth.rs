@@ -14,6 +14,7 @@ use std::{borrow::Cow, collections::HashMap, sync::Arc, time::{Duration, SystemT
 type Cache<K, V> = mini_moka::sync::Cache<K, V, FnvBuildHasher>;
 type MCache = MultiCache<String, TokenCacheItem, UniRedisImpl>;
 type PermitValue = Arc<Vec<i16>>;
+type RoleIds = Arc<Vec<u32>>;
 type ArcMap<K, V> = ArcSwap<HashMap<K, V>>;
 
 pub struct Authentication {
@@ -21,14 +22,18 @@ pub struct Authentication {
     jwt_data: Option<JwtData>,                              // 令牌校验参数
     token_cache: MCache,                                    // jwt验证解码缓存
     role_permits_map: ArcMap<u32, String>,           // 角色权限缓存
-    path_permits_map: ArcMap<String, PermitValue>,   // 接口权限缓存
-    user_cache: Cache<u32, (u32, i64)>,                     // 用户/角色/更新时间映射缓存
+    path_matcher: ArcSwap<RouteMatcher<i16>>,        // 接口权限规则匹配器
+    user_cache: Cache<u32, (RoleIds, i64)>,                 // 用户/角色列表/更新时间映射缓存
     path_permits_cache: Cache<String, PermitValue>,  // 实际访问路径的权限缓存
+    session_touch_cache: Cache<String, ()>,          // 最近已更新访问时间的会话
+    skip_paths: Vec<String>,                         // 跳过权限校验的路径前缀
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 struct TokenCacheItem {
     uid: String,
+    #[serde(default)]
+    tid: u32,
     exp: u64,
 }
 
@@ -42,27 +47,50 @@ pub const COOKIE_NAME: &str = "Cookie";
 
 const TOKEN_VERIFIED: &str = "Token-Verified";
 const UID_NAME: &str = "uid";
+const TID_NAME: &str = "tid";
 const USER_ROLE_CACHE_SIZE: u64 = 256; //USER_ROLE_CACHE的缓存大小
 const PATH_CACHE_SIZE: u64 = 256; //PATH_CACHE的缓存大小
+const SESSION_TOUCH_CACHE_SIZE: u64 = 1024; //SESSION_TOUCH_CACHE的缓存大小
+const SESSION_TOUCH_INTERVAL: u64 = 60; // 会话最后访问时间的更新间隔(单位: 秒)
 
 #[async_trait::async_trait]
 impl httpserver::HttpMiddleware for Authentication {
     async fn handle<'a>(&'a self, mut ctx: HttpContext, next: Next<'a>) -> HttpResponse {
         use hyper::StatusCode;
 
-        let (uid, opt_err) = match self.parse_user_id(&ctx).await {
-            Ok(uid) => (uid, None),
-            Err(e) => (String::with_capacity(0), Some(e)),
+        // 探针等基础接口无需登录, 也不区分租户
+        let path = ctx.req.uri().path();
+        if self.skip_paths.iter().any(|p| path.starts_with(p.as_str())) {
+            return next.run(ctx).await;
+        }
+
+        let (uid, token_tid, opt_err) = match self.parse_user_id(&ctx).await {
+            Ok((uid, tid)) => (uid, tid, None),
+            Err(e) => (String::with_capacity(0), None, Some(e)),
         };
         if !uid.is_empty() {
             ctx.uid.push_str(&uid);
         }
 
         // 权限校验通过，执行下一步
-        if self.auth(ctx.id, &uid, ctx.req.uri().path()).await {
-            next.run(ctx).await
+        if self.auth(ctx.id, &uid, ctx.req.method().as_str(), ctx.req.uri().path()).await {
+            // 已登录用户以令牌中的租户为准, 未登录时使用请求头部指定的租户
+            let tenant_id = match token_tid {
+                Some(tid) => tid,
+                None => tenant::from_header(&ctx)?.unwrap_or(tenant::PLATFORM_TENANT_ID),
+            };
+            if !tenant::is_enabled(tenant_id).await? {
+                log_debug!(ctx.id, "租户[{}]不存在或已停用", tenant_id);
+                metrics::AUTH_DENY.inc();
+                return Resp::fail_with_status(StatusCode::FORBIDDEN, 403, "Forbidden");
+            }
+            ctx.set_attr(tenant::ATTR_TENANT_ID.to_owned(), tenant_id);
+            metrics::AUTH_ALLOW.inc();
+
+            tenant::scope(tenant_id, next.run(ctx)).await
         } else {
             if let Some(e) = opt_err {
+                metrics::AUTH_UNAUTHORIZED.inc();
                 if e.to_string() == "Incorrect exp" {
                     return Resp::fail_with_status(StatusCode::UNAUTHORIZED, 401, "Unauthorized");
                 }
@@ -76,6 +104,7 @@ impl httpserver::HttpMiddleware for Authentication {
                     "权限校验失败[{}], 用户尚未登录",
                     ctx.req.uri().path()
                 );
+                metrics::AUTH_UNAUTHORIZED.inc();
                 Resp::fail_with_status(StatusCode::UNAUTHORIZED, 401, "Unauthorized")
             } else {
                 log_debug!(
@@ -83,6 +112,7 @@ impl httpserver::HttpMiddleware for Authentication {
                     "权限校验失败[{}], 当前用户没有访问权限",
                     ctx.req.uri().path()
                 );
+                metrics::AUTH_DENY.inc();
                 Resp::fail_with_status(StatusCode::FORBIDDEN, 403, "Forbidden")
             }
         }
@@ -120,21 +150,47 @@ impl Authentication {
             .time_to_idle(local_cache_expire)
             .build_with_hasher(FnvBuildHasher::default());
 
+        let session_touch_cache = mini_moka::sync::Cache::builder()
+            .max_capacity(SESSION_TOUCH_CACHE_SIZE)
+            .time_to_live(Duration::from_secs(SESSION_TOUCH_INTERVAL))
+            .build_with_hasher(FnvBuildHasher::default());
+
         let result = Authentication {
             content_path: String::from(content_path),
             jwt_data,
             token_cache,
             role_permits_map: ArcSwap::from_pointee(load_roles().await.dot()?),
-            path_permits_map: ArcSwap::from_pointee(load_permits().await.dot()?),
+            path_matcher: ArcSwap::from_pointee(load_permits().await.dot()?),
             user_cache,
             path_permits_cache: path_cache,
+            session_touch_cache,
+            skip_paths: Vec::new(),
         };
 
         Ok(result)
     }
 
+    /// 设置跳过权限校验的路径前缀, 匹配的请求不受接口权限表控制
+    ///
+    /// Arguments:
+    ///
+    /// * `paths`: 路径前缀列表, 包含上下文路径, 如 `/api/health/`
+    ///
+    pub fn skip_paths(mut self, paths: Vec<String>) -> Self {
+        self.skip_paths = paths;
+        self
+    }
+
     /// 权限校验, 返回true表示有权访问, false表示无权访问
-    pub async fn auth(&self, req_id: u32, uid: &str, mut path: &str) -> bool {
+    ///
+    /// Arguments:
+    ///
+    /// * `req_id`: 请求id
+    /// * `uid`: 用户id, 未登录时为空字符串
+    /// * `method`: 请求方法, 为空时只匹配未限定请求方法的接口规则
+    /// * `path`: 请求路径
+    ///
+    pub async fn auth(&self, req_id: u32, uid: &str, method: &str, mut path: &str) -> bool {
         let uid: u32 = if uid.is_empty() {
             0
         } else {
@@ -155,52 +211,36 @@ impl Authentication {
         }
 
         path = &path[self.content_path.len()..];
-        let orig_path = String::from(path);
-        log_trace!(req_id, "权限校验路径: {}", path);
+        let cache_key = format!("{method} {path}");
+        log_trace!(req_id, "权限校验路径: {}", cache_key);
 
         let user_permits = self.get_user_permits(uid).await;
 
         // 优先从缓存中读取，加快匹配速度
-        if let Some(ps) = self.path_permits_cache.get(&orig_path) {
-            log_trace!(req_id, "在缓存中找到匹配路径: {}", orig_path);
+        if let Some(ps) = self.path_permits_cache.get(&cache_key) {
+            log_trace!(req_id, "在缓存中找到匹配路径: {}", cache_key);
             return Self::check_permit(uid, &user_permits, &ps);
         }
 
-        // 末尾的'/'不参与匹配
-        let path_bytes = path.as_bytes();
-        let mut end_pos = path_bytes.len();
-        if end_pos > 1 && path_bytes[end_pos - 1] == b'/' {
-            end_pos -= 1;
-        }
-
-        let permits = self.path_permits_map.load();
-        // 递归每个父路径进行权限匹配, 有权限访问则直接返回true, 否则继续循环
-        loop {
-            path = &path[..end_pos];
-
-            if let Some(ps) = permits.get(path) {
-                log_trace!(req_id, "找到匹配路径: {}", path);
-                self.path_permits_cache.insert(orig_path, ps.clone());
-                return Self::check_permit(uid, &user_permits, ps);
+        // 按规则的具体程度依次匹配, 使用最具体的规则进行权限校验
+        let matcher = self.path_matcher.load();
+        match matcher.find(method, path) {
+            Some((pattern, ps)) => {
+                log_trace!(req_id, "找到匹配规则: {}", pattern);
+                self.path_permits_cache.insert(cache_key, ps.clone());
+                Self::check_permit(uid, &user_permits, ps)
             }
-
-            // 找到上一级目录, 找不到退出循环
-            end_pos = match path.rfind('/') {
-                Some(n) if n > 0 => n,
-                _ => break,
-            };
-        }
-
-        // 路径都不匹配，尝试与根路径的权限匹配
-        if let Some(ps) = permits.get("/") {
-            return Self::check_permit(uid, &user_permits, ps);
+            None => false,
         }
+    }
 
-        false
+    /// 令牌缓存的命中统计
+    pub fn token_cache_stats(&self) -> &CacheStats {
+        self.token_cache.stats()
     }
 
-    /// 校验jwt token, 返回token携带的uid值, 获取失败返回空字符串
-    pub async fn decode_token(&self, req_id: u32, token: &str) -> Result<String> {
+    /// 校验jwt token, 返回token携带的uid及租户id
+    pub async fn decode_token(&self, req_id: u32, token: &str) -> Result<(String, u32)> {
         // 获取token的签名值
         let sign = match &jwt::get_sign(token) {
             Some(sign) => sign.to_string(),
@@ -216,7 +256,7 @@ impl Authentication {
             // 校验token过期时间
             if cache_item.exp >= now {
                 log_trace!(req_id, "使用缓存校验token: {sign}");
-                return Ok(cache_item.uid);
+                return Ok((cache_item.uid, cache_item.tid));
             } else {
                 // 缓存项过期
                 self.token_cache.del(&sign).await;
@@ -227,16 +267,32 @@ impl Authentication {
 
         // 如果使用了api网关，则jwt令牌已被网关校验过
         let claims = match &self.jwt_data {
-            Some(jwt_data) => jwt::decode(token, &jwt_data.key, &jwt_data.iss).dot()?,
+            Some(jwt_data) => match jwt_keys::get() {
+                // 启用非对称签名后, 令牌必须带有kid并使用非对称签名密钥校验
+                Some(keys) => match jwt_keys::get_kid(token) {
+                    Some(_) => keys.decode(token, &jwt_data.iss).dot()?,
+                    // 密钥迁移期内仍接受使用jwt-key签名的令牌
+                    None if now < AppGlobal::get().jwt_hs_until => {
+                        jwt::decode(token, &jwt_data.key, &jwt_data.iss).dot()?
+                    }
+                    None => return Err(anyhow!("令牌缺少kid")).dot(),
+                },
+                None => jwt::decode(token, &jwt_data.key, &jwt_data.iss).dot()?,
+            },
             None => Self::get_claims(token).dot()?,
         };
 
         let exp = jwt::get_exp(&claims).dot()?;
+        // 未携带租户id的令牌属于平台租户
+        let tid = match claims.get(TID_NAME).and_then(Value::as_u64) {
+            Some(n) => n as u32,
+            None => tenant::PLATFORM_TENANT_ID,
+        };
 
         if let Some(Value::String(uid)) = claims.get(UID_NAME) {
             log_trace!(req_id, "将token加入缓存: {}", sign);
-            self.token_cache.set(sign, TokenCacheItem {uid: String::from(uid), exp}).await;
-            return Ok(uid.to_string());
+            self.token_cache.set(sign, TokenCacheItem {uid: String::from(uid), tid, exp}).await;
+            return Ok((uid.to_string(), tid));
         }
 
         Err(anyhow!("找不到uid")).dot()
@@ -285,33 +341,35 @@ impl Authentication {
         });
     }
 
-    async fn parse_user_id(&self, ctx: &HttpContext) -> Result<String> {
+    /// 解析令牌中的用户id及租户id, 令牌无效时用户id为空字符串, 租户id为None
+    async fn parse_user_id(&self, ctx: &HttpContext) -> Result<(String, Option<u32>)> {
         let mut uid = String::new();
 
         // 获取token
         let token = match self.get_token(ctx) {
             Some(s) => s,
-            None => return Ok(uid),
+            None => return Ok((uid, None)),
         };
         log_trace!(ctx.id, "token: {}", token);
 
         // 判断token是否有效（当用户退出登录后，token无效，会写入redis）
         if self.jwt_data.is_some() && get_invalid_token(&token).await {
-            return Ok(uid);
+            return Ok((uid, None));
         }
 
-        let uid_str = self.decode_token(ctx.id, &token).await.dot()?;
+        let (uid_str, tid) = self.decode_token(ctx.id, &token).await.dot()?;
         // 解码token成功，将登录用户id写入ctx上下文环境
         match uid_str.parse::<u32>() {
             Ok(uid_int) => {
                 // 用户记录没有更新，token有效
                 if !self.check_user_updated(uid_int, &token).await {
                     uid.push_str(&uid_str);
-                    log_trace!(ctx.id, "uid: {}", uid_str);
-                } else {
-                    log_debug!(ctx.id, "用户信息已更新，token失效");
+                    log_trace!(ctx.id, "uid: {}, tid: {}", uid_str, tid);
+                    self.touch_session(uid_int, &token);
+                    return Ok((uid, Some(tid)));
                 }
-                Ok(uid)
+                log_debug!(ctx.id, "用户信息已更新，token失效");
+                Ok((uid, None))
             }
             Err(e) => {
                 log_error!(ctx.id, "token校验失败，uid格式错误: {uid_str}");
@@ -320,6 +378,26 @@ impl Authentication {
         }
     }
 
+    /// 更新令牌对应会话的最后访问时间, 同一会话在更新间隔内只更新1次
+    fn touch_session(&self, user_id: u32, token: &str) {
+        if self.jwt_data.is_none() {
+            return;
+        }
+        let sign = match jwt::get_sign(token) {
+            Some(sign) => sign.to_string(),
+            None => return,
+        };
+        if self.session_touch_cache.contains_key(&sign) {
+            return;
+        }
+        self.session_touch_cache.insert(sign, ());
+
+        let token = token.to_string();
+        tokio::spawn(async move {
+            crate::services::session::touch(user_id, &token).await;
+        });
+    }
+
     /// 从jwt token中获取claims的内容并进行json反序列化
     fn get_claims(token: &str) -> Result<Value> {
         let mut iter = token.split('.');
@@ -388,22 +466,27 @@ impl Authentication {
         None
     }
 
-    /// 根据用户id加载用户权限
+    /// 根据用户id加载用户权限, 用户拥有多个角色时, 返回所有角色权限按位或的结果
     async fn get_user_permits(&self, uid: u32) -> String {
         if uid != 0 {
-            let rid = match self.user_cache.get(&uid) {
-                Some((rid, _)) => rid,
+            let rids = match self.user_cache.get(&uid) {
+                Some((rids, _)) => rids,
                 None => {
-                    let rid_updated = get_role_and_updated(uid).await;
-                    self.user_cache.insert(uid, rid_updated);
-                    rid_updated.0
+                    let rids_updated = get_roles_and_updated(uid).await;
+                    let rids = rids_updated.0.clone();
+                    self.user_cache.insert(uid, rids_updated);
+                    rids
                 }
             };
 
-            if rid != 0 {
-                if let Some(permits) =  self.role_permits_map.load().get(&rid) {
-                    return permits.clone();
+            let role_permits = self.role_permits_map.load();
+            let mut iter = rids.iter().filter_map(|rid| role_permits.get(rid));
+            if let Some(first) = iter.next() {
+                let mut permits = first.clone();
+                for item in iter {
+                    crate::utils::bits::or(&mut permits, item);
                 }
+                return permits;
             }
         }
 
@@ -448,7 +531,7 @@ impl Authentication {
     }
 
     fn get_exp(token: &str) -> u64 {
-        match jwt::decode(token, "", "") {
+        match Self::get_claims(token) {
             Ok(claims) => match jwt::get_exp(&claims) {
                 Ok(exp) => return exp,
                 Err(e) => log::error!("获取token过期时间失败: {e:?}"),
@@ -459,7 +542,7 @@ impl Authentication {
     }
 
     fn get_created(token: &str) -> i64 {
-        match jwt::decode(token, "", "") {
+        match Self::get_claims(token) {
             Ok(claims) => match claims.get("created") {
                 Some(created) => match created.as_i64() {
                     Some(created) => return created,
@@ -484,9 +567,9 @@ impl Authentication {
             return updated >= created;
         }
 
-        let (rid, updated) = get_role_and_updated(user_id).await;
+        let (rids, updated) = get_roles_and_updated(user_id).await;
         // 保存到缓存
-        self.user_cache.insert(user_id, (rid, updated));
+        self.user_cache.insert(user_id, (rids, updated));
         if updated != 0 {
             if log::log_enabled!(log::Level::Trace) {
                 log::trace!(
@@ -502,17 +585,17 @@ impl Authentication {
     }
 }
 
-async fn get_role_and_updated(user_id: u32) -> (u32, i64) {
+async fn get_roles_and_updated(user_id: u32) -> (RoleIds, i64) {
     use crate::entities::sys_user::SysUser;
-    match SysUser::select_role_and_updated(user_id).await {
+    match SysUser::select_roles_and_updated(user_id).await {
         Ok(r) => {
-            if let Some((rid, updated)) = r {
-                return (rid, updated.timestamp());
+            if let Some((rids, updated)) = r {
+                return (Arc::new(rids), updated.timestamp());
             }
         }
         Err(e) => log::error!("数据库查询出错: {e:?}"),
     }
-    (0, 0)
+    (Arc::new(Vec::with_capacity(0)), 0)
 }
 
 /// 从数据库中加载角色信息表, 返回所有角色id与对应的权限
@@ -537,27 +620,24 @@ async fn load_roles() -> Result<HashMap<u32, String>> {
     Ok(roles)
 }
 
-/// 从数据库中加载权限信息表, 返回所有路径对应的权限索引组
-async fn load_permits() -> Result<HashMap<String, Arc<Vec<i16>>>> {
+/// 从数据库中加载权限信息表, 返回编译后的接口规则匹配器
+async fn load_permits() -> Result<RouteMatcher<i16>> {
     use crate::entities::sys_api::SysApi;
 
-    let mut permits = HashMap::new();
     let api_data = SysApi::select_all().await.dot()?;
-
-    for a in &api_data {
-        let pcode = a.permission_code.unwrap();
-        let api_path = String::from(a.api_path.as_ref().unwrap());
-        let v = permits.entry(api_path).or_insert_with(Vec::new);
-        v.push(pcode);
-    }
+    let permits = RouteMatcher::compile(
+        api_data
+            .iter()
+            .map(|a| (a.api_path.as_ref().unwrap(), a.permission_code.unwrap())),
+    );
 
     if log::log_enabled!(log::Level::Trace) {
         log::trace!("权限模块加载权限数据: {permits:?}");
     } else {
-        log::debug!("权限模块加载权限数据成功!");
+        log::debug!("权限模块加载权限数据成功, 共{}条规则!", permits.len());
     }
 
-    Ok(permits.into_iter().map(|(k, v)| (k, Arc::new(v))).collect())
+    Ok(permits)
 }
 
 async fn start_listen(
@@ -584,7 +664,7 @@ async fn start_listen(
         let that = that.clone();
         async move {
             log::debug!("收到接口变动消息，正在重新加载权限数据...");
-            that.path_permits_map.store(Arc::new(load_permits().await.dot()?));
+            that.path_matcher.store(Arc::new(load_permits().await.dot()?));
             that.path_permits_cache.invalidate_all();
             Ok(())
         }
@@ -655,6 +735,10 @@ pub async fn set_invalid_token(token: &str) {
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_secs();
+    // 已过期的token无需加入黑名单
+    if exp <= now {
+        return;
+    }
 
     crate::services::uri::set(&key, "1", exp - now).await;
 }
@@ -667,7 +751,7 @@ fn get_invalid_token_key(sign: &str) -> String {
 pub const ANONYMOUS_CODE: i16 = -2;
 pub const PUBLIC_CODE: i16 = -1;
 
-use crate::{services::uri::UniRedisImpl, utils::{consts, multi_cache::MultiCache, staticmut::StaticMut}, AppConf};
+use crate::{services::{jwt_keys, metrics, tenant, uri::UniRedisImpl}, utils::{consts, multi_cache::{CacheStats, MultiCache}, route_matcher::RouteMatcher, staticmut::StaticMut}, AppConf, AppGlobal};
 pub static mut AUTHENTICATION: StaticMut<Arc<Authentication>> = StaticMut::new();
 
 pub fn get_authentication() -> Arc<Authentication> {
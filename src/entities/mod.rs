@@ -2,12 +2,17 @@ use serde::{Deserialize, Serialize};
 
 pub mod sys_api;
 pub mod sys_config;
+pub mod sys_dept;
 pub mod sys_dict;
 pub mod sys_log;
 pub mod sys_menu;
+pub mod sys_password_history;
 pub mod sys_permission;
 pub mod sys_role;
+pub mod sys_tenant;
 pub mod sys_user;
+pub mod sys_user_mfa;
+pub mod sys_user_role;
 pub mod sys_user_state;
 
 #[derive(Serialize, Deserialize)]
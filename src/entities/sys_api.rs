@@ -22,7 +22,7 @@ pub struct SysApi {
     api_id: u32,
     /// 权限代码
     permission_code: i16,
-    /// 接口地址
+    /// 接口地址, 支持`*`/`{param}`/`**`通配及请求方法前缀, 如`GET /sys/user/{id}`
     api_path: String,
     /// 接口描述
     api_remark: String,
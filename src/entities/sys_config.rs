@@ -4,7 +4,13 @@
 use std::sync::Arc;
 
 use super::{PageData, PageInfo};
-use crate::{services::gmc, utils::consts};
+use crate::{
+    services::{
+        gmc,
+        tenant::{self, TenantFilter, PLATFORM_TENANT_ID},
+    },
+    utils::consts,
+};
 use gensql::{table, DbResult, Queryable};
 use localtime::LocalTime;
 
@@ -17,6 +23,8 @@ pub struct SysConfig {
     /// 配置项id
     #[table(id)]
     cfg_id: u32,
+    /// 租户id
+    tenant_id: u32,
     /// 配置项分类
     category: u8,
     /// 配置项名称
@@ -30,7 +38,8 @@ pub struct SysConfig {
 }
 
 impl SysConfig {
-    pub async fn insert_with_notify(self) -> DbResult<(u32, u32)> {
+    pub async fn insert_with_notify(mut self) -> DbResult<(u32, u32)> {
+        self.tenant_id = Some(tenant::current_or_platform());
         let name = String::from(self.cfg_name.as_ref().map_or("", |s| s.as_str()));
         let ret = self.insert().await;
         if ret.is_ok() {
@@ -39,7 +48,9 @@ impl SysConfig {
         ret
     }
 
-    pub async fn update_with_notify(self) -> DbResult<bool> {
+    pub async fn update_with_notify(mut self) -> DbResult<bool> {
+        // 记录所属的租户不允许变更
+        self.tenant_id = None;
         let name = String::from(self.cfg_name.as_ref().map_or("", |s| s.as_str()));
         let ret = self.update_by_id().await;
         if ret.is_ok() {
@@ -62,9 +73,13 @@ impl SysConfig {
         }
     }
 
-    /// 数据变更通知
+    /// 数据变更通知, 平台租户的配置项是所有租户的缺省值, 变更时清除所有租户的缓存
     pub async fn notify_changed(name: &str) {
-        gmc::get_cache().notify(CATEGORY, name).await
+        let key = match tenant::current_or_platform() {
+            PLATFORM_TENANT_ID => String::new(),
+            _ => tenant::cache_key(name),
+        };
+        gmc::get_cache().notify(CATEGORY, &key).await
     }
 
     /// 查询记录
@@ -72,7 +87,8 @@ impl SysConfig {
         let (tsql, psql, params) = gensql::SelectSql::new()
             .from(Self::TABLE_NAME)
             .where_sql(|w| {
-                w.like_opt("", Self::CFG_NAME, value.cfg_name)
+                w.filter("", &TenantFilter)
+                    .like_opt("", Self::CFG_NAME, value.cfg_name)
                     .like_opt("", Self::CFG_VALUE, value.cfg_value)
                     .like_opt("", Self::CFG_REMARK, value.cfg_remark)
             })
@@ -91,19 +107,28 @@ impl SysConfig {
     }
 
     /// 获取配置项(优先从缓存中读取，缓存读取不到则从数据库中读取)
+    ///
+    /// 当前租户未设置该配置项时使用平台租户的配置项
     pub async fn get_value(name: &str) -> DbResult<CacheValueType> {
         // 优先从缓存加载
         let cache = gmc::get_cache();
-        let value = cache.get(CATEGORY, name).await;
+        let cache_key = tenant::cache_key(name);
+        let value = cache.get(CATEGORY, &cache_key).await;
         if value.is_some() {
             return Ok(value);
         }
 
-        // 从数据库中加载
+        // 从数据库中加载, 租户自身的配置项优先
+        let tenant_ids = [tenant::current_or_platform(), PLATFORM_TENANT_ID];
         let (sql, params) = gensql::SelectSql::new()
             .select("", Self::CFG_VALUE)
             .from(Self::TABLE_NAME)
-            .where_sql(|w| w.eq("", Self::CFG_NAME, name))
+            .where_sql(|w| {
+                w.eq("", Self::CFG_NAME, name)
+                    .in_("", Self::TENANT_ID, tenant_ids)
+            })
+            .order_by_with_iter([("", Self::TENANT_ID, true)])
+            .limits(0, 1)
             .build();
 
         let result: Option<String> = gensql::sql_query_one(sql, params).await?;
@@ -112,13 +137,19 @@ impl SysConfig {
         match result {
             Some(value) => {
                 let value = Arc::new(value);
-                cache.put(CATEGORY, name, value.clone()).await;
+                cache.put(CATEGORY, &cache_key, value.clone()).await;
                 Ok(Some(value))
             }
             None => Ok(None),
         }
     }
 
+    /// 获取数值类型的配置项, 配置项不存在或格式错误时返回缺省值
+    pub async fn get_u32(name: &str, default: u32) -> DbResult<u32> {
+        let value = Self::get_value(name).await?;
+        Ok(value.and_then(|v| v.trim().parse().ok()).unwrap_or(default))
+    }
+
     /// 获取配置项(优先从缓存中读取，缓存读取不到则从数据库中读取)
     pub async fn get_or_init<F: Fn() -> SysConfig>(name: &str, init_fn: F) -> DbResult<CacheValueType> {
         // 如果成功加载数据则直接返回
@@ -128,7 +159,8 @@ impl SysConfig {
         }
 
         // 写入缺省值到数据库
-        let cfg = init_fn();
+        let mut cfg = init_fn();
+        cfg.tenant_id = Some(tenant::current_or_platform());
         let value = cfg.cfg_value.as_ref().map(|s| Arc::new(s.clone()));
         cfg.insert().await?;
 
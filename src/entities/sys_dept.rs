@@ -0,0 +1,304 @@
+//! 部门表
+use std::{collections::HashMap, sync::Arc};
+
+use super::{sys_user::SysUser, PageData, PageInfo};
+use crate::{
+    services::{
+        gmc,
+        tenant::{self, TenantFilter},
+    },
+    utils::code_tree::CODE_LEN,
+};
+use gensql::{table, DbResult, Queryable};
+use localtime::LocalTime;
+
+const CATEGORY: &str = crate::utils::consts::gmc::SYS_DEPT;
+const CACHE_ALL_KEY: &str = "all";
+
+/// 部门(组织机构)表
+#[table("t_sys_dept")]
+pub struct SysDept {
+    /// 部门id
+    #[table(id)]
+    dept_id: u32,
+    /// 租户id
+    tenant_id: u32,
+    /// 部门代码, 每级2位, 由上级部门代码加上本级代码组成
+    dept_code: String,
+    /// 部门名称
+    dept_name: String,
+    /// 部门负责人的用户id
+    leader_id: u32,
+    /// 禁用标志, 0: 启用, 1: 禁用
+    disabled: u8,
+    /// 部门描述
+    dept_desc: String,
+    /// 更新时间
+    updated_time: LocalTime,
+}
+
+#[table]
+pub struct SysDeptVo {
+    #[serde(flatten)]
+    pub inner: SysDept,
+    /// 部门负责人昵称
+    leader_name: String,
+    /// 上级部门代码
+    parent_dept_code: String,
+    /// 下级部门列表
+    #[table(ignore)]
+    pub depts: Vec<SysDeptVo>,
+}
+
+impl SysDept {
+    pub async fn insert_with_notify(mut self) -> DbResult<(u32, u32)> {
+        self.tenant_id = Some(tenant::current_or_platform());
+        let ret = self.insert().await;
+        if ret.is_ok() {
+            Self::notify_changed().await;
+        }
+        ret
+    }
+
+    pub async fn update_with_notify(mut self) -> DbResult<bool> {
+        // 记录所属的租户不允许变更
+        self.tenant_id = None;
+        let ret = self.update_by_id().await;
+        if ret.is_ok() {
+            Self::notify_changed().await;
+        }
+        ret
+    }
+
+    pub async fn delete_with_notify(id: u32) -> DbResult<bool> {
+        let ret = Self::delete_by_id(id).await;
+        if let Ok(true) = ret {
+            Self::notify_changed().await;
+        }
+        ret
+    }
+
+    /// 数据变更通知, 部门数据整体缓存, 任一记录变更时清除全部缓存
+    pub async fn notify_changed() {
+        gmc::get_cache().notify(CATEGORY, "").await
+    }
+
+    /// 部门分页查询
+    ///
+    /// Arguments:
+    ///
+    /// * `value`: 查询参数
+    /// * `page`: 查询分页参数
+    ///
+    pub async fn select_page(value: SysDept, page: PageInfo) -> DbResult<PageData<SysDeptVo>> {
+        type T = SysDept;
+        type U = SysUser;
+
+        let (t, u) = ("t", "u");
+
+        let (tsql, psql, params) = gensql::SelectSql::new()
+            .select_all_with_table(t)
+            .select_as(u, U::NICKNAME, SysDeptVo::LEADER_NAME)
+            .from_as(T::TABLE_NAME, t)
+            .left_join(U::TABLE_NAME, u, |j| j.on_eq(U::USER_ID, t, T::LEADER_ID))
+            .where_sql(|w| {
+                w.filter(t, &TenantFilter)
+                    .eq_opt(t, T::LEADER_ID, value.leader_id)
+                    .eq_opt(t, T::DISABLED, value.disabled)
+                    .like_opt(t, T::DEPT_NAME, value.dept_name)
+                    .expr_opt(t, T::DEPT_CODE, "like", value.dept_code)
+            })
+            .order_by(t, T::DEPT_CODE)
+            .build_with_page(page.index, page.size, page.total);
+
+        let mut conn = gensql::get_conn().await?;
+
+        let total = match page.total {
+            Some(total) => total as usize,
+            None => conn.query_one(tsql, params.clone()).await?.unwrap_or(0),
+        };
+
+        let list = conn.query_fast(psql, params).await?;
+
+        Ok(PageData { total, list })
+    }
+
+    /// 获取所有部门, 按部门代码排序, 优先从缓存中获取
+    pub async fn select_all() -> DbResult<Arc<Vec<SysDept>>> {
+        let cache_key = tenant::cache_key(CACHE_ALL_KEY);
+        if let Some(list) = gmc::get_cache().get_json(CATEGORY, &cache_key).await {
+            log::trace!("加载部门列表时使用缓存");
+            return Ok(list);
+        }
+
+        let (sql, params) = gensql::SelectSql::new()
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| w.filter("", &TenantFilter))
+            .order_by("", Self::DEPT_CODE)
+            .build();
+
+        let depts = Arc::new(gensql::sql_query_fast(sql, params).await?);
+        if !depts.is_empty() {
+            gmc::get_cache()
+                .put_json(CATEGORY, &cache_key, depts.clone())
+                .await;
+        }
+
+        Ok(depts)
+    }
+
+    /// 查询指定上级部门代码的下级部门最大代码值, 不存在下级部门时返回None
+    pub async fn select_max_code(parent_dept_code: &str) -> DbResult<Option<String>> {
+        let pdc = format!("{}{}", parent_dept_code, "_".repeat(CODE_LEN));
+        let (sql, params) = gensql::SelectSql::new()
+            .select("", &format!("max({})", Self::DEPT_CODE))
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| {
+                w.filter("", &TenantFilter)
+                    .add_value(&format!(" and {} like ?", Self::DEPT_CODE), &pdc)
+            })
+            .build();
+
+        gensql::sql_query_one(sql, params).await
+    }
+
+    /// 查询指定部门代码的下级部门(包括所有层级)数量
+    pub async fn select_descendant_count(dept_code: &str) -> DbResult<u32> {
+        let (sql, params) = gensql::SelectSql::new()
+            .select("", "count(*)")
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| {
+                w.filter("", &TenantFilter).add_value(
+                    &format!(" and {} like ?", Self::DEPT_CODE),
+                    format!("{dept_code}_%"),
+                )
+            })
+            .build();
+
+        Ok(gensql::sql_query_one(sql, params).await?.unwrap_or(0))
+    }
+
+    /// 更新部门记录, 部门代码变更(移动到新的上级部门)时, 将其所有下级部门的代码前缀
+    /// 替换为新的部门代码, 使用事务模式, 更新失败则回滚
+    ///
+    /// Arguments:
+    ///
+    /// * `old_code`: 部门原来的代码
+    ///
+    pub async fn update_with_descendant(mut self, old_code: &str) -> DbResult<bool> {
+        // 记录所属的租户不允许变更
+        self.tenant_id = None;
+        let new_code = self.dept_code.clone();
+
+        let mut trans = gensql::start_transaction().await?;
+        let (sql, params) = self.prepare_update_by_id();
+        gensql::db_log_sql_params(&sql, &params);
+        let updated = trans.exec(&sql, params).await? > 0;
+        if let Some(new_code) = new_code {
+            Self::update_descendant_code(&mut trans, old_code, &new_code).await?;
+        }
+        trans.commit().await?;
+        Self::notify_changed().await;
+
+        Ok(updated)
+    }
+
+    /// 部门移动到新的上级部门后, 将其所有下级部门的代码前缀替换为新的部门代码
+    async fn update_descendant_code(
+        trans: &mut gensql::Transaction<'_>,
+        old_code: &str,
+        new_code: &str,
+    ) -> DbResult<()> {
+        let sql = format!(
+            "update {} set {1} = concat(?, substring({1}, ?)) where {1} like ? and {2} = ?",
+            Self::TABLE_NAME,
+            Self::DEPT_CODE,
+            Self::TENANT_ID,
+        );
+        let params = gensql::to_values![
+            new_code,
+            old_code.len() + 1,
+            format!("{old_code}_%"),
+            tenant::current_or_platform()
+        ];
+        gensql::db_log_sql_params(&sql, &params);
+        trans.exec(sql, params).await?;
+
+        Ok(())
+    }
+
+    /// 批量更新部门排列顺序
+    ///
+    /// Arguments:
+    ///
+    /// * `depts`: 要更新的部门记录(部门id及新的部门代码)
+    ///
+    pub async fn batch_update_rearrange(depts: &[SysDept]) -> DbResult<()> {
+        // 加载原有记录，与要变更的记录比较，只修改有变化的, 不属于当前租户的记录忽略
+        let old_depts = Self::select_all().await?;
+        let old_depts: HashMap<_, _> = old_depts
+            .iter()
+            .map(|v| (v.dept_id.unwrap(), v.dept_code.as_ref().unwrap()))
+            .collect();
+        let depts: Vec<_> = depts
+            .iter()
+            .filter(|v| match old_depts.get(v.dept_id.as_ref().unwrap()) {
+                Some(code) => *code != v.dept_code.as_ref().unwrap(),
+                None => false,
+            })
+            .collect();
+
+        // 更新变化的数据，使用事务模式
+        let sql = format!(
+            "update {} set {} = ? where {} = ?",
+            Self::TABLE_NAME,
+            Self::DEPT_CODE,
+            Self::DEPT_ID
+        );
+        let mut trans = gensql::start_transaction().await?;
+        for item in depts.iter() {
+            let params = gensql::to_values![item.dept_code, item.dept_id];
+            gensql::db_log_sql_params(&sql, &params);
+            trans.exec(&sql, params).await?;
+        }
+        trans.commit().await?;
+        Self::notify_changed().await;
+
+        Ok(())
+    }
+}
+
+/// 将按部门代码排序的部门列表转换为树形结构, 上级部门不存在的部门作为顶级部门
+pub fn to_tree(depts: &[SysDept]) -> Vec<SysDeptVo> {
+    let mut roots: Vec<SysDeptVo> = Vec::new();
+    // 当前节点的路径, 用于查找上级部门
+    let mut path: Vec<&str> = Vec::new();
+
+    for dept in depts {
+        let code = dept.dept_code.as_deref().unwrap_or_default();
+        while let Some(last) = path.last() {
+            if code.len() > last.len() && code.starts_with(last) {
+                break;
+            }
+            path.pop();
+        }
+
+        let node = SysDeptVo {
+            inner: dept.clone(),
+            parent_dept_code: path.last().map(|v| v.to_string()),
+            ..Default::default()
+        };
+        let mut children = &mut roots;
+        for _ in 0..path.len() {
+            children = children
+                .last_mut()
+                .unwrap()
+                .depts
+                .get_or_insert_with(Vec::new);
+        }
+        children.push(node);
+        path.push(code);
+    }
+
+    roots
+}
@@ -9,7 +9,13 @@ use gensql::{
 use httpserver::log_debug;
 use localtime::LocalTime;
 
-use crate::{services::gmc, utils::{consts, IntStr}};
+use crate::{
+    services::{
+        gmc,
+        tenant::{self, TenantFilter},
+    },
+    utils::{consts, IntStr},
+};
 
 use super::{PageData, PageInfo};
 
@@ -36,6 +42,8 @@ pub struct SysDict {
     /// 字典项id
     #[table(id)]
     dict_id: u32,
+    /// 租户id
+    tenant_id: u32,
     /// 字典项类型
     dict_type: u8,
     /// 字典项代码
@@ -57,7 +65,8 @@ pub struct SysDictExt {
 }
 
 impl SysDict {
-    pub async fn insert_with_notify(self) -> DbResult<(u32, u32)> {
+    pub async fn insert_with_notify(mut self) -> DbResult<(u32, u32)> {
+        self.tenant_id = Some(tenant::current_or_platform());
         let dict_type = self.dict_type;
         let ret = self.insert().await;
         if ret.is_ok() {
@@ -66,7 +75,9 @@ impl SysDict {
         ret
     }
 
-    pub async fn update_with_notify(self) -> DbResult<bool> {
+    pub async fn update_with_notify(mut self) -> DbResult<bool> {
+        // 记录所属的租户不允许变更
+        self.tenant_id = None;
         let dict_type = self.dict_type;
         let ret = self.update_by_id().await;
         if ret.is_ok() {
@@ -89,9 +100,10 @@ impl SysDict {
         }
     }
 
+    /// 数据变更通知, 缓存按租户区分
     pub async fn notify_changed(ty: Option<u8>) {
         let ty = match ty {
-            Some(n) => format!("{n}"),
+            Some(n) => tenant::cache_key(&n.to_string()),
             None => String::new(),
         };
         gmc::get_cache().notify(CATEGORY, &ty).await
@@ -109,7 +121,8 @@ impl SysDict {
                 j.on_eq(Self::DICT_ID, t, Self::DICT_TYPE)
             })
             .where_sql(|w| {
-                w.eq_opt(t, Self::DICT_TYPE, query.dict_type)
+                w.filter(t, &TenantFilter)
+                    .eq_opt(t, Self::DICT_TYPE, query.dict_type)
                     .expr(t, Self::DICT_TYPE, "!=", DictType::DictCategory as u8)
                     .like_opt(t, Self::DICT_NAME, query.dict_name)
             })
@@ -131,20 +144,22 @@ impl SysDict {
     /// 返回指定类型的所有字典项
     pub async fn select_by_type(dict_type: u8) -> DbResult<Arc<Vec<SysDict>>> {
         // 优先从缓存中读取
-        let mut buf = itoa::Buffer::new();
-        let dt_str = buf.format(dict_type);
-        if let Some(dicts) = gmc::get_cache().get_json(CATEGORY, dt_str).await {
+        let cache_key = tenant::cache_key(&dict_type.to_string());
+        if let Some(dicts) = gmc::get_cache().get_json(CATEGORY, &cache_key).await {
             return Ok(dicts);
         }
 
         let (sql, params) = gensql::SelectSql::new()
             .from(Self::TABLE_NAME)
-            .where_sql(|w| w.eq("", Self::DICT_TYPE, dict_type))
+            .where_sql(|w| {
+                w.filter("", &TenantFilter)
+                    .eq("", Self::DICT_TYPE, dict_type)
+            })
             .order_by("", Self::DICT_CODE)
             .build();
         let list = Arc::new(gensql::sql_query_fast(&sql, params).await?);
         if !list.is_empty() {
-            gmc::get_cache().put_json(CATEGORY, dt_str, list.clone()).await;
+            gmc::get_cache().put_json(CATEGORY, &cache_key, list.clone()).await;
         }
         Ok(list)
     }
@@ -173,6 +188,7 @@ impl SysDict {
 
     /// 批量更新指定的类别(使用事务进行更新)
     pub async fn batch_by_type(dict_type: u8, dicts: Vec<SysDict>) -> DbResult<()> {
+        let tenant_id = tenant::current_or_platform();
         let old_dicts = Self::select_by_type(dict_type).await?;
         let update_count = std::cmp::min(old_dicts.len(), dicts.len());
         let mut trans = gensql::start_transaction().await?;
@@ -200,6 +216,7 @@ impl SysDict {
             let (sql, params) = BatchInsertSql::new(
                 Self::TABLE_NAME,
                 &[
+                    Self::TENANT_ID,
                     Self::DICT_TYPE,
                     Self::DICT_CODE,
                     Self::DICT_NAME,
@@ -208,6 +225,7 @@ impl SysDict {
             )
             .values(dicts.iter().skip(update_count).map(|v| {
                 vec![
+                    InsertValue::Value(tenant_id.to_value()),
                     InsertValue::Value(dict_type.to_value()),
                     InsertValue::Value(v.dict_code.to_value()),
                     InsertValue::Value(v.dict_name.to_value()),
@@ -1,9 +1,11 @@
 //! 系统审计日志表
 //!
 
-use super::{PageData, PageInfo};
+use super::{sys_user::SysUser, PageData, PageInfo};
+use crate::services::tenant::TenantFilter;
 use gensql::{table, DbResult, Queryable};
 use localtime::LocalTime;
+use serde::Deserialize;
 
 /// 系统接口表
 #[table("t_sys_log")]
@@ -11,6 +13,8 @@ pub struct SysLog {
     /// 配置项id
     #[table(id)]
     log_id: u32,
+    /// 租户id
+    tenant_id: u32,
     /// 配置项分类
     opera_type: String,
     /// 配置项名称
@@ -21,16 +25,37 @@ pub struct SysLog {
     opera_text: String,
 }
 
+#[table]
+pub struct SysLogExt {
+    #[serde(flatten)]
+    inner: SysLog,
+
+    /// 操作员用户名
+    #[table(not_field)]
+    username: String,
+}
+
+/// 审计日志查询条件
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SysLogQuery {
+    /// 操作员id
+    pub user_id: Option<u32>,
+    /// 操作类别, 前缀匹配, 如 "用户" 可匹配 "用户:添加", "用户:修改" 等
+    pub opera_type: Option<String>,
+    /// 操作内容, 模糊匹配
+    pub opera_text: Option<String>,
+    /// 起始时间(包含)
+    pub begin_time: Option<LocalTime>,
+    /// 截止时间(包含)
+    pub end_time: Option<LocalTime>,
+}
+
 impl SysLog {
     /// 查询记录
-    pub async fn select_page(self, page: PageInfo) -> DbResult<PageData<SysLog>> {
-        let (tsql, psql, params) = gensql::SelectSql::new()
-            .from(Self::TABLE_NAME)
-            .where_sql(|w| {
-                w.eq_opt("", Self::OPERA_TYPE, self.opera_type)
-                    .eq_opt("", Self::USER_ID, self.user_id)
-                    .like_opt("", Self::OPERA_TEXT, self.opera_text)
-            })
+    pub async fn select_page(query: SysLogQuery, page: PageInfo) -> DbResult<PageData<SysLogExt>> {
+        let (tsql, psql, params) = Self::select_sql(query, None)
+            .order_by_with_iter([("t", Self::LOG_ID, true)])
             .build_with_page(page.index, page.size, page.total);
 
         let mut conn = gensql::get_conn().await?;
@@ -45,10 +70,42 @@ impl SysLog {
         Ok(PageData { total, list })
     }
 
+    /// 按日志id升序分批读取记录, 用于大数据量导出
+    ///
+    /// Arguments:
+    ///
+    /// * `query`: 查询条件
+    /// * `after_id`: 只返回日志id大于该值的记录
+    /// * `count`: 本批次读取的最大记录数
+    ///
+    pub async fn select_batch(query: SysLogQuery, after_id: u32, count: u32) -> DbResult<Vec<SysLogExt>> {
+        let (sql, params) = Self::select_sql(query, Some(after_id))
+            .order_by("t", Self::LOG_ID)
+            .limits(0, count)
+            .build();
+
+        gensql::sql_query_fast(&sql, params).await
+    }
+
+    /// 统计符合条件的记录数
+    pub async fn select_count(query: SysLogQuery) -> DbResult<usize> {
+        let (tsql, _, params) = Self::select_sql(query, None).build_with_page(1, 1, None);
+        Ok(gensql::sql_query_one(tsql, params).await?.unwrap_or(0))
+    }
+
     /// 添加审计日志
-    pub async fn append(category: String, user_id: u32, data: String) -> DbResult<()> {
+    ///
+    /// Arguments:
+    ///
+    /// * `tenant_id`: 租户id, 审计日志异步写入, 需要在记录日志时确定租户
+    /// * `category`: 日志分类
+    /// * `user_id`: 操作员id
+    /// * `data`: 日志内容
+    ///
+    pub async fn append(tenant_id: u32, category: String, user_id: u32, data: String) -> DbResult<()> {
         let log = Self {
             log_id: None,
+            tenant_id: Some(tenant_id),
             opera_type: Some(category),
             user_id: Some(user_id),
             created_time: Some(LocalTime::now()),
@@ -57,4 +114,25 @@ impl SysLog {
 
         log.insert().await.map(|_| ())
     }
+
+    fn select_sql(query: SysLogQuery, after_id: Option<u32>) -> gensql::SelectSql {
+        let (t, t1) = ("t", "t1");
+
+        gensql::SelectSql::new()
+            .select_all_with_table(t)
+            .select_as(t1, SysUser::USERNAME, SysLogExt::USERNAME)
+            .from_as(Self::TABLE_NAME, t)
+            .left_join(SysUser::TABLE_NAME, t1, |j| {
+                j.on_eq(SysUser::USER_ID, t, Self::USER_ID)
+            })
+            .where_sql(|w| {
+                w.filter(t, &TenantFilter)
+                    .eq_opt(t, Self::USER_ID, query.user_id)
+                    .like_right_opt(t, Self::OPERA_TYPE, query.opera_type)
+                    .like_opt(t, Self::OPERA_TEXT, query.opera_text)
+                    .expr_opt(t, Self::CREATED_TIME, ">=", query.begin_time)
+                    .expr_opt(t, Self::CREATED_TIME, "<=", query.end_time)
+                    .expr_opt(t, Self::LOG_ID, ">", after_id)
+            })
+    }
 }
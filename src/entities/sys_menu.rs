@@ -7,7 +7,11 @@ use crate::{
         sys_dict::{DictType, SysDict},
         sys_permission::SysPermission,
     },
-    services::gmc,
+    services::{
+        gmc,
+        tenant::{self, TenantFilter},
+    },
+    utils::code_tree::CODE_LEN,
 };
 use gensql::{table, DbResult, Queryable};
 use localtime::LocalTime;
@@ -24,6 +28,8 @@ pub struct SysMenu {
     /// 菜单id
     #[table(id)]
     menu_id: u32,
+    /// 租户id
+    tenant_id: u32,
     /// 客户端类型
     client_type: i16,
     /// 菜单代码
@@ -69,7 +75,8 @@ pub struct SysMenuVo {
 }
 
 impl SysMenu {
-    pub async fn insert_with_notify(self) -> DbResult<(u32, u32)> {
+    pub async fn insert_with_notify(mut self) -> DbResult<(u32, u32)> {
+        self.tenant_id = Some(tenant::current_or_platform());
         let client_type = self.client_type;
         let ret = self.insert().await;
         if ret.is_ok() {
@@ -78,7 +85,9 @@ impl SysMenu {
         ret
     }
 
-    pub async fn update_with_notify(self) -> DbResult<bool> {
+    pub async fn update_with_notify(mut self) -> DbResult<bool> {
+        // 记录所属的租户不允许变更
+        self.tenant_id = None;
         let client_type = self.client_type;
         let ret = self.update_by_id().await;
         if ret.is_ok() {
@@ -101,10 +110,10 @@ impl SysMenu {
         }
     }
 
-    /// 数据变更通知
+    /// 数据变更通知, 缓存按租户区分
     pub async fn notify_changed(ty: Option<i16>) {
         let ty = match ty {
-            Some(n) => format!("{n}"),
+            Some(n) => tenant::cache_key(&n.to_string()),
             None => String::new(),
         };
         crate::services::gmc::get_cache().notify(CATEGORY, &ty).await
@@ -139,11 +148,13 @@ impl SysMenu {
             })
             .left_join(T::TABLE_NAME, t1, |j| {
                 j.on(&format!(
-                    "{}.{} = left({}.{1}, length({2}.{1}) - 2)",
+                    "{}.{} = left({}.{1}, length({2}.{1}) - {3})",
                     t1,
                     T::MENU_CODE,
-                    t
+                    t,
+                    CODE_LEN
                 ))
+                .on_eq(T::TENANT_ID, t, T::TENANT_ID)
             })
             .left_join(C::TABLE_NAME, c, |j| {
                 j.on_eq(C::DICT_CODE, t, T::CLIENT_TYPE)
@@ -154,7 +165,8 @@ impl SysMenu {
                     .on_eq_val(G::DICT_TYPE, DictType::PermissionGroup as u16)
             })
             .where_sql(|w| {
-                w.eq_opt(t, T::CLIENT_TYPE, value.client_type)
+                w.filter(t, &TenantFilter)
+                    .eq_opt(t, T::CLIENT_TYPE, value.client_type)
                     .eq_opt(t, T::PERMISSION_CODE, value.permission_code)
                     .like_opt(t, T::MENU_NAME, value.menu_name)
                     .like_opt(t, T::MENU_LINK, value.menu_link)
@@ -181,9 +193,8 @@ impl SysMenu {
     ///
     /// * `client_type`: 客户端类型，表示正在检索其菜单项的客户端类型。该函数获取菜单项
     pub async fn select_by_client_type(client_type: i16) -> DbResult<Arc<Vec<SysMenu>>> {
-        let mut buf = itoa::Buffer::new();
-        let ct_str = buf.format(client_type);
-        if let Some(list) = gmc::get_cache().get_json(CATEGORY, ct_str).await {
+        let cache_key = tenant::cache_key(&client_type.to_string());
+        if let Some(list) = gmc::get_cache().get_json(CATEGORY, &cache_key).await {
             log::trace!("加载菜单项时使用缓存: client_type = {}", client_type);
             return Ok(list);
         }
@@ -191,7 +202,10 @@ impl SysMenu {
         // 使用gensql构建SQL查询语句和参数
         let (sql, params) = gensql::SelectSql::new()
             .from(SysMenu::TABLE_NAME)
-            .where_sql(|w| w.eq("", SysMenu::CLIENT_TYPE, client_type))
+            .where_sql(|w| {
+                w.filter("", &TenantFilter)
+                    .eq("", SysMenu::CLIENT_TYPE, client_type)
+            })
             .order_by("", SysMenu::MENU_CODE)
             .build();
 
@@ -199,7 +213,7 @@ impl SysMenu {
         let menus = Arc::new(gensql::sql_query_fast(sql, params).await?);
         // 将查询结果写入缓存
         if !menus.is_empty() {
-            gmc::get_cache().put_json(CATEGORY, ct_str, menus.clone()).await;
+            gmc::get_cache().put_json(CATEGORY, &cache_key, menus.clone()).await;
         }
 
         // 返回查询结果
@@ -211,36 +225,35 @@ impl SysMenu {
         // 构建SQL语句
         let (sql, params) = gensql::SelectSql::new()
             .from(Self::TABLE_NAME)
+            .where_sql(|w| w.filter("", &TenantFilter))
             .order_by("", Self::MENU_CODE)
             .build();
         // 执行SQL查询并返回结果
         gensql::sql_query_fast(sql, params).await
     }
 
-    /// 获取顶级菜单列表
-    pub async fn select_top_level(use_top_level: bool) -> DbResult<Vec<SysMenu>> {
+    /// 获取所有可作为上级菜单的目录菜单(链接为"#"的菜单), 首项为虚拟的顶级菜单
+    pub async fn select_parents() -> DbResult<Vec<SysMenu>> {
         // 构建SQL语句
         let (sql, params) = gensql::SelectSql::new()
             .from(Self::TABLE_NAME)
-            .where_sql(|w| w.add_sql(&format!("and {} like '__'", Self::MENU_CODE)))
+            .where_sql(|w| w.filter("", &TenantFilter).eq("", Self::MENU_LINK, "#"))
+            .order_by("", Self::MENU_CODE)
             .build();
 
         // 执行SQL查询
         let mut list = gensql::sql_query_fast(sql, params).await?;
 
-        if use_top_level {
-            list.insert(
-                0,
-                SysMenu {
-                    menu_code: Some(TOP_MENU_CODE.to_string()),
-                    menu_name: Some(TOP_MENU_NAME.to_string()),
-                    menu_link: Some("#".to_string()),
-                    client_type: Some(TOP_MENU_CLIENT_TYPE),
-                    ..Default::default()
-                },
-            );
-            list.retain(|v| v.menu_link.as_ref().unwrap() == "#");
-        };
+        list.insert(
+            0,
+            SysMenu {
+                menu_code: Some(TOP_MENU_CODE.to_string()),
+                menu_name: Some(TOP_MENU_NAME.to_string()),
+                menu_link: Some("#".to_string()),
+                client_type: Some(TOP_MENU_CLIENT_TYPE),
+                ..Default::default()
+            },
+        );
 
         Ok(list)
     }
@@ -256,18 +269,63 @@ impl SysMenu {
     /// * `Option<String>` 子菜单最大代码值
     /// * `None` 不存在子菜单
     pub async fn select_max_code(parent_menu_code: &str) -> DbResult<Option<String>> {
-        let pmc = format!("{}__", parent_menu_code);
+        let pmc = format!("{}{}", parent_menu_code, "_".repeat(CODE_LEN));
         let (sql, params) = gensql::SelectSql::new()
             .select("", &format!("max({})", Self::MENU_CODE))
             .from(Self::TABLE_NAME)
-            .where_sql(
-                |w| w.add_value(&format!("{} like ?", Self::MENU_CODE), &pmc), // 添加条件，代码以指定字符串开头
-            )
+            .where_sql(|w| {
+                // 添加条件，代码以指定字符串开头
+                w.filter("", &TenantFilter)
+                    .add_value(&format!(" and {} like ?", Self::MENU_CODE), &pmc)
+            })
             .build();
 
         gensql::sql_query_one(sql, params).await // 执行查询并等待结果
     }
 
+    /// 查询指定菜单代码的下级菜单(包括所有层级)数量
+    pub async fn select_descendant_count(menu_code: &str) -> DbResult<u32> {
+        let (sql, params) = gensql::SelectSql::new()
+            .select("", "count(*)")
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| {
+                w.filter("", &TenantFilter).add_value(
+                    &format!(" and {} like ?", Self::MENU_CODE),
+                    format!("{menu_code}_%"),
+                )
+            })
+            .build();
+
+        Ok(gensql::sql_query_one(sql, params).await?.unwrap_or(0))
+    }
+
+    /// 菜单移动到新的上级菜单后, 将其所有下级菜单的代码前缀替换为新的菜单代码
+    ///
+    /// Arguments:
+    ///
+    /// * `old_code`: 菜单原来的代码
+    /// * `new_code`: 菜单新的代码
+    ///
+    pub async fn update_descendant_code(old_code: &str, new_code: &str) -> DbResult<()> {
+        let sql = format!(
+            "update {} set {1} = concat(?, substring({1}, ?)) where {1} like ? and {2} = ?",
+            Self::TABLE_NAME,
+            Self::MENU_CODE,
+            Self::TENANT_ID,
+        );
+        let params = gensql::to_values![
+            new_code,
+            old_code.len() + 1,
+            format!("{old_code}_%"),
+            tenant::current_or_platform()
+        ];
+        gensql::db_log_sql_params(&sql, &params);
+        gensql::sql_exec(sql, params).await?;
+        Self::notify_changed(None).await;
+
+        Ok(())
+    }
+
     /// 批量更新菜单排列顺序
     ///
     /// Arguments:
@@ -276,7 +334,7 @@ impl SysMenu {
     /// * `menus`: 要更新的菜单记录
     ///
     pub async fn batch_update_rearrange(client_type: i16, menus: &[SysMenu]) -> DbResult<()> {
-        // 加载原有记录，与要变更的记录比较，只修改有变化的
+        // 加载原有记录，与要变更的记录比较，只修改有变化的, 不属于当前租户及分类的记录忽略
         let old_menus = Self::select_by_client_type(client_type).await?;
         let old_menus: HashMap<_, _> = old_menus
             .iter()
@@ -286,7 +344,7 @@ impl SysMenu {
             .iter()
             .filter(|v| match old_menus.get(v.menu_id.as_ref().unwrap()) {
                 Some(code) => *code != v.menu_code.as_ref().unwrap(),
-                None => true,
+                None => false,
             })
             .collect();
 
@@ -308,5 +366,4 @@ impl SysMenu {
 
         Ok(())
     }
-
 }
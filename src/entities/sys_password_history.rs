@@ -0,0 +1,95 @@
+//! 用户口令历史表
+use gensql::{table, DbResult};
+use localtime::LocalTime;
+
+/// 用户口令历史表, 用于限制重复使用最近的口令及计算口令有效期
+#[table("t_sys_password_history")]
+pub struct SysPasswordHistory {
+    /// 历史记录id
+    #[table(id)]
+    history_id: u32,
+    /// 用户id
+    user_id: u32,
+    /// 加密后的口令
+    password: String,
+    /// 是否已过期, 1: 管理员设置的缺省口令, 用户登录后需要立即修改
+    expired: u8,
+    /// 创建时间
+    created_time: LocalTime,
+}
+
+impl SysPasswordHistory {
+    /// 查询用户最近使用的口令
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: 用户id
+    /// * `count`: 最多返回的记录数
+    ///
+    pub async fn select_recent(user_id: u32, count: u32) -> DbResult<Vec<SysPasswordHistory>> {
+        let (sql, params) = gensql::SelectSql::new()
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| w.eq("", Self::USER_ID, user_id))
+            .order_by_with_iter([("", Self::HISTORY_ID, true)])
+            .limits(0, count)
+            .build();
+
+        gensql::sql_query_fast(sql, params).await
+    }
+
+    /// 查询用户最后一次修改口令的记录
+    pub async fn select_last(user_id: u32) -> DbResult<Option<SysPasswordHistory>> {
+        let (sql, params) = gensql::SelectSql::new()
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| w.eq("", Self::USER_ID, user_id))
+            .order_by_with_iter([("", Self::HISTORY_ID, true)])
+            .limits(0, 1)
+            .build();
+
+        gensql::sql_query_one(sql, params).await
+    }
+
+    /// 添加口令历史记录, 并删除超出保留数量的旧记录
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: 用户id
+    /// * `password`: 加密后的口令
+    /// * `expired`: 口令是否需要在登录后立即修改
+    /// * `keep`: 保留的历史记录数量, 最少保留1条用于计算口令有效期
+    ///
+    pub async fn append(user_id: u32, password: String, expired: bool, keep: u32) -> DbResult<()> {
+        let rec = Self {
+            history_id: None,
+            user_id: Some(user_id),
+            password: Some(password),
+            expired: Some(expired as u8),
+            created_time: Some(LocalTime::now()),
+        };
+        rec.insert().await?;
+
+        // mysql不支持在子查询中直接使用limit, 需要再嵌套一层
+        let sql = format!(
+            "delete from {0} where {1} = ? and {2} not in \
+                (select {2} from (select {2} from {0} where {1} = ? order by {2} desc limit ?) t)",
+            Self::TABLE_NAME,
+            Self::USER_ID,
+            Self::HISTORY_ID,
+        );
+        let params = gensql::to_values![user_id, user_id, keep.max(1)];
+        gensql::db_log_sql_params(&sql, &params);
+        gensql::sql_exec(sql, params).await?;
+
+        Ok(())
+    }
+
+    /// 删除用户的所有口令历史记录
+    pub async fn delete_by_user(user_id: u32) -> DbResult<u32> {
+        let (sql, params) = gensql::DeleteSql::new(Self::TABLE_NAME, |w| {
+            w.eq("", Self::USER_ID, user_id)
+        })
+        .build();
+
+        gensql::sql_exec(sql, params).await
+    }
+}
@@ -1,7 +1,11 @@
 //! 角色表
 use super::{PageData, PageInfo};
-use crate::{entities::sys_permission::SysPermission, utils::{bits, consts}};
-use gensql::{table, DbResult, Queryable};
+use crate::{
+    entities::{sys_permission::SysPermission, sys_user::SysUser, sys_user_role::SysUserRole},
+    services::tenant::{self, TenantFilter},
+    utils::{bits, consts},
+};
+use gensql::{table, DbResult, DeleteSql, Queryable};
 use localtime::LocalTime;
 
 #[table("t_sys_role")]
@@ -9,16 +13,50 @@ pub struct SysRole {
     /// 角色id
     #[table(id)]
     role_id: u32,
+    /// 租户id
+    tenant_id: u32,
     /// 角色类型
     role_type: String,
     /// 角色名称
     role_name: String,
     /// 角色权限位集
     permissions: String,
+    /// 数据权限范围, 参见DataScopeType
+    data_scope: u8,
+    /// 自定义数据权限的部门id列表, 逗号分隔
+    data_depts: String,
     /// 更新时间
     updated_time: LocalTime,
 }
 
+/// 角色的数据权限范围
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DataScopeType {
+    /// 全部数据
+    All,
+    /// 本部门
+    Dept,
+    /// 本部门及下级部门
+    DeptAndSub,
+    /// 仅本人
+    SelfOnly,
+    /// 自定义部门
+    Custom,
+}
+
+impl DataScopeType {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::All),
+            1 => Some(Self::Dept),
+            2 => Some(Self::DeptAndSub),
+            3 => Some(Self::SelfOnly),
+            4 => Some(Self::Custom),
+            _ => None,
+        }
+    }
+}
+
 #[table]
 pub struct SysRoleVo {
     #[serde(flatten)]
@@ -29,7 +67,8 @@ pub struct SysRoleVo {
 }
 
 impl SysRole {
-    pub async fn insert_with_notify(self) -> DbResult<(u32, u32)> {
+    pub async fn insert_with_notify(mut self) -> DbResult<(u32, u32)> {
+        self.tenant_id = Some(tenant::current_or_platform());
         let role_id = self.role_id;
         let ret = self.insert().await;
         if ret.is_ok() {
@@ -38,7 +77,9 @@ impl SysRole {
         ret
     }
 
-    pub async fn update_with_notify(self) -> DbResult<bool> {
+    pub async fn update_with_notify(mut self) -> DbResult<bool> {
+        // 记录所属的租户不允许变更
+        self.tenant_id = None;
         let role_id = self.role_id;
         let ret = self.update_by_id().await;
         if ret.is_ok() {
@@ -47,18 +88,31 @@ impl SysRole {
         ret
     }
 
+    /// 删除角色并清除该角色的所有用户关联记录, 两者在同一事务中执行,
+    /// 提交后通知角色及所有用户信息变化
     pub async fn delete_with_notify(id: u32) -> DbResult<bool> {
-        match Self::select_by_id(id).await? {
-            Some(record) => {
-                let role_id = record.role_id;
-                let ret = Self::delete_by_id(id).await;
-                if ret.is_ok() {
-                    Self::notify_changed(role_id).await;
-                }
-                ret
-            }
-            None => Ok(false),
+        let (sql, params) =
+            DeleteSql::new(Self::TABLE_NAME, |w| w.eq("", Self::ROLE_ID, id)).build();
+        let (ur_sql, ur_params) = DeleteSql::new(SysUserRole::TABLE_NAME, |w| {
+            w.eq("", SysUserRole::ROLE_ID, id)
+        })
+        .build();
+
+        let mut trans = gensql::start_transaction().await?;
+        gensql::db_log_sql_params(&sql, &params);
+        let count = trans.exec(sql, params).await?;
+        gensql::db_log_sql_params(&ur_sql, &ur_params);
+        let ur_count = trans.exec(ur_sql, ur_params).await?;
+        trans.commit().await?;
+
+        if count > 0 {
+            Self::notify_changed(Some(id)).await;
+        }
+        if ur_count > 0 {
+            SysUser::notify_changed(None).await;
         }
+
+        Ok(count > 0)
     }
 
     pub async fn notify_changed(id: Option<u32>) {
@@ -74,11 +128,9 @@ impl SysRole {
         let (tsql, psql, params) = gensql::SelectSql::new()
             .from(Self::TABLE_NAME)
             .where_sql(|w| {
-                w.like_opt("", Self::ROLE_TYPE, value.role_type).like_opt(
-                    "",
-                    Self::ROLE_NAME,
-                    value.role_name,
-                )
+                w.filter("", &TenantFilter)
+                    .like_opt("", Self::ROLE_TYPE, value.role_type)
+                    .like_opt("", Self::ROLE_NAME, value.role_name)
             })
             .build_with_page(page.index, page.size, page.total);
 
@@ -114,7 +166,7 @@ impl SysRole {
         Ok(PageData { total, list })
     }
 
-    /// 加载所有记录
+    /// 加载所有记录, 在请求上下文中只加载当前租户的记录
     pub async fn select_all() -> DbResult<Vec<SysRole>> {
         Self::select_by_role_type(None).await
     }
@@ -123,10 +175,44 @@ impl SysRole {
     pub async fn select_by_role_type(role_type: Option<String>) -> DbResult<Vec<SysRole>> {
         let (sql, params) = gensql::SelectSql::new()
             .from(Self::TABLE_NAME)
-            .where_sql(|w| w.eq_opt("", Self::ROLE_TYPE, role_type))
+            .where_sql(|w| w.filter("", &TenantFilter).eq_opt("", Self::ROLE_TYPE, role_type))
             .build();
 
         gensql::sql_query_fast(sql, params).await
     }
 
+    /// 批量加载指定id的角色列表, 不存在的角色忽略
+    ///
+    /// Arguments:
+    ///
+    /// * `role_ids`: 角色id列表
+    ///
+    pub async fn select_by_ids(role_ids: &[u32]) -> DbResult<Vec<SysRole>> {
+        // 空列表不会生成in条件, 需提前返回, 避免查询出所有记录
+        if role_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (sql, params) = gensql::SelectSql::new()
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| w.in_("", Self::ROLE_ID, role_ids))
+            .build();
+
+        gensql::sql_query_fast(sql, params).await
+    }
+
+    /// 获取角色的数据权限范围, 未设置时为全部数据
+    pub fn data_scope_type(&self) -> DataScopeType {
+        self.data_scope
+            .and_then(DataScopeType::from_u8)
+            .unwrap_or(DataScopeType::All)
+    }
+
+    /// 获取自定义数据权限的部门id列表
+    pub fn data_dept_ids(&self) -> Vec<u32> {
+        match &self.data_depts {
+            Some(s) => s.split(',').filter_map(|v| v.trim().parse().ok()).collect(),
+            None => Vec::new(),
+        }
+    }
 }
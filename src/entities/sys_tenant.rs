@@ -0,0 +1,99 @@
+//! 租户表
+use std::sync::Arc;
+
+use super::{PageData, PageInfo};
+use crate::{services::gmc, utils::consts};
+use gensql::{table, DbResult, Queryable};
+use localtime::LocalTime;
+
+const CATEGORY: &str = consts::gmc::SYS_TENANT;
+
+/// 租户表
+#[table("t_sys_tenant")]
+pub struct SysTenant {
+    /// 租户id
+    #[table(id)]
+    tenant_id: u32,
+    /// 租户名称
+    tenant_name: String,
+    /// 禁用标志, 0: 启用, 1: 禁用
+    disabled: u8,
+    /// 租户备注
+    tenant_remark: String,
+    /// 更新时间
+    updated_time: LocalTime,
+    /// 创建时间
+    created_time: LocalTime,
+}
+
+impl SysTenant {
+    pub async fn insert_with_notify(self) -> DbResult<(u32, u32)> {
+        let ret = self.insert().await;
+        if let Ok((_, id)) = ret {
+            Self::notify_changed(Some(id)).await;
+        }
+        ret
+    }
+
+    pub async fn update_with_notify(self) -> DbResult<bool> {
+        let tenant_id = self.tenant_id;
+        let ret = self.update_by_id().await;
+        if ret.is_ok() {
+            Self::notify_changed(tenant_id).await;
+        }
+        ret
+    }
+
+    /// 数据变更通知
+    pub async fn notify_changed(id: Option<u32>) {
+        let id = match id {
+            Some(n) => format!("{n}"),
+            None => String::new(),
+        };
+        gmc::get_cache().notify(CATEGORY, &id).await
+    }
+
+    /// 查询记录
+    pub async fn select_page(value: SysTenant, page: PageInfo) -> DbResult<PageData<SysTenant>> {
+        let (tsql, psql, params) = gensql::SelectSql::new()
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| {
+                w.eq_opt("", Self::DISABLED, value.disabled)
+                    .like_opt("", Self::TENANT_NAME, value.tenant_name)
+                    .like_opt("", Self::TENANT_REMARK, value.tenant_remark)
+            })
+            .build_with_page(page.index, page.size, page.total);
+
+        let mut conn = gensql::get_conn().await?;
+
+        let total = match page.total {
+            Some(total) => total as usize,
+            None => conn.query_one(tsql, params.clone()).await?.unwrap_or(0),
+        };
+
+        let list = conn.query_fast(psql, params).await?;
+
+        Ok(PageData { total, list })
+    }
+
+    /// 按id查询租户, 优先从缓存中读取, 用于每次请求时的租户状态校验
+    pub async fn select_by_id_with_cache(id: u32) -> DbResult<Option<Arc<SysTenant>>> {
+        let mut buf = itoa::Buffer::new();
+        let cache_key = buf.format(id);
+
+        if let Some(rec) = gmc::get_cache().get_json(CATEGORY, cache_key).await {
+            return Ok(Some(rec));
+        }
+
+        match Self::select_by_id(id).await? {
+            Some(rec) => {
+                let rec = Arc::new(rec);
+                gmc::get_cache()
+                    .put_json(CATEGORY, cache_key, rec.clone())
+                    .await;
+                Ok(Some(rec))
+            }
+            None => Ok(None),
+        }
+    }
+}
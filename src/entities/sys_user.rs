@@ -1,10 +1,19 @@
 //! 用户表
-use super::{sys_role::SysRole, PageData, PageInfo};
+use super::{
+    sys_password_history::SysPasswordHistory, sys_role::SysRole, sys_user_role::SysUserRole,
+    PageData, PageInfo,
+};
 use crate::{
     entities::sys_user_state::SysUserState,
-    services::{gmc, uri},
+    services::{
+        data_scope::DataScope,
+        gmc, jwt_keys,
+        login_lock::{self, LoginLock},
+        metrics,
+        tenant::{self, TenantFilter},
+    },
     utils::{
-        self, consts, time::{gen_time_desc, unix_timestamp}
+        self, audit, time::{gen_time_desc, unix_timestamp}
     },
     AppConf, AppGlobal,
 };
@@ -13,12 +22,9 @@ use base64::{engine::general_purpose, Engine};
 use gensql::{table, DbResult, Queryable};
 use httpserver::http_bail;
 use localtime::LocalTime;
-use std::{net::Ipv4Addr, sync::Arc};
+use serde::Deserialize;
+use std::{net::IpAddr, sync::Arc};
 
-/// 允许最大的登录失败次数
-const MAX_FAIL_COUNT: u32 = 10;
-/// 登录失败达到限制值后的禁止登录时长(单位: 秒)
-const DISABLE_LOGIN_TTL: u32 = 300;
 const CATEGORY: &str = utils::consts::gmc::SYS_USER;
 
 /// 登录账号类型
@@ -39,14 +45,28 @@ pub enum DisabledType {
     Deleted,
 }
 
+/// 用户查询条件
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SysUserQuery {
+    #[serde(flatten)]
+    pub inner: SysUser,
+    /// 为true时只查询已删除的用户, 缺省时查询结果不包含已删除的用户
+    pub deleted: Option<bool>,
+}
+
 /// 系统接口表
 #[table("t_sys_user")]
 pub struct SysUser {
     /// 用户id
     #[table(id)]
     user_id: u32,
+    /// 租户id
+    tenant_id: u32,
     /// 角色id
     role_id: u32,
+    /// 部门id
+    dept_id: u32,
     /// 用户头像
     icon_id: String,
     /// 禁用标志, 0: 启用, 1: 禁用
@@ -68,7 +88,8 @@ pub struct SysUser {
 }
 
 impl SysUser {
-    pub async fn insert_with_notify(self) -> DbResult<(u32, u32)> {
+    pub async fn insert_with_notify(mut self) -> DbResult<(u32, u32)> {
+        self.tenant_id = Some(tenant::current_or_platform());
         let user_id = self.user_id;
         let ret = self.insert().await;
         if ret.is_ok() {
@@ -77,7 +98,9 @@ impl SysUser {
         ret
     }
 
-    pub async fn update_with_notify(self) -> DbResult<bool> {
+    pub async fn update_with_notify(mut self) -> DbResult<bool> {
+        // 记录所属的租户不允许变更
+        self.tenant_id = None;
         let user_id = self.user_id;
         let ret = self.update_by_id().await;
         if ret.is_ok() {
@@ -86,6 +109,17 @@ impl SysUser {
         ret
     }
 
+    /// 将用户标记为已删除, 保留用户记录以便恢复, 用户名等账号在彻底删除前仍被占用
+    pub async fn soft_delete_with_notify(id: u32) -> DbResult<bool> {
+        Self::update_disabled_with_notify(id, DisabledType::Deleted).await
+    }
+
+    /// 恢复已删除的用户, 恢复后的用户状态为正常
+    pub async fn restore_with_notify(id: u32) -> DbResult<bool> {
+        Self::update_disabled_with_notify(id, DisabledType::Normal).await
+    }
+
+    /// 从数据库中彻底删除用户记录
     pub async fn delete_with_notify(id: u32) -> DbResult<bool> {
         match Self::select_by_id(id).await? {
             Some(record) => {
@@ -100,6 +134,17 @@ impl SysUser {
         }
     }
 
+    // 更新用户状态, 同时更新修改时间使已签发的令牌失效
+    async fn update_disabled_with_notify(id: u32, disabled: DisabledType) -> DbResult<bool> {
+        let user = SysUser {
+            user_id: Some(id),
+            disabled: Some(disabled as u8),
+            updated_time: Some(LocalTime::now()),
+            ..Default::default()
+        };
+        user.update_with_notify().await
+    }
+
     pub async fn notify_changed(id: Option<u32>) {
         let id = match id {
             Some(n) => format!("{n}"),
@@ -109,25 +154,20 @@ impl SysUser {
     }
 
     /// 查询记录
-    pub async fn select_page(value: SysUser, page: PageInfo) -> DbResult<PageData<SysUser>> {
-        let (tsql, psql, params) = gensql::SelectSql::new()
-            .from(Self::TABLE_NAME)
-            .where_sql(|w| {
-                w.eq_opt("", Self::ROLE_ID, value.role_id)
-                    .eq_opt("", Self::DISABLED, value.disabled)
-                    .expr_if(
-                        value.disabled.is_none(),
-                        "",
-                        Self::DISABLED,
-                        "!=",
-                        DisabledType::Deleted as u8,
-                    )
-                    .like_opt("", Self::USERNAME, value.username)
-                    .like_opt("", Self::NICKNAME, value.nickname)
-                    .like_opt("", Self::MOBILE, value.mobile)
-                    .like_opt("", Self::EMAIL, value.email)
-            })
-            .build_with_page(page.index, page.size, page.total);
+    ///
+    /// Arguments:
+    ///
+    /// * `value`: 查询参数
+    /// * `page`: 查询分页参数
+    /// * `scope`: 当前用户的数据权限范围
+    ///
+    pub async fn select_page(
+        value: SysUserQuery,
+        page: PageInfo,
+        scope: &DataScope,
+    ) -> DbResult<PageData<SysUser>> {
+        let (tsql, psql, params) =
+            Self::select_sql(value, scope, None).build_with_page(page.index, page.size, page.total);
 
         let mut conn = gensql::get_conn().await?;
 
@@ -141,6 +181,99 @@ impl SysUser {
         Ok(PageData { total, list })
     }
 
+    /// 按用户id顺序分批查询记录, 用于数据导出
+    ///
+    /// Arguments:
+    ///
+    /// * `value`: 查询参数
+    /// * `scope`: 当前用户的数据权限范围
+    /// * `after_id`: 只查询用户id大于该值的记录
+    /// * `count`: 最多返回的记录数
+    ///
+    pub async fn select_batch(
+        value: SysUserQuery,
+        scope: &DataScope,
+        after_id: u32,
+        count: u32,
+    ) -> DbResult<Vec<SysUser>> {
+        let (sql, params) = Self::select_sql(value, scope, Some(after_id))
+            .order_by("", Self::USER_ID)
+            .limits(0, count)
+            .build();
+
+        gensql::sql_query_fast(sql, params).await
+    }
+
+    /// 统计符合条件的记录数
+    ///
+    /// Arguments:
+    ///
+    /// * `value`: 查询参数
+    /// * `scope`: 当前用户的数据权限范围
+    ///
+    pub async fn select_count(value: SysUserQuery, scope: &DataScope) -> DbResult<usize> {
+        let (tsql, _, params) = Self::select_sql(value, scope, None).build_with_page(1, 1, None);
+        Ok(gensql::sql_query_one(tsql, params).await?.unwrap_or(0))
+    }
+
+    /// 批量添加用户及其口令历史记录, 使用事务进行添加, 任意记录失败则全部回滚
+    ///
+    /// Arguments:
+    ///
+    /// * `list`: 待添加的用户列表, 口令字段需要是加密后的口令
+    /// * `default_pwd`: 加密后的缺省口令, 使用该口令的用户登录后需要立即修改口令
+    ///
+    /// Returns:
+    ///
+    /// 新增用户的id列表, 顺序与参数列表一致
+    pub async fn batch_insert_with_notify(
+        list: Vec<SysUser>,
+        default_pwd: &str,
+    ) -> DbResult<Vec<u32>> {
+        let tenant_id = tenant::current_or_platform();
+        let now = LocalTime::now();
+        let mut ids = Vec::with_capacity(list.len());
+        let mut trans = gensql::start_transaction().await?;
+
+        for mut user in list {
+            user.tenant_id = Some(tenant_id);
+            let password = user.password.clone();
+            let (sql, params) = user.prepare_insert();
+            gensql::db_log_sql_params(&sql, &params);
+            let user_id = trans.insert(sql, params).await?.1;
+
+            let expired = password.as_deref() == Some(default_pwd);
+            let history = SysPasswordHistory {
+                history_id: None,
+                user_id: Some(user_id),
+                password,
+                expired: Some(expired as u8),
+                created_time: Some(now.clone()),
+            };
+            let (sql, params) = history.prepare_insert();
+            gensql::db_log_sql_params(&sql, &params);
+            trans.insert(sql, params).await?;
+
+            ids.push(user_id);
+        }
+
+        trans.commit().await?;
+        Self::notify_changed(None).await;
+
+        Ok(ids)
+    }
+
+    /// 查询指定部门的用户数量
+    pub async fn select_count_by_dept(dept_id: u32) -> DbResult<u32> {
+        let (sql, params) = gensql::SelectSql::new()
+            .select("", "count(*)")
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| w.eq("", Self::DEPT_ID, dept_id))
+            .build();
+
+        Ok(gensql::sql_query_one(sql, params).await?.unwrap_or(0))
+    }
+
     /// 按登录账号查询，登录账号可以是 登录名/电子邮箱/手机号 任意一个
     pub async fn select_by_account(account: &str) -> DbResult<Option<SysUser>> {
         let col = match check_account_type(account) {
@@ -151,18 +284,63 @@ impl SysUser {
 
         let (sql, params) = gensql::SelectSql::new()
             .from(Self::TABLE_NAME)
-            .where_sql(|w| w.eq("", col, account.to_owned()))
+            .where_sql(|w| w.filter("", &TenantFilter).eq("", col, account.to_owned()))
             .build();
 
         gensql::sql_query_one(sql, params).await
     }
 
+    fn select_sql(
+        query: SysUserQuery,
+        scope: &DataScope,
+        after_id: Option<u32>,
+    ) -> gensql::SelectSql {
+        let value = query.inner;
+        // 未指定查询已删除的用户时, 缺省排除已删除的用户
+        let (disabled, exclude_deleted) = match query.deleted {
+            Some(true) => (Some(DisabledType::Deleted as u8), false),
+            Some(false) => (value.disabled, true),
+            None => (value.disabled, value.disabled.is_none()),
+        };
+
+        gensql::SelectSql::new()
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| {
+                w.filter("", &TenantFilter)
+                    .filter("", scope)
+                    .eq_opt("", Self::ROLE_ID, value.role_id)
+                    .eq_opt("", Self::DEPT_ID, value.dept_id)
+                    .eq_opt("", Self::DISABLED, disabled)
+                    .expr_if(
+                        exclude_deleted,
+                        "",
+                        Self::DISABLED,
+                        "!=",
+                        DisabledType::Deleted as u8,
+                    )
+                    .like_opt("", Self::USERNAME, value.username)
+                    .like_opt("", Self::NICKNAME, value.nickname)
+                    .like_opt("", Self::MOBILE, value.mobile)
+                    .like_opt("", Self::EMAIL, value.email)
+                    .expr_opt("", Self::USER_ID, ">", after_id)
+            })
+    }
+
     /// 根据 user_name/nickname,mobile/email 查找用户
-    pub async fn select_by(value: SysUser) -> DbResult<Vec<SysUser>> {
+    ///
+    /// Arguments:
+    ///
+    /// * `value`: 查询参数
+    /// * `scope`: 当前用户的数据权限范围
+    ///
+    pub async fn select_by(value: SysUser, scope: &DataScope) -> DbResult<Vec<SysUser>> {
         let (sql, params) = gensql::SelectSql::new()
             .from(Self::TABLE_NAME)
             .where_sql(|w| {
-                w.eq_opt("", Self::ROLE_ID, value.role_id)
+                w.filter("", &TenantFilter)
+                    .filter("", scope)
+                    .expr("", Self::DISABLED, "!=", DisabledType::Deleted as u8)
+                    .eq_opt("", Self::ROLE_ID, value.role_id)
                     .like_opt("", Self::USERNAME, value.username)
                     .like_opt("", Self::NICKNAME, value.nickname)
                     .like_opt("", Self::MOBILE, value.mobile)
@@ -196,21 +374,45 @@ impl SysUser {
         gensql::sql_query_one(sql, params).await
     }
 
-    /// 根据用户id查询权限
+    /// 根据用户id查询用户拥有的所有角色id(主角色及附加角色)和最后更新时间（用于权限校验）
+    pub async fn select_roles_and_updated(id: u32) -> DbResult<Option<(Vec<u32>, LocalTime)>> {
+        let (role_id, updated) = match Self::select_role_and_updated(id).await? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let mut role_ids = SysUserRole::select_role_ids(id).await?;
+        if !role_ids.contains(&role_id) {
+            role_ids.insert(0, role_id);
+        }
+
+        Ok(Some((role_ids, updated)))
+    }
+
+    /// 根据用户id查询权限, 返回用户所有角色权限位集按位或的结果
     pub async fn select_permissions_by_id(id: u32) -> DbResult<Option<String>> {
-        type T = SysUser;
-        type R = SysRole;
-        const T: &str = "t";
-        const R: &str = "r";
+        let role_ids = match Self::select_roles_and_updated(id).await? {
+            Some((role_ids, _)) => role_ids,
+            None => return Ok(None),
+        };
 
         let (sql, params) = gensql::SelectSql::new()
-            .select(R, R::PERMISSIONS)
-            .from_as(T::TABLE_NAME, T)
-            .join(R::TABLE_NAME, R, |j| j.on_eq(Self::ROLE_ID, R, R::ROLE_ID))
-            .where_sql(|w| w.eq(T, Self::USER_ID, id))
+            .select("", SysRole::PERMISSIONS)
+            .from(SysRole::TABLE_NAME)
+            .where_sql(|w| w.in_("", SysRole::ROLE_ID, role_ids))
             .build();
 
-        gensql::sql_query_one(sql, params).await
+        let list: Vec<String> = gensql::sql_query(sql, params).await?;
+        if list.is_empty() {
+            return Ok(None);
+        }
+
+        let mut permits = String::new();
+        for item in list.iter() {
+            utils::bits::or(&mut permits, item);
+        }
+
+        Ok(Some(permits))
     }
 }
 
@@ -235,27 +437,30 @@ pub fn check_account_type(account: &str) -> AccountType {
 /// Arguments
 ///
 /// * `user_id`: 用户id
+/// * `tenant_id`: 用户所属的租户id
 ///
-pub fn create_jwt_token(user_id: u32) -> Result<String> {
+pub fn create_jwt_token(user_id: u32, tenant_id: u32) -> Result<String> {
     let ac = AppConf::get();
-    jwt::encode(
-        serde_json::json!({
-            "uid": user_id.to_string(),
-            "created": unix_timestamp(),
-        }),
-        &ac.jwt_key,
-        &ac.jwt_iss,
-        AppGlobal::get().jwt_ttl as u64,
-    )
-    .context("生成jwt令牌失败")
+    let claims = serde_json::json!({
+        "uid": user_id.to_string(),
+        "tid": tenant_id,
+        "created": unix_timestamp(),
+    });
+    let ttl = AppGlobal::get().jwt_ttl as u64;
+
+    // 配置了非对称签名密钥时优先使用非对称签名
+    match jwt_keys::get() {
+        Some(keys) => keys.encode(claims, &ac.jwt_iss, ttl).context("生成jwt令牌失败"),
+        None => jwt::encode(claims, &ac.jwt_key, &ac.jwt_iss, ttl).context("生成jwt令牌失败"),
+    }
 }
 
-/// 生成刷新token的key
+/// 生成刷新令牌的摘要
 ///
 /// Arguments
 ///
-/// * `token`: 令牌
-/// * `key`": 生成key的键
+/// * `token`: 刷新令牌
+/// * `key`": 生成摘要的密钥
 pub fn create_refresh_token(token: &str, key: &str) -> String {
     let mut hasher = md5::Context::new();
     hasher.consume(token);
@@ -268,75 +473,55 @@ pub fn create_refresh_token(token: &str, key: &str) -> String {
 ///
 /// Arguments
 ///
-/// * `rid`: http请求id
 /// * `account`": 账号(用户名/手机号/电子邮箱)
 /// * `password`": 口令
 /// * `ip`": 客户端ip
 ///
-pub async fn user_login(account: &str, password: &str, ip: &Ipv4Addr) -> Result<SysUser> {
-    let cache_key = gen_login_fail_key(account);
+pub async fn user_login(account: &str, password: &str, ip: &IpAddr) -> Result<SysUser> {
+    let lock = LoginLock::load().await?;
 
-    // 校验是否已经达到最大尝试次数，如已达到，返回失败信息
-    check_login_count(&cache_key).await?;
+    // 校验账号及ip是否已达到最大尝试次数，如已达到，返回失败信息
+    lock.check(account, ip).await?;
 
     // 加载账号对应的记录
-    let user = get_user_by_account(account).await?;
+    let user = get_user_by_account(&lock, account, ip).await?;
     // 校验口令
-    check_password(account, password, user.password.as_ref().unwrap()).await?;
+    let pw_hash = user.password.as_ref().unwrap();
+    check_password(&lock, &user, account, password, ip).await?;
+    // 使用旧算法加密的口令, 登录成功后重新使用缺省算法加密
+    if utils::password::need_rehash(pw_hash) {
+        rehash_password(user.user_id.unwrap(), password).await;
+    }
 
     // 更新用户登录次数，时间等状态
     SysUserState::incr(user.user_id.unwrap(), ip).await?;
 
     // 清空缓冲中对应账号的失败次数信息
-    uri::del(&cache_key).await;
+    login_lock::clear(account).await;
 
     Ok(user)
 }
 
-/// 校验失败登录次数
-///
-/// Arguments
-///
-/// * `cache_key`: 基于登录账号的缓存键名
-///
-async fn check_login_count(cache_key: &str) -> Result<()> {
-    // 获取缓冲中保存的当前用户登录失败次数
-    let mut fail_count = 0;
-    if let Some(s) = uri::get::<String>(cache_key).await {
-        if let Ok(n) = s.parse() {
-            fail_count = n;
-        }
-    }
-
-    // 判断当前登录次数是否达到限制值
-    if fail_count >= MAX_FAIL_COUNT {
-        if let Some(ttl) = uri::ttl(cache_key).await {
-            if ttl > 0 {
-                http_bail!("账号已锁定, 请过{}后再进行登录", gen_time_desc(ttl as u32));
-            } else if ttl == uri::TTL_NOT_EXPIRE {
-                log::error!("redis缓存项{cache_key}没有过期时间，口令错误锁定后将无法解锁该账号");
-            }
-        }
-    }
-
-    Ok(())
-}
-
-/// 生成用于记录口令错误次数的键名
-fn gen_login_fail_key(account: &str) -> String {
-    format!("{}:{}:{}", AppConf::get().redis_pre, consts::CK_LOGIN_FAIL, account)
-}
-
 /// 根据登录账号(用户名/邮件/手机号)查找, 并校验记录状态，返回用户记录
-async fn get_user_by_account(account: &str) -> Result<SysUser> {
+async fn get_user_by_account(lock: &LoginLock, account: &str, ip: &IpAddr) -> Result<SysUser> {
     // 加载账号对应的记录
     let user = match SysUser::select_by_account(account).await? {
         Some(user) => user,
-        None => http_bail!("账号不存在"),
+        None => {
+            login_fail(lock, account, None, ip, "账号不存在").await;
+            http_bail!("账号不存在");
+        }
     };
 
+    // 已删除的账号视为不存在
+    if user.disabled == Some(DisabledType::Deleted as u8) {
+        login_fail(lock, account, None, ip, "账号已删除").await;
+        http_bail!("账号不存在");
+    }
+
     // 校验账号是否有效
     if user.disabled.unwrap() != 0 {
+        login_fail(lock, account, user.user_id, ip, "账号已被禁用").await;
         http_bail!("账号已被禁用");
     }
 
@@ -344,27 +529,84 @@ async fn get_user_by_account(account: &str) -> Result<SysUser> {
 }
 
 /// 校验登录口令
-async fn check_password(account: &str, password: &str, pw_hash: &str) -> Result<()> {
-    let veri_ret = utils::md5_crypt::verify(password, pw_hash).dot()?;
+async fn check_password(
+    lock: &LoginLock,
+    user: &SysUser,
+    account: &str,
+    password: &str,
+    ip: &IpAddr,
+) -> Result<()> {
+    let pw_hash = user.password.as_ref().unwrap();
+    let veri_ret = utils::password::verify_async(password, pw_hash)
+        .await
+        .dot()?;
     // 校验口令失败
     if !veri_ret {
-        let cache_key = gen_login_fail_key(account);
-        let count = uri::incr(&cache_key, DISABLE_LOGIN_TTL as u64).await;
-        let remainder = MAX_FAIL_COUNT - count.unwrap_or(0) as u32;
-
-        if remainder > 0 {
-            http_bail!("口令错误, 您还可以尝试{}次", remainder);
-        } else {
-            http_bail!(
+        match login_fail(lock, account, user.user_id, ip, "口令错误").await {
+            Some(0) => http_bail!(
                 "账号已锁定, 请过{}后再进行登录",
-                gen_time_desc(DISABLE_LOGIN_TTL)
-            );
+                gen_time_desc(lock.lock_secs)
+            ),
+            Some(remainder) => http_bail!("口令错误, 您还可以尝试{}次", remainder),
+            None => http_bail!("口令错误"),
         }
     }
 
     Ok(())
 }
 
+/// 记录登录失败次数及审计日志, 返回账号剩余可尝试的次数
+///
+/// Arguments
+///
+/// * `lock`: 登录锁定策略
+/// * `account`: 登录账号
+/// * `user_id`: 账号对应的用户id, 账号不存在时为None, 此时只记录ip的失败次数
+/// * `ip`: 客户端ip
+/// * `reason`: 失败原因
+///
+async fn login_fail(
+    lock: &LoginLock,
+    account: &str,
+    user_id: Option<u32>,
+    ip: &IpAddr,
+    reason: &str,
+) -> Option<u32> {
+    let remainder = lock.record_fail(user_id.map(|_| account), ip).await;
+    metrics::LOGIN_FAILURES.inc();
+
+    let audit_data = serde_json::json!({
+        "account": account,
+        "ip": ip.to_string(),
+        "reason": reason,
+    });
+    audit::log_json(audit::LOGIN_FAIL, user_id.unwrap_or(0), &audit_data);
+
+    remainder
+}
+
+/// 使用缺省算法重新加密口令并保存, 失败时只记录日志, 不影响登录
+async fn rehash_password(user_id: u32, password: &str) {
+    let pw_hash = match utils::password::encrypt_async(password).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("重新加密用户{user_id}的口令失败: {e:?}");
+            return;
+        }
+    };
+
+    // 不更新updated_time, 避免令牌被判定为用户信息已变更而失效
+    let user = SysUser {
+        user_id: Some(user_id),
+        password: Some(pw_hash),
+        ..Default::default()
+    };
+    match user.update_with_notify().await {
+        Ok(_) => log::debug!("用户{user_id}的口令已升级为新的加密格式"),
+        Err(e) => log::error!("保存用户{user_id}重新加密的口令失败: {e:?}"),
+    }
+}
+
 /// 判断字符串是否由全数字组成
 fn is_number(s: &str) -> bool {
     for c in s.as_bytes() {
@@ -0,0 +1,104 @@
+//! 用户双因子认证表
+use gensql::{table, DbResult};
+use localtime::LocalTime;
+
+/// 恢复码之间的分隔符
+const RECOVERY_SEP: char = ';';
+
+/// 用户双因子认证(TOTP)配置表
+#[table("t_sys_user_mfa")]
+pub struct SysUserMfa {
+    /// 用户id
+    #[table(id)]
+    user_id: u32,
+    /// base32编码的TOTP密钥
+    totp_secret: String,
+    /// 是否已启用, 0: 绑定中(尚未确认), 1: 已启用
+    enabled: u8,
+    /// 加密后的恢复码列表, 分号分隔, 每个恢复码只能使用1次
+    recovery_codes: String,
+    /// 最后一次校验通过的时间步, 用于防止同一口令被重复使用
+    last_step: u64,
+    /// 更新时间
+    updated_time: LocalTime,
+}
+
+impl SysUserMfa {
+    /// 用户是否已启用双因子认证
+    pub fn is_enabled(&self) -> bool {
+        self.enabled == Some(1)
+    }
+
+    /// 获取加密后的恢复码列表
+    pub fn recovery_list(&self) -> Vec<&str> {
+        match &self.recovery_codes {
+            Some(s) => s.split(RECOVERY_SEP).filter(|v| !v.is_empty()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// 将加密后的恢复码列表合并为数据库保存的格式
+    pub fn join_recovery<T: AsRef<str>>(codes: &[T]) -> String {
+        let mut s = String::new();
+        for c in codes {
+            if !s.is_empty() {
+                s.push(RECOVERY_SEP);
+            }
+            s.push_str(c.as_ref());
+        }
+        s
+    }
+
+    /// 更新最后一次校验通过的时间步, 只有新的时间步大于原值时才更新,
+    /// 使并发请求中同一口令只有1个能够通过校验, 返回是否更新成功
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: 用户id
+    /// * `step`: 校验通过的时间步
+    ///
+    pub async fn update_last_step(user_id: u32, step: u64) -> DbResult<bool> {
+        let sql = format!(
+            "update {0} set {1} = ? where {2} = ? and ({1} is null or {1} < ?)",
+            Self::TABLE_NAME,
+            Self::LAST_STEP,
+            Self::USER_ID,
+        );
+        let params = gensql::to_values![step, user_id, step];
+        gensql::db_log_sql_params(&sql, &params);
+        Ok(gensql::sql_exec(sql, params).await? > 0)
+    }
+
+    /// 更新恢复码列表, 只有恢复码列表未被其它请求修改时才更新,
+    /// 使并发请求中同一恢复码只有1个能够通过校验, 返回是否更新成功
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: 用户id
+    /// * `old_codes`: 读取到的原恢复码列表
+    /// * `new_codes`: 新的恢复码列表
+    ///
+    pub async fn update_recovery(user_id: u32, old_codes: &str, new_codes: &str) -> DbResult<bool> {
+        let sql = format!(
+            "update {0} set {1} = ?, {2} = ? where {3} = ? and {1} = ?",
+            Self::TABLE_NAME,
+            Self::RECOVERY_CODES,
+            Self::UPDATED_TIME,
+            Self::USER_ID,
+        );
+        let params = gensql::to_values![new_codes, LocalTime::now(), user_id, old_codes];
+        gensql::db_log_sql_params(&sql, &params);
+        Ok(gensql::sql_exec(sql, params).await? > 0)
+    }
+
+    /// 保存用户的双因子认证配置, 记录不存在时新增
+    pub async fn save(self) -> DbResult<()> {
+        let user_id = self.user_id.unwrap();
+        if Self::select_by_id(user_id).await?.is_some() {
+            self.update_by_id().await?;
+        } else {
+            self.insert().await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+//! 用户角色关联表
+use super::sys_user::SysUser;
+use gensql::{table, BatchInsertSql, DbResult, DeleteSql, InsertValue, Queryable, ToValue};
+use localtime::LocalTime;
+
+/// 用户角色关联表, 一个用户可以拥有多个角色
+#[table("t_sys_user_role")]
+pub struct SysUserRole {
+    /// 关联记录id
+    #[table(id)]
+    user_role_id: u32,
+    /// 用户id
+    user_id: u32,
+    /// 角色id
+    role_id: u32,
+    /// 更新时间
+    updated_time: LocalTime,
+}
+
+impl SysUserRole {
+    /// 查询用户拥有的附加角色id列表(不包含用户表中的主角色)
+    pub async fn select_role_ids(user_id: u32) -> DbResult<Vec<u32>> {
+        let (sql, params) = gensql::SelectSql::new()
+            .select("", Self::ROLE_ID)
+            .from(Self::TABLE_NAME)
+            .where_sql(|w| w.eq("", Self::USER_ID, user_id))
+            .order_by("", Self::ROLE_ID)
+            .build();
+
+        gensql::sql_query(sql, params).await
+    }
+
+    /// 使用事务替换用户的附加角色, 并通知用户信息变化
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: 用户id
+    /// * `role_ids`: 新的附加角色id列表, 为空时表示清除所有附加角色
+    ///
+    pub async fn replace_roles(user_id: u32, role_ids: &[u32]) -> DbResult<()> {
+        let mut trans = gensql::start_transaction().await?;
+
+        let (sql, params) = DeleteSql::new(Self::TABLE_NAME, |w| {
+            w.eq("", Self::USER_ID, user_id)
+        })
+        .build();
+        trans.exec(sql, params).await?;
+
+        if !role_ids.is_empty() {
+            let (sql, params) = BatchInsertSql::new(
+                Self::TABLE_NAME,
+                &[Self::USER_ID, Self::ROLE_ID, Self::UPDATED_TIME],
+            )
+            .values(role_ids.iter().map(|rid| {
+                vec![
+                    InsertValue::Value(user_id.to_value()),
+                    InsertValue::Value(rid.to_value()),
+                    InsertValue::Sql("now()"),
+                ]
+            }))
+            .build();
+            trans.exec(sql, params).await?;
+        }
+
+        trans.commit().await?;
+        SysUser::notify_changed(Some(user_id)).await;
+
+        Ok(())
+    }
+
+    /// 删除用户的所有附加角色
+    pub async fn delete_by_user(user_id: u32) -> DbResult<u32> {
+        let (sql, params) = DeleteSql::new(Self::TABLE_NAME, |w| {
+            w.eq("", Self::USER_ID, user_id)
+        })
+        .build();
+
+        gensql::sql_exec(sql, params).await
+    }
+}
@@ -1,5 +1,5 @@
 //! 用户状态表
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 
 use gensql::{table, DbResult};
 use localtime::LocalTime;
@@ -13,13 +13,13 @@ pub struct SysUserState {
     total_login: u32,
     /// 最后登录时间
     last_login_time: LocalTime,
-    /// 最后登录ip
+    /// 最后登录ip, 需要支持ipv6地址, 数据库字段长度不能小于45, 即varchar(45)
     last_login_ip: String,
 }
 
 impl SysUserState {
     /// 更新登录状态，当前的登录次数+1，并更新最后登录时间
-    pub async fn incr(user_id: u32, ip: &Ipv4Addr) -> DbResult<()> {
+    pub async fn incr(user_id: u32, ip: &IpAddr) -> DbResult<()> {
         let ip = ip.to_string();
         let now = LocalTime::now();
 
@@ -0,0 +1,170 @@
+//! 验证码发送服务, 支持可替换的发送通道实现
+//!
+//! 内置两种实现:
+//!   1. SmtpSender: 通过smtp服务器发送电子邮件
+//!   2. LogSender: 将验证码写入日志及指定文件, 用于开发测试或未配置发送通道的情况
+use std::path::PathBuf;
+
+use anyhow_ext::{Context, Result};
+use lettre::{
+    message::header::ContentType, transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use localtime::LocalTime;
+use tokio::io::AsyncWriteExt;
+
+use crate::utils::staticmut::StaticMut;
+
+/// 验证码发送接口
+#[async_trait::async_trait]
+pub trait CodeSender: Send + Sync + 'static {
+    /// 发送验证码
+    ///
+    /// Arguments:
+    ///
+    /// * `target`: 接收目标(手机号/电子邮箱)
+    /// * `code`: 验证码
+    /// * `ttl`: 验证码有效时长(单位: 秒)
+    ///
+    async fn send(&self, target: &str, code: &str, ttl: u32) -> Result<()>;
+}
+
+/// 基于smtp协议的电子邮件发送
+pub struct SmtpSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: String,
+}
+
+/// 将验证码输出到日志及文件的发送实现
+pub struct LogSender {
+    channel: &'static str,
+    file: Option<PathBuf>,
+}
+
+struct Senders {
+    mobile: Box<dyn CodeSender>,
+    email: Box<dyn CodeSender>,
+}
+
+const SMTPS_PORT: u16 = 465;
+const EMAIL_SUBJECT: &str = "验证码";
+
+static mut SENDERS: StaticMut<Senders> = StaticMut::new();
+
+impl SmtpSender {
+    /// 创建smtp发送对象, 端口为465时使用TLS连接, 否则使用STARTTLS
+    ///
+    /// Arguments:
+    ///
+    /// * `host`: smtp服务器主机名
+    /// * `port`: smtp服务器端口
+    /// * `user`: 登录用户名
+    /// * `pass`: 登录口令
+    /// * `from`: 发件人地址
+    ///
+    pub fn new(host: &str, port: u16, user: &str, pass: &str, from: &str) -> Result<Self> {
+        let builder = if port == SMTPS_PORT {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+        }
+        .with_context(|| format!("创建smtp连接失败: {host}:{port}"))?;
+
+        let transport = builder
+            .port(port)
+            .credentials(Credentials::new(user.to_owned(), pass.to_owned()))
+            .build();
+
+        Ok(Self {
+            transport,
+            from: from.to_owned(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl CodeSender for SmtpSender {
+    async fn send(&self, target: &str, code: &str, ttl: u32) -> Result<()> {
+        let body = format!(
+            "您的验证码是: {code}, {}分钟内有效, 请勿泄露给他人。",
+            ttl / 60
+        );
+
+        let email = Message::builder()
+            .from(self.from.parse().context("发件人地址格式错误")?)
+            .to(target.parse().context("收件人地址格式错误")?)
+            .subject(EMAIL_SUBJECT)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .context("生成邮件内容失败")?;
+
+        self.transport
+            .send(email)
+            .await
+            .with_context(|| format!("发送邮件到{target}失败"))?;
+
+        Ok(())
+    }
+}
+
+impl LogSender {
+    /// 创建日志发送对象
+    ///
+    /// Arguments:
+    ///
+    /// * `channel`: 发送通道名称, 仅用于日志输出
+    /// * `file`: 验证码写入的文件名, 为空时只输出到日志
+    ///
+    pub fn new(channel: &'static str, file: &str) -> Self {
+        let file = if file.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(file))
+        };
+
+        Self { channel, file }
+    }
+}
+
+#[async_trait::async_trait]
+impl CodeSender for LogSender {
+    async fn send(&self, target: &str, code: &str, ttl: u32) -> Result<()> {
+        log::info!("[{}]发送验证码: target = {target}, code = {code}, ttl = {ttl}", self.channel);
+
+        if let Some(file) = &self.file {
+            let line = format!("{}\t{}\t{target}\t{code}\n", LocalTime::now(), self.channel);
+            let mut f = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file)
+                .await
+                .with_context(|| format!("打开验证码输出文件失败: {}", file.display()))?;
+            f.write_all(line.as_bytes()).await.context("写入验证码输出文件失败")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// 初始化验证码发送服务
+///
+/// Arguments:
+///
+/// * `mobile`: 手机短信发送通道
+/// * `email`: 电子邮件发送通道
+///
+pub fn init(mobile: Box<dyn CodeSender>, email: Box<dyn CodeSender>) {
+    unsafe {
+        SENDERS.init(Senders { mobile, email });
+    }
+}
+
+/// 获取手机短信发送通道
+pub fn mobile_sender() -> &'static dyn CodeSender {
+    unsafe { SENDERS.get().mobile.as_ref() }
+}
+
+/// 获取电子邮件发送通道
+pub fn email_sender() -> &'static dyn CodeSender {
+    unsafe { SENDERS.get().email.as_ref() }
+}
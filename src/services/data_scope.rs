@@ -0,0 +1,133 @@
+//! 数据权限服务, 根据用户角色的数据权限范围生成查询过滤条件
+//!
+//! 用户拥有多个角色时取各角色数据权限的并集, 任一角色为全部数据时不做过滤,
+//! 用户始终可以访问本人的数据
+use anyhow_ext::Result;
+use gensql::{GeneratorSql, SqlFilter, WhereSql};
+
+use crate::entities::{
+    sys_dept::SysDept,
+    sys_role::{DataScopeType, SysRole},
+    sys_user::SysUser,
+};
+
+/// 用户的数据权限范围
+#[derive(Clone, Debug)]
+pub struct DataScope {
+    /// 可以访问全部数据
+    all: bool,
+    /// 可以访问的部门id列表
+    dept_ids: Vec<u32>,
+    /// 当前用户id
+    user_id: u32,
+}
+
+impl DataScope {
+    /// 不做任何过滤的数据权限范围
+    pub fn all() -> Self {
+        Self {
+            all: true,
+            dept_ids: Vec::new(),
+            user_id: 0,
+        }
+    }
+
+    /// 加载用户的数据权限范围
+    pub async fn load(user_id: u32) -> Result<Self> {
+        let mut scope = Self {
+            all: false,
+            dept_ids: Vec::new(),
+            user_id,
+        };
+
+        let user = match SysUser::select_by_id(user_id).await? {
+            Some(v) => v,
+            None => return Ok(scope),
+        };
+        let role_ids = match SysUser::select_roles_and_updated(user_id).await? {
+            Some((ids, _)) => ids,
+            None => return Ok(scope),
+        };
+        let user_dept = user.dept_id.unwrap_or(0);
+        let mut depts = None;
+
+        for role in SysRole::select_by_ids(&role_ids).await? {
+            match role.data_scope_type() {
+                DataScopeType::All => return Ok(Self::all()),
+                DataScopeType::Dept => scope.dept_ids.push(user_dept),
+                DataScopeType::DeptAndSub => {
+                    if depts.is_none() {
+                        depts = Some(SysDept::select_all().await?);
+                    }
+                    scope.add_with_sub(user_dept, depts.as_ref().unwrap());
+                }
+                DataScopeType::SelfOnly => {}
+                DataScopeType::Custom => scope.dept_ids.extend(role.data_dept_ids()),
+            }
+        }
+
+        scope.dept_ids.retain(|v| *v != 0);
+        scope.dept_ids.sort_unstable();
+        scope.dept_ids.dedup();
+
+        Ok(scope)
+    }
+
+    /// 判断数据是否在权限范围内
+    ///
+    /// Arguments:
+    ///
+    /// * `dept_id`: 数据所属的部门id
+    /// * `user_id`: 数据所属的用户id
+    ///
+    pub fn contains(&self, dept_id: Option<u32>, user_id: Option<u32>) -> bool {
+        self.all
+            || user_id == Some(self.user_id)
+            || dept_id.is_some_and(|v| self.dept_ids.binary_search(&v).is_ok())
+    }
+
+    /// 添加部门及其所有下级部门
+    fn add_with_sub(&mut self, dept_id: u32, depts: &[SysDept]) {
+        let code = match depts.iter().find(|d| d.dept_id == Some(dept_id)) {
+            Some(d) => d.dept_code.as_deref().unwrap_or_default(),
+            None => return,
+        };
+        if code.is_empty() {
+            self.dept_ids.push(dept_id);
+            return;
+        }
+        for d in depts {
+            if d.dept_code.as_deref().unwrap_or_default().starts_with(code) {
+                self.dept_ids.push(d.dept_id.unwrap());
+            }
+        }
+    }
+}
+
+/// 数据权限过滤条件, 要求过滤的表包含dept_id及user_id字段
+impl SqlFilter for DataScope {
+    fn apply<T: GeneratorSql>(&self, w: WhereSql<T>, table: &str) -> WhereSql<T> {
+        if self.all {
+            return w;
+        }
+
+        let col_pre = if table.is_empty() {
+            String::new()
+        } else {
+            format!("{table}.")
+        };
+        let user_col = format!("{col_pre}{}", SysUser::USER_ID);
+
+        if self.dept_ids.is_empty() {
+            return w.add_value(&format!(" and {user_col} = ?"), self.user_id);
+        }
+
+        let placeholders = vec!["?"; self.dept_ids.len()].join(", ");
+        let sql = format!(
+            " and ({col_pre}{} in ({placeholders}) or {user_col} = ?)",
+            SysUser::DEPT_ID
+        );
+        let params = self.dept_ids.iter().copied().chain(Some(self.user_id));
+        w.add_values(&sql, params)
+    }
+}
@@ -0,0 +1,125 @@
+//! 大数据量导出服务, 按记录id分批读取并通过流式回复逐批发送给客户端,
+//! 避免在内存中缓存全部导出内容
+use std::{error::Error, future::Future};
+
+use anyhow_ext::Result;
+use httpserver::{Body, BodySender, HttpResponse};
+
+use crate::services::tenant;
+
+/// 每批次从数据库读取的记录数
+const BATCH_SIZE: u32 = 1000;
+/// 单次导出允许的最大记录数
+pub const MAX_ROWS: usize = 100_000;
+/// 等待发送的批次数, 客户端接收较慢时暂停读取数据库
+const CHANNEL_BUFFER: usize = 4;
+/// 回复头部, 符合条件的记录总数
+const TOTAL_HEADER: &str = "X-Export-Total";
+/// 回复头部, 记录总数超过导出上限时为true, 此时只导出前MAX_ROWS条记录
+const TRUNCATED_HEADER: &str = "X-Export-Truncated";
+
+/// 导出文件编码器, 把记录逐条编码为文件内容
+pub trait Encoder<T>: Send + 'static {
+    /// 写入1条记录
+    fn write(&mut self, item: &T) -> Result<()>;
+
+    /// 取出已编码的内容, 无法分段输出的格式(如xlsx)返回空
+    fn take(&mut self) -> Vec<u8>;
+
+    /// 结束编码, 返回剩余的内容
+    fn finish(self) -> Result<Vec<u8>>;
+}
+
+/// 导出文件信息
+pub struct ExportFile {
+    pub file_name: String,
+    pub content_type: &'static str,
+    /// 符合条件的记录总数
+    pub total: usize,
+}
+
+/// 以流式回复导出记录, 回复头部发送后在后台任务中分批读取记录并编码发送,
+/// 读取失败时中断连接, 使客户端能够识别内容不完整
+///
+/// Arguments:
+///
+/// * `file`: 导出文件信息
+/// * `encoder`: 导出文件编码器
+/// * `fetch`: 读取记录的函数, 参数为上一批最后1条记录的id及本批次读取的记录数,
+///   返回按id升序排列的记录
+/// * `id_of`: 获取记录id的函数
+///
+pub fn stream<T, E, F, Fut, Er>(
+    file: ExportFile,
+    encoder: E,
+    fetch: F,
+    id_of: fn(&T) -> Option<u32>,
+) -> HttpResponse
+where
+    T: Send + 'static,
+    E: Encoder<T>,
+    F: FnMut(u32, u32) -> Fut + Send + 'static,
+    Fut: Future<Output = std::result::Result<Vec<T>, Er>> + Send,
+    Er: Error + Send + Sync + 'static,
+{
+    let (tx, body) = Body::channel(CHANNEL_BUFFER);
+    // 后台任务不在请求的租户作用域中, 需要重新设置
+    let tenant_id = tenant::current_or_platform();
+    tokio::spawn(tenant::scope(tenant_id, async move {
+        if let Err(e) = send_batches(&tx, encoder, fetch, id_of).await {
+            log::error!("导出数据失败: {e:?}");
+            tx.abort(e).await;
+        }
+    }));
+
+    let truncated = file.total > MAX_ROWS;
+    Ok(hyper::Response::builder()
+        .header(httpserver::CONTENT_TYPE, file.content_type)
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", file.file_name),
+        )
+        .header(TOTAL_HEADER, file.total)
+        .header(TRUNCATED_HEADER, if truncated { "true" } else { "false" })
+        .body(body)?)
+}
+
+async fn send_batches<T, E, F, Fut, Er>(
+    tx: &BodySender,
+    mut encoder: E,
+    mut fetch: F,
+    id_of: fn(&T) -> Option<u32>,
+) -> Result<()>
+where
+    E: Encoder<T>,
+    F: FnMut(u32, u32) -> Fut,
+    Fut: Future<Output = std::result::Result<Vec<T>, Er>>,
+    Er: Error + Send + Sync + 'static,
+{
+    let (mut after_id, mut rows) = (0, 0);
+    while rows < MAX_ROWS {
+        let count = BATCH_SIZE.min((MAX_ROWS - rows) as u32);
+        let list = fetch(after_id, count).await?;
+        for item in list.iter() {
+            encoder.write(item)?;
+        }
+        rows += list.len();
+
+        let chunk = encoder.take();
+        if !chunk.is_empty() {
+            tx.send(chunk).await?;
+        }
+
+        match list.last().and_then(id_of) {
+            Some(id) if list.len() == count as usize => after_id = id,
+            _ => break,
+        }
+    }
+
+    let chunk = encoder.finish()?;
+    if !chunk.is_empty() {
+        tx.send(chunk).await?;
+    }
+
+    Ok(())
+}
@@ -5,7 +5,7 @@ use deadpool_redis::redis::{FromRedisValue, ToRedisArgs};
 use mini_moka::sync::Cache;
 use serde::{de::DeserializeOwned, Serialize};
 
-use crate::utils::{consts, uni_redis::UniRedis};
+use crate::utils::{consts, multi_cache::CacheStats, uni_redis::UniRedis};
 
 use super::{mq, uri::UniRedisImpl};
 
@@ -20,6 +20,7 @@ pub struct GeneralMultiCacheTmpl<R: UniRedis> {
     redis_prefix: String,
     redis_expire: u32,
     chan_prefix: String,
+    stats: CacheStats,
 }
 
 static mut CACHE: MaybeUninit<GeneralMultiCache> = MaybeUninit::uninit();
@@ -55,6 +56,7 @@ impl<R: UniRedis> GeneralMultiCacheTmpl<R> {
             redis_prefix,
             redis_expire: redis_expire_secs,
             chan_prefix,
+            stats: CacheStats::default(),
         }
     }
 
@@ -74,6 +76,7 @@ impl<R: UniRedis> GeneralMultiCacheTmpl<R> {
         let value = self.memory_cache.get(&mem_key);
         if let Some(value) = value {
             if let Ok(v) = value.downcast() {
+                self.stats.hit_memory();
                 return Some(v);
             }
         }
@@ -81,12 +84,14 @@ impl<R: UniRedis> GeneralMultiCacheTmpl<R> {
         // 本地缓存找不到，从redis中读取，如果存在，则更新存活时间及本地缓存并返回
         let redis_key = format!("{}{}", self.redis_prefix, mem_key);
         if let Some(value) = self.redis_cache.get::<T>(&redis_key).await {
+            self.stats.record_redis(true);
             let arc_value = Arc::new(value);
             self.redis_cache.expire(&redis_key, self.redis_expire as i64).await;
             self.memory_cache.insert(mem_key, arc_value.clone());
             return Some(arc_value);
         }
 
+        self.stats.record_redis(false);
         None
     }
 
@@ -121,6 +126,7 @@ impl<R: UniRedis> GeneralMultiCacheTmpl<R> {
         let value = self.memory_cache.get(&mem_key);
         if let Some(value) = value {
             if let Ok(v) = value.downcast() {
+                self.stats.hit_memory();
                 return Some(v);
             }
         }
@@ -128,12 +134,14 @@ impl<R: UniRedis> GeneralMultiCacheTmpl<R> {
         // 本地缓存找不到，从redis中读取，如果存在，则更新存活时间及本地缓存并返回
         let redis_key = format!("{}{}", self.redis_prefix, mem_key);
         if let Some(value) = self.redis_cache.get_json::<T>(&redis_key, use_lz4).await {
+            self.stats.record_redis(true);
             let arc_value = Arc::new(value);
             self.redis_cache.expire(&redis_key, self.redis_expire as i64).await;
             self.memory_cache.insert(mem_key, arc_value.clone());
             return Some(arc_value);
         }
 
+        self.stats.record_redis(false);
         None
     }
 
@@ -230,6 +238,11 @@ impl<R: UniRedis> GeneralMultiCacheTmpl<R> {
         self.redis_cache.pdel(&redis_key).await;
     }
 
+    /// 缓存命中统计
+    pub fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
     pub async fn notify(&self, category: &str, key: &str) {
         let chan = format!("{}{}", self.chan_prefix, category);
         self.redis_cache.publish(&chan, key).await
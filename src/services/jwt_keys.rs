@@ -0,0 +1,228 @@
+//! 非对称令牌签名服务(EdDSA/Ed25519)
+//!
+//! 密钥目录中的每个pem文件对应一个密钥, 文件名(不含扩展名)即为密钥id(kid).
+//! 私钥文件(pkcs8格式)可用于签名及校验, 公钥文件只用于校验, 用于密钥轮换时
+//! 保留已停用的密钥, 确保停用前签发的令牌在过期前仍然有效.
+//! 密钥轮换时修改目录中的密钥文件后向服务发送SIGHUP信号重新加载, 无需重启
+use std::{fs, path::Path, sync::Arc};
+
+use anyhow_ext::{anyhow, bail, Context, Result};
+use arc_swap::ArcSwapOption;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::{
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    Signature, Signer, SigningKey, Verifier, VerifyingKey,
+};
+use serde_json::{json, Value};
+
+use crate::utils::time::unix_timestamp;
+
+pub const ALG: &str = "EdDSA";
+const KEY_FILE_EXT: &str = "pem";
+
+struct JwtKey {
+    kid: String,
+    signing: Option<SigningKey>,
+    verifying: VerifyingKey,
+}
+
+/// 令牌签名密钥集合
+pub struct JwtKeys {
+    keys: Vec<JwtKey>,
+    /// 当前用于签名的密钥索引
+    active: usize,
+}
+
+static JWT_KEYS: ArcSwapOption<JwtKeys> = ArcSwapOption::const_empty();
+
+/// 初始化签名密钥, 密钥目录为空时不启用非对称签名
+///
+/// Arguments:
+///
+/// * `dir`: 密钥目录
+/// * `active_kid`: 当前签名使用的密钥id, 为空时使用目录中排序最后的私钥
+///
+pub fn init(dir: &str, active_kid: &str) -> Result<()> {
+    reload(dir, active_kid)
+}
+
+/// 重新加载签名密钥, 用于密钥轮换, 加载失败时继续使用原有的密钥,
+/// 未启用非对称签名时不做任何处理
+///
+/// Arguments:
+///
+/// * `dir`: 密钥目录
+/// * `active_kid`: 当前签名使用的密钥id, 为空时使用目录中排序最后的私钥
+///
+pub fn reload(dir: &str, active_kid: &str) -> Result<()> {
+    if dir.is_empty() {
+        return Ok(());
+    }
+    JWT_KEYS.store(Some(Arc::new(JwtKeys::load(dir, active_kid)?)));
+    Ok(())
+}
+
+/// 获取签名密钥集合, 未启用非对称签名时返回None
+pub fn get() -> Option<Arc<JwtKeys>> {
+    JWT_KEYS.load_full()
+}
+
+/// 获取令牌头部的密钥id, 令牌未指定时返回None
+pub fn get_kid(token: &str) -> Option<String> {
+    let header = token.split('.').next()?;
+    let header = URL_SAFE_NO_PAD.decode(header).ok()?;
+    let header: Value = serde_json::from_slice(&header).ok()?;
+    header.get("kid")?.as_str().map(String::from)
+}
+
+impl JwtKeys {
+    /// 从目录中加载所有密钥
+    pub fn load(dir: &str, active_kid: &str) -> Result<Self> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir).with_context(|| format!("读取密钥目录{dir}失败"))? {
+            let path = entry?.path();
+            if path.extension().and_then(|s| s.to_str()) == Some(KEY_FILE_EXT) {
+                files.push(path);
+            }
+        }
+        files.sort();
+
+        let mut keys = Vec::with_capacity(files.len());
+        for path in files.iter() {
+            keys.push(Self::load_key(path)?);
+        }
+
+        let active = if active_kid.is_empty() {
+            keys.iter().rposition(|k| k.signing.is_some())
+        } else {
+            keys.iter()
+                .position(|k| k.kid == active_kid && k.signing.is_some())
+        };
+        let active = match active {
+            Some(v) => v,
+            None => bail!("密钥目录{dir}中找不到可用于签名的私钥{active_kid}"),
+        };
+        log::info!(
+            "令牌签名密钥加载完成, 共{}个, 当前签名密钥: {}",
+            keys.len(),
+            keys[active].kid
+        );
+
+        Ok(Self { keys, active })
+    }
+
+    /// 签发令牌
+    ///
+    /// Arguments:
+    ///
+    /// * `claims`: 令牌内容
+    /// * `iss`: 发行者
+    /// * `ttl`: 有效时长(单位: 秒)
+    ///
+    pub fn encode(&self, mut claims: Value, iss: &str, ttl: u64) -> Result<String> {
+        let key = &self.keys[self.active];
+        let header = json!({ "alg": ALG, "typ": "JWT", "kid": key.kid });
+
+        if let Value::Object(map) = &mut claims {
+            map.insert("iss".to_owned(), Value::String(iss.to_owned()));
+            map.insert("exp".to_owned(), Value::from(unix_timestamp() + ttl));
+        } else {
+            bail!("令牌内容必须是json对象");
+        }
+
+        let mut token = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?);
+        token.push('.');
+        token.push_str(&URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?));
+
+        let signature = key.signing.as_ref().unwrap().sign(token.as_bytes());
+        token.push('.');
+        token.push_str(&URL_SAFE_NO_PAD.encode(signature.to_bytes()));
+
+        Ok(token)
+    }
+
+    /// 校验令牌签名、发行者及过期时间, 返回令牌内容
+    pub fn decode(&self, token: &str, iss: &str) -> Result<Value> {
+        let (content, sign) = match token.rsplit_once('.') {
+            Some(v) => v,
+            None => bail!("令牌格式错误"),
+        };
+
+        let kid = get_kid(token).ok_or_else(|| anyhow!("令牌缺少kid"))?;
+        let key = match self.keys.iter().find(|k| k.kid == kid) {
+            Some(k) => k,
+            None => bail!("找不到令牌签名密钥: {kid}"),
+        };
+
+        let sign = URL_SAFE_NO_PAD.decode(sign)?;
+        let signature = Signature::from_slice(&sign).map_err(|_| anyhow!("令牌签名格式错误"))?;
+        if key
+            .verifying
+            .verify(content.as_bytes(), &signature)
+            .is_err()
+        {
+            bail!("Incorrect signature");
+        }
+
+        let payload = content
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| anyhow!("令牌格式错误"))?;
+        let claims: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload)?)?;
+
+        if claims.get("iss").and_then(|v| v.as_str()) != Some(iss) {
+            bail!("Incorrect iss");
+        }
+        match claims.get("exp").and_then(|v| v.as_u64()) {
+            Some(exp) if exp >= unix_timestamp() => Ok(claims),
+            _ => bail!("Incorrect exp"),
+        }
+    }
+
+    /// 生成JWKS格式的公钥集合
+    pub fn jwks(&self) -> Value {
+        let keys: Vec<Value> = self
+            .keys
+            .iter()
+            .map(|k| {
+                json!({
+                    "kty": "OKP",
+                    "crv": "Ed25519",
+                    "use": "sig",
+                    "alg": ALG,
+                    "kid": k.kid,
+                    "x": URL_SAFE_NO_PAD.encode(k.verifying.as_bytes()),
+                })
+            })
+            .collect();
+
+        json!({ "keys": keys })
+    }
+
+    fn load_key(path: &Path) -> Result<JwtKey> {
+        let kid = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| anyhow!("密钥文件名错误: {path:?}"))?
+            .to_owned();
+        let pem = fs::read_to_string(path).with_context(|| format!("读取密钥文件{path:?}失败"))?;
+
+        if let Ok(signing) = SigningKey::from_pkcs8_pem(&pem) {
+            let verifying = signing.verifying_key();
+            return Ok(JwtKey {
+                kid,
+                signing: Some(signing),
+                verifying,
+            });
+        }
+
+        match VerifyingKey::from_public_key_pem(&pem) {
+            Ok(verifying) => Ok(JwtKey {
+                kid,
+                signing: None,
+                verifying,
+            }),
+            Err(e) => bail!("密钥文件{path:?}格式错误: {e}"),
+        }
+    }
+}
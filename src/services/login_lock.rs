@@ -0,0 +1,252 @@
+//! 登录失败锁定服务, 分别按账号及客户端ip统计登录失败次数,
+//! 达到限制值后在锁定时长内禁止登录, 锁定参数保存在系统配置表中
+use std::net::IpAddr;
+
+use anyhow_ext::Result;
+use httpserver::http_bail;
+use serde::Serialize;
+
+use crate::{
+    entities::sys_config::SysConfig,
+    services::{metrics, tenant, uri},
+    utils::{
+        consts::{self, cfg},
+        ip::{ip_bucket, ip_bucket_str},
+        time::gen_time_desc,
+    },
+    AppConf,
+};
+
+/// 账号允许的最大连续登录失败次数的缺省值
+const DEFAULT_MAX_FAIL: u32 = 10;
+/// 锁定时长的缺省值(单位: 秒)
+const DEFAULT_LOCK_SECS: u32 = 300;
+/// 同一ip允许的最大连续登录失败次数的缺省值
+const DEFAULT_IP_MAX_FAIL: u32 = 50;
+
+/// 登录锁定策略
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginLock {
+    /// 账号允许的最大连续登录失败次数, 0表示不限制
+    pub max_fail: u32,
+    /// 达到限制值后的锁定时长(单位: 秒)
+    pub lock_secs: u32,
+    /// 同一ip允许的最大连续登录失败次数(不区分账号), 0表示不限制
+    pub ip_max_fail: u32,
+}
+
+/// 被锁定的账号或ip信息
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedInfo {
+    /// 账号或ip
+    pub target: String,
+    /// 登录失败次数
+    pub fail_count: u32,
+    /// 剩余锁定时长(单位: 秒)
+    pub ttl: u32,
+}
+
+impl LoginLock {
+    /// 从系统配置表中加载锁定策略, 未配置的项使用缺省值
+    pub async fn load() -> Result<Self> {
+        Ok(Self {
+            max_fail: SysConfig::get_u32(cfg::CK_LOGIN_MAX_FAIL, DEFAULT_MAX_FAIL).await?,
+            lock_secs: SysConfig::get_u32(cfg::CK_LOGIN_LOCK_SECS, DEFAULT_LOCK_SECS)
+                .await?
+                .max(1),
+            ip_max_fail: SysConfig::get_u32(cfg::CK_LOGIN_IP_MAX_FAIL, DEFAULT_IP_MAX_FAIL).await?,
+        })
+    }
+
+    /// 校验账号及ip是否已被锁定
+    pub async fn check(&self, account: &str, ip: &IpAddr) -> Result<()> {
+        if let Some(ttl) = locked_ttl(&account_key(account), self.max_fail).await {
+            http_bail!("账号已锁定, 请过{}后再进行登录", gen_time_desc(ttl));
+        }
+        if let Some(ttl) = locked_ttl(&ip_key(&ip_bucket(ip)), self.ip_max_fail).await {
+            http_bail!("登录失败次数过多, 请过{}后再进行登录", gen_time_desc(ttl));
+        }
+
+        Ok(())
+    }
+
+    /// 记录登录失败, 返回账号剩余可尝试的次数, 不限制失败次数时返回None
+    ///
+    /// Arguments:
+    ///
+    /// * `account`: 登录账号, 账号不存在时为None, 只记录ip的失败次数
+    /// * `ip`: 客户端ip
+    ///
+    pub async fn record_fail(&self, account: Option<&str>, ip: &IpAddr) -> Option<u32> {
+        self.record_ip_fail(ip).await;
+
+        match account {
+            Some(account) => self.record_account_fail(&account_key(account)).await,
+            None => None,
+        }
+    }
+
+    /// 校验用户的双因子认证是否因连续验证失败而被锁定
+    pub async fn check_mfa(&self, username: &str, ip: &IpAddr) -> Result<()> {
+        if let Some(ttl) = locked_ttl(&mfa_key(username), self.max_fail).await {
+            http_bail!("双因子认证已锁定, 请过{}后再进行登录", gen_time_desc(ttl));
+        }
+        if let Some(ttl) = locked_ttl(&ip_key(&ip_bucket(ip)), self.ip_max_fail).await {
+            http_bail!("登录失败次数过多, 请过{}后再进行登录", gen_time_desc(ttl));
+        }
+
+        Ok(())
+    }
+
+    /// 记录双因子认证失败, 返回剩余可尝试的次数, 不限制失败次数时返回None
+    ///
+    /// 该计数独立于口令失败次数, 口令校验通过时不会清除,
+    /// 避免知道口令的攻击者通过反复登录获取新的待验证令牌来穷举验证码
+    ///
+    /// Arguments:
+    ///
+    /// * `username`: 用户名
+    /// * `ip`: 客户端ip
+    ///
+    pub async fn record_mfa_fail(&self, username: &str, ip: &IpAddr) -> Option<u32> {
+        self.record_ip_fail(ip).await;
+        self.record_account_fail(&mfa_key(username)).await
+    }
+
+    async fn record_ip_fail(&self, ip: &IpAddr) {
+        if self.ip_max_fail > 0 {
+            let count = self.incr(&ip_key(&ip_bucket(ip)), self.ip_max_fail).await;
+            if count == self.ip_max_fail {
+                metrics::IP_LOCKOUTS.inc();
+            }
+        }
+    }
+
+    async fn record_account_fail(&self, key: &str) -> Option<u32> {
+        if self.max_fail == 0 {
+            return None;
+        }
+        let count = self.incr(key, self.max_fail).await;
+        if count == self.max_fail {
+            metrics::ACCOUNT_LOCKOUTS.inc();
+        }
+        Some(self.max_fail.saturating_sub(count))
+    }
+
+    /// 增加失败次数, 首次失败时开始计时, 达到限制值时重新计算锁定时长
+    async fn incr(&self, key: &str, max: u32) -> u32 {
+        let count = uri::incr(key, 1).await.unwrap_or(0) as u32;
+        if count <= 1 || count >= max {
+            uri::expire(key, self.lock_secs as i64).await;
+        }
+        count
+    }
+}
+
+/// 登录成功后清除账号的失败次数
+pub async fn clear(account: &str) {
+    uri::del(account_key(account)).await;
+}
+
+/// 双因子认证成功后清除用户的验证失败次数
+pub async fn clear_mfa(username: &str) {
+    uri::del(mfa_key(username)).await;
+}
+
+/// 获取所有被锁定的账号, 包括双因子认证被锁定的用户
+pub async fn list_locked_accounts() -> Result<Vec<LockedInfo>> {
+    let policy = LoginLock::load().await?;
+    let mut result = list_locked(&account_key(""), policy.max_fail).await;
+    result.extend(list_locked(&mfa_key(""), policy.max_fail).await);
+    result.sort_by(|a, b| a.target.cmp(&b.target));
+    Ok(result)
+}
+
+/// 获取所有被锁定的ip
+pub async fn list_locked_ips() -> Result<Vec<LockedInfo>> {
+    let policy = LoginLock::load().await?;
+    Ok(list_locked(&ip_key(""), policy.ip_max_fail).await)
+}
+
+/// 解锁账号(同时解锁双因子认证), 返回账号是否处于锁定或失败计数状态
+pub async fn unlock_account(account: &str) -> bool {
+    let keys = vec![account_key(account), mfa_key(account)];
+    uri::del(keys).await.unwrap_or(0) > 0
+}
+
+/// 解锁ip(ipv6地址解锁其所在的/64网段), 返回ip是否处于锁定或失败计数状态
+pub async fn unlock_ip(ip: &str) -> bool {
+    uri::del(ip_key(&ip_bucket_str(ip))).await.unwrap_or(0) > 0
+}
+
+/// 获取缓存项剩余的锁定时长, 未锁定时返回None
+async fn locked_ttl(key: &str, max: u32) -> Option<u32> {
+    if max == 0 {
+        return None;
+    }
+    let count: u32 = uri::get::<String>(key).await?.parse().ok()?;
+    if count < max {
+        return None;
+    }
+    uri::ttl(key).await.map(|v| v as u32).filter(|v| *v > 0)
+}
+
+async fn list_locked(prefix: &str, max: u32) -> Vec<LockedInfo> {
+    let mut result = Vec::new();
+    if max == 0 {
+        return result;
+    }
+
+    let keys = uri::scan(&format!("{prefix}*")).await.unwrap_or_default();
+    for key in keys {
+        let fail_count: u32 = match uri::get::<String>(&key).await.and_then(|v| v.parse().ok()) {
+            Some(v) => v,
+            None => continue,
+        };
+        if fail_count < max {
+            continue;
+        }
+        if let Some(ttl) = uri::ttl(&key).await {
+            result.push(LockedInfo {
+                target: key[prefix.len()..].to_owned(),
+                fail_count,
+                ttl: ttl as u32,
+            });
+        }
+    }
+    result.sort_by(|a, b| a.target.cmp(&b.target));
+
+    result
+}
+
+/// 生成用于记录账号登录失败次数的键名, 不同租户的同名账号分别计数
+fn account_key(account: &str) -> String {
+    format!(
+        "{}:{}:{}",
+        AppConf::get().redis_pre,
+        consts::CK_LOGIN_FAIL,
+        tenant::cache_key(account)
+    )
+}
+
+/// 生成用于记录双因子认证失败次数的键名, 不同租户的同名用户分别计数
+fn mfa_key(username: &str) -> String {
+    format!(
+        "{}:{}:{}",
+        AppConf::get().redis_pre,
+        consts::CK_MFA_FAIL,
+        tenant::cache_key(username)
+    )
+}
+
+/// 生成用于记录ip登录失败次数的键名, 参数为ip计数标识
+fn ip_key(ip: &str) -> String {
+    format!(
+        "{}:{}:{}",
+        AppConf::get().redis_pre,
+        consts::CK_LOGIN_FAIL_IP,
+        ip
+    )
+}
@@ -0,0 +1,311 @@
+//! Prometheus监控指标, 统计接口请求、权限校验、缓存命中、数据库连接池、消息订阅及登录失败等运行数据
+use std::{
+    fmt::{Display, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use dashmap::DashMap;
+use httpserver::{HttpContext, HttpResponse, HttpServer, Next};
+
+use crate::{
+    auth,
+    utils::{multi_cache::CacheStats, staticmut::StaticMut},
+    AppGlobal,
+};
+
+use super::{gmc, mq};
+
+/// Prometheus文本格式的Content-Type
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// 接口耗时直方图的分桶上限(单位: 秒)
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// 未注册接口的请求统一使用的路由标签, 避免任意路径导致指标数量无限增长
+const OTHER_ROUTE: &str = "other";
+
+/// 计数器
+pub struct Counter(AtomicU64);
+
+/// 权限校验通过的请求数
+pub static AUTH_ALLOW: Counter = Counter::new();
+/// 权限校验失败, 返回403的请求数
+pub static AUTH_DENY: Counter = Counter::new();
+/// 未登录或令牌无效的请求数
+pub static AUTH_UNAUTHORIZED: Counter = Counter::new();
+/// 登录失败次数
+pub static LOGIN_FAILURES: Counter = Counter::new();
+/// 账号因登录失败次数过多被锁定的次数
+pub static ACCOUNT_LOCKOUTS: Counter = Counter::new();
+/// ip因登录失败次数过多被锁定的次数
+pub static IP_LOCKOUTS: Counter = Counter::new();
+
+/// 接口请求统计中间件, 需要注册在权限校验中间件之前, 以便统计被拒绝的请求
+pub struct Metrics;
+
+struct Registry {
+    server: Arc<HttpServer>,
+    requests: DashMap<(String, u16), Histogram>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+static mut REGISTRY: StaticMut<Registry> = StaticMut::new();
+
+/// 初始化监控指标, 必须在http服务开始监听前调用
+///
+/// Arguments:
+///
+/// * `server`: http服务, 用于判断请求路径是否为已注册的接口及获取连接数
+///
+pub fn init(server: Arc<HttpServer>) {
+    let registry = Registry {
+        server,
+        requests: DashMap::new(),
+    };
+    unsafe { REGISTRY.init(registry) }
+}
+
+/// 生成Prometheus文本格式的监控指标
+pub fn render() -> String {
+    let reg = registry();
+    let mut buf = String::with_capacity(8192);
+
+    reg.render_requests(&mut buf);
+
+    let name = "http_connections_total";
+    write_header(&mut buf, name, "counter", "累计接受的http连接数");
+    write_sample(&mut buf, name, &[], reg.server.request_count());
+    let name = "http_active_connections";
+    write_header(&mut buf, name, "gauge", "当前活动的http连接数");
+    write_sample(&mut buf, name, &[], reg.server.active_connections());
+
+    let name = "auth_requests_total";
+    write_header(&mut buf, name, "counter", "权限校验结果统计");
+    write_sample(&mut buf, name, &[("result", "allow")], AUTH_ALLOW.get());
+    write_sample(&mut buf, name, &[("result", "deny")], AUTH_DENY.get());
+    let unauthorized = AUTH_UNAUTHORIZED.get();
+    write_sample(&mut buf, name, &[("result", "unauthorized")], unauthorized);
+
+    let name = "cache_requests_total";
+    write_header(&mut buf, name, "counter", "多级缓存各层的命中统计");
+    write_cache_stats(&mut buf, "gmc", gmc::get_cache().stats());
+    let authent = auth::get_authentication();
+    write_cache_stats(&mut buf, "token", authent.token_cache_stats());
+
+    let pool = gensql::pool_stats();
+    let name = "db_pool_max_connections";
+    write_header(&mut buf, name, "gauge", "数据库连接池的最大连接数");
+    write_sample(&mut buf, name, &[], pool.max_size);
+    let name = "db_pool_connections_in_use";
+    write_header(&mut buf, name, "gauge", "正在使用的数据库连接数");
+    write_sample(&mut buf, name, &[], pool.in_use);
+    let name = "db_pool_acquire_total";
+    write_header(&mut buf, name, "counter", "累计获取数据库连接的次数");
+    write_sample(&mut buf, name, &[], pool.acquired);
+    let name = "db_pool_acquire_errors_total";
+    write_header(&mut buf, name, "counter", "累计获取数据库连接失败的次数");
+    write_sample(&mut buf, name, &[], pool.errors);
+
+    let name = "mq_subscriptions";
+    write_header(&mut buf, name, "gauge", "当前有效的消息订阅数量");
+    write_sample(&mut buf, name, &[], mq::subscription_count());
+
+    let name = "login_failures_total";
+    write_header(&mut buf, name, "counter", "累计登录失败次数");
+    write_sample(&mut buf, name, &[], LOGIN_FAILURES.get());
+    let name = "login_lockouts_total";
+    write_header(&mut buf, name, "counter", "因登录失败次数过多被锁定的次数");
+    write_sample(
+        &mut buf,
+        name,
+        &[("kind", "account")],
+        ACCOUNT_LOCKOUTS.get(),
+    );
+    write_sample(&mut buf, name, &[("kind", "ip")], IP_LOCKOUTS.get());
+
+    let name = "process_start_time_seconds";
+    write_header(&mut buf, name, "gauge", "服务启动时间(unix时间戳)");
+    write_sample(&mut buf, name, &[], AppGlobal::get().startup_time);
+
+    buf
+}
+
+#[async_trait::async_trait]
+impl httpserver::HttpMiddleware for Metrics {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        let start = Instant::now();
+        let path = String::from(ctx.req.uri().path());
+
+        let res = next.run(ctx).await;
+
+        // 处理失败的请求由错误处理函数生成回复, 此处按500记录
+        let status = match &res {
+            Ok(r) => r.status().as_u16(),
+            Err(_) => 500,
+        };
+        registry().observe(&path, status, start.elapsed().as_secs_f64());
+
+        res
+    }
+}
+
+impl Counter {
+    pub const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Registry {
+    /// 记录1次接口请求的状态及耗时
+    fn observe(&self, path: &str, status: u16, secs: f64) {
+        let route = if self.server.has_route(path) {
+            path.strip_suffix('/')
+                .filter(|v| !v.is_empty())
+                .unwrap_or(path)
+        } else {
+            OTHER_ROUTE
+        };
+
+        self.requests
+            .entry((route.to_owned(), status))
+            .or_default()
+            .observe(secs);
+    }
+
+    fn render_requests(&self, buf: &mut String) {
+        let mut items: Vec<_> = self
+            .requests
+            .iter()
+            .map(|v| {
+                let h = v.value();
+                let buckets = h.buckets.iter().map(|b| b.load(Ordering::Relaxed));
+                let count = h.count.load(Ordering::Relaxed);
+                let sum = h.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+                (v.key().clone(), buckets.collect::<Vec<_>>(), count, sum)
+            })
+            .collect();
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let name = "http_requests_total";
+        write_header(buf, name, "counter", "接口请求次数");
+        for ((route, status), _, count, _) in items.iter() {
+            let status = status.to_string();
+            let labels = [("route", route.as_str()), ("status", &status)];
+            write_sample(buf, name, &labels, count);
+        }
+
+        let name = "http_request_duration_seconds";
+        write_header(buf, name, "histogram", "接口请求耗时");
+        let (bucket, sum_name, count_name) = (
+            format!("{name}_bucket"),
+            format!("{name}_sum"),
+            format!("{name}_count"),
+        );
+        for ((route, status), buckets, count, sum) in items.iter() {
+            let status = status.to_string();
+            let mut acc = 0;
+            for (le, n) in LATENCY_BUCKETS.iter().zip(buckets.iter()) {
+                acc += n;
+                let le = le.to_string();
+                let labels = [("route", route.as_str()), ("status", &status), ("le", &le)];
+                write_sample(buf, &bucket, &labels, acc);
+            }
+            let labels = [
+                ("route", route.as_str()),
+                ("status", &status),
+                ("le", "+Inf"),
+            ];
+            write_sample(buf, &bucket, &labels, count);
+            let labels = [("route", route.as_str()), ("status", &status)];
+            write_sample(buf, &sum_name, &labels, sum);
+            write_sample(buf, &count_name, &labels, count);
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&self, secs: f64) {
+        // 超出最大分桶上限的只计入总数(+Inf)
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|le| secs <= *le) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add((secs * 1_000_000.0) as u64, Ordering::Relaxed);
+    }
+}
+
+fn registry() -> &'static Registry {
+    unsafe { REGISTRY.get() }
+}
+
+/// 输出缓存的命中统计, 内存缓存未命中时才会读取redis缓存
+fn write_cache_stats(buf: &mut String, cache: &str, stats: &CacheStats) {
+    let name = "cache_requests_total";
+    let (memory_hits, redis_hits, misses) =
+        (stats.memory_hits(), stats.redis_hits(), stats.misses());
+    let items = [
+        ("memory", "hit", memory_hits),
+        ("memory", "miss", redis_hits + misses),
+        ("redis", "hit", redis_hits),
+        ("redis", "miss", misses),
+    ];
+    for (layer, result, value) in items {
+        let labels = [("cache", cache), ("layer", layer), ("result", result)];
+        write_sample(buf, name, &labels, value);
+    }
+}
+
+fn write_header(buf: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(buf, "# HELP {name} {help}");
+    let _ = writeln!(buf, "# TYPE {name} {kind}");
+}
+
+fn write_sample(buf: &mut String, name: &str, labels: &[(&str, &str)], value: impl Display) {
+    buf.push_str(name);
+    if !labels.is_empty() {
+        buf.push('{');
+        for (i, (k, v)) in labels.iter().enumerate() {
+            if i > 0 {
+                buf.push(',');
+            }
+            buf.push_str(k);
+            buf.push_str("=\"");
+            write_label_value(buf, v);
+            buf.push('"');
+        }
+        buf.push('}');
+    }
+    let _ = writeln!(buf, " {value}");
+}
+
+/// 写入标签值, 对反斜杠、双引号及换行符进行转义
+fn write_label_value(buf: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '\\' => buf.push_str("\\\\"),
+            '"' => buf.push_str("\\\""),
+            '\n' => buf.push_str("\\n"),
+            _ => buf.push(c),
+        }
+    }
+}
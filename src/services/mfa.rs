@@ -0,0 +1,253 @@
+//! 双因子认证(TOTP)服务
+use anyhow_ext::Result;
+use localtime::LocalTime;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entities::{
+        sys_config::SysConfig, sys_role::SysRole, sys_user::SysUser, sys_user_mfa::SysUserMfa,
+    },
+    services::uri,
+    utils::{consts::cfg, password, time::unix_timestamp, totp},
+    AppConf,
+};
+
+/// 登录待验证令牌的有效时长(单位: 秒)
+pub const PENDING_TTL: u64 = 300;
+/// 待验证令牌允许尝试的最大次数
+const MAX_PENDING_TRY: u64 = 5;
+/// 允许的时间步偏差
+const TOTP_SKEW: u64 = 1;
+/// 恢复码的数量
+const RECOVERY_COUNT: usize = 10;
+/// 恢复码的长度
+const RECOVERY_LEN: usize = 10;
+/// 恢复码使用的字符, 去掉了容易混淆的字符
+const RECOVERY_CHARS: &[u8] = b"23456789abcdefghjkmnpqrstuvwxyz";
+/// 待验证令牌的长度
+const PENDING_TOKEN_LEN: usize = 32;
+/// 缺省的强制启用双因子认证的角色类型
+const DEFAULT_REQUIRED_ROLE_TYPES: &str = "admin";
+const CK_MFA_PENDING: &str = "mfaPending";
+/// 待验证令牌尝试次数的键名后缀
+const TRY_SUFFIX: &str = "try";
+
+/// 待验证令牌对应的登录步骤, 令牌只能用于对应步骤的接口
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PendingStage {
+    /// 等待双因子认证
+    Mfa,
+    /// 口令已过期, 等待修改口令
+    ChangePassword,
+}
+
+/// 登录时等待进一步验证的信息
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingInfo {
+    pub user_id: u32,
+    pub stage: PendingStage,
+    /// 用户尚未绑定, 需要先完成绑定
+    pub enroll: bool,
+}
+
+/// 判断用户是否必须启用双因子认证, 用户拥有的角色中任一角色类型在配置项中即为必须
+pub async fn is_required(user_id: u32) -> Result<bool> {
+    let types = SysConfig::get_value(cfg::CK_MFA_REQUIRED_ROLE_TYPES).await?;
+    let types = match &types {
+        Some(v) => v.as_str(),
+        None => DEFAULT_REQUIRED_ROLE_TYPES,
+    };
+    let types: Vec<&str> = types
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    if types.is_empty() {
+        return Ok(false);
+    }
+
+    let role_ids = match SysUser::select_roles_and_updated(user_id).await? {
+        Some((ids, _)) => ids,
+        None => return Ok(false),
+    };
+    for rid in role_ids {
+        if let Some(role) = SysRole::select_by_id(rid).await? {
+            if let Some(rt) = &role.role_type {
+                if types.contains(&rt.as_str()) {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// 为用户生成新的TOTP密钥(未启用状态), 返回密钥及otpauth链接
+///
+/// Arguments:
+///
+/// * `user_id`: 用户id
+/// * `account`: 显示在认证器app中的账号名称
+///
+pub async fn enroll(user_id: u32, account: &str) -> Result<(String, String)> {
+    let secret = totp::gen_secret();
+    let uri = totp::otpauth_uri(&AppConf::get().jwt_iss, account, &secret);
+
+    SysUserMfa {
+        user_id: Some(user_id),
+        totp_secret: Some(secret.clone()),
+        enabled: Some(0),
+        recovery_codes: Some(String::new()),
+        last_step: Some(0),
+        updated_time: Some(LocalTime::now()),
+    }
+    .save()
+    .await?;
+
+    Ok((secret, uri))
+}
+
+/// 使用TOTP口令确认绑定, 成功后启用双因子认证并返回恢复码明文, 口令错误返回None
+pub async fn confirm(mfa: SysUserMfa, code: &str) -> Result<Option<Vec<String>>> {
+    let step = match check_totp(&mfa, code) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let (codes, hashes) = gen_recovery_codes().await?;
+    SysUserMfa {
+        user_id: mfa.user_id,
+        enabled: Some(1),
+        recovery_codes: Some(hashes),
+        last_step: Some(step),
+        updated_time: Some(LocalTime::now()),
+        ..Default::default()
+    }
+    .update_by_id()
+    .await?;
+
+    Ok(Some(codes))
+}
+
+/// 校验TOTP口令或恢复码, 恢复码使用后即作废
+///
+/// 只有符合恢复码格式的输入才逐个比对恢复码, 避免每次输错验证码都要进行多次口令哈希计算.
+/// 口令及恢复码通过条件更新原子地标记为已使用, 并发提交同一口令时只有1个请求能够通过
+pub async fn verify(mfa: &SysUserMfa, code: &str) -> Result<bool> {
+    let code = code.trim();
+    let user_id = mfa.user_id.unwrap_or(0);
+
+    if let Some(step) = check_totp(mfa, code) {
+        return Ok(SysUserMfa::update_last_step(user_id, step).await?);
+    }
+
+    if !is_recovery_format(code) {
+        return Ok(false);
+    }
+
+    let list = mfa.recovery_list();
+    let hashes = list.iter().map(|v| v.to_string()).collect();
+    let i = match password::position_async(code, hashes).await? {
+        Some(v) => v,
+        None => return Ok(false),
+    };
+
+    let mut rest = list.clone();
+    rest.remove(i);
+    let old_codes = mfa.recovery_codes.as_deref().unwrap_or_default();
+    let new_codes = SysUserMfa::join_recovery(&rest);
+    if !SysUserMfa::update_recovery(user_id, old_codes, &new_codes).await? {
+        return Ok(false);
+    }
+    log::info!("用户[{user_id}]使用恢复码通过双因子认证");
+
+    Ok(true)
+}
+
+/// 创建登录待验证令牌
+///
+/// Arguments:
+///
+/// * `user_id`: 用户id
+/// * `stage`: 令牌对应的登录步骤
+/// * `enroll`: 用户尚未绑定双因子认证
+///
+pub async fn create_pending(user_id: u32, stage: PendingStage, enroll: bool) -> String {
+    let token = gen_random(PENDING_TOKEN_LEN, b"0123456789abcdefghijklmnopqrstuvwxyz");
+    let info = PendingInfo {
+        user_id,
+        stage,
+        enroll,
+    };
+    uri::set_json(&pending_key(&token), &info, PENDING_TTL, false).await;
+    token
+}
+
+/// 获取登录待验证令牌的信息, 令牌不存在、步骤不符或超过尝试次数时返回None
+pub async fn get_pending(token: &str, stage: PendingStage) -> Option<PendingInfo> {
+    let key = pending_key(token);
+    let info: PendingInfo = uri::get_json(&key, false).await?;
+    if info.stage != stage {
+        return None;
+    }
+
+    // 尝试次数使用独立的键原子递增, 并发请求也无法突破最大尝试次数, redis异常时视为失效
+    let try_key = format!("{key}:{TRY_SUFFIX}");
+    let count = uri::incr(&try_key, 1).await?;
+    if count == 1 {
+        uri::expire(&try_key, PENDING_TTL as i64).await;
+    }
+    if count > MAX_PENDING_TRY {
+        remove_pending(token).await;
+        return None;
+    }
+
+    Some(info)
+}
+
+/// 删除登录待验证令牌
+pub async fn remove_pending(token: &str) {
+    let key = pending_key(token);
+    let try_key = format!("{key}:{TRY_SUFFIX}");
+    uri::del(vec![key, try_key]).await;
+}
+
+fn check_totp(mfa: &SysUserMfa, code: &str) -> Option<u64> {
+    let secret = mfa.totp_secret.as_ref()?;
+    let step = totp::verify(secret, code, unix_timestamp(), TOTP_SKEW)?;
+    // 同一时间步的口令只能使用1次
+    if step <= mfa.last_step.unwrap_or(0) {
+        return None;
+    }
+    Some(step)
+}
+
+/// 生成恢复码, 返回恢复码明文列表及加密后用于保存的字符串
+async fn gen_recovery_codes() -> Result<(Vec<String>, String)> {
+    let codes: Vec<_> = (0..RECOVERY_COUNT)
+        .map(|_| gen_random(RECOVERY_LEN, RECOVERY_CHARS))
+        .collect();
+    let hashes = password::encrypt_batch_async(codes.clone()).await?;
+
+    Ok((codes, SysUserMfa::join_recovery(&hashes)))
+}
+
+/// 判断输入是否符合恢复码的格式
+fn is_recovery_format(code: &str) -> bool {
+    code.len() == RECOVERY_LEN && code.bytes().all(|c| RECOVERY_CHARS.contains(&c))
+}
+
+fn gen_random(len: usize, chars: &[u8]) -> String {
+    let mut rng = rand::thread_rng();
+    (0..len)
+        .map(|_| chars[rng.gen_range(0..chars.len())] as char)
+        .collect()
+}
+
+fn pending_key(token: &str) -> String {
+    format!("{}:{}:{}", AppConf::get().redis_pre, CK_MFA_PENDING, token)
+}
@@ -1,5 +1,17 @@
+pub mod code_sender;
+pub mod data_scope;
+pub mod export;
 pub mod gmc;
+pub mod jwt_keys;
+pub mod login_lock;
+pub mod metrics;
+pub mod mfa;
 #[allow(dead_code)]
 pub mod mq;
+pub mod password_policy;
+pub mod rate_limit;
+pub mod refresh_token;
+pub mod session;
+pub mod tenant;
 #[allow(dead_code)]
 pub mod uri;
@@ -1,9 +1,9 @@
 //! redis 消息队列服务
 use std::{
     collections::{hash_map::Entry, HashMap}, mem::MaybeUninit, sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
         Arc,
-    }, vec
+    }, time::Duration, vec
 };
 
 use anyhow_ext::{Context, Result};
@@ -81,6 +81,10 @@ struct GlobalVal {
 }
 
 static mut GLOBAL_VAL: MaybeUninit<GlobalVal> = MaybeUninit::uninit();
+static SUB_COUNT: AtomicU32 = AtomicU32::new(0); // 当前有效的订阅数量
+static CONNECTED: AtomicBool = AtomicBool::new(false); // 消息订阅连接是否正常
+
+const RECONNECT_INTERVAL: u64 = 5; // 消息订阅连接断开后的重连间隔(单位: 秒)
 #[cfg(debug_assertions)]
 static mut INITED: bool = false;
 
@@ -163,6 +167,16 @@ pub async fn unsubscribe_slice(ids: &[u32]) -> Result<()> {
     Ok(())
 }
 
+/// 获取当前有效的订阅数量
+pub fn subscription_count() -> u32 {
+    SUB_COUNT.load(Ordering::Relaxed)
+}
+
+/// 消息订阅连接是否正常
+pub fn is_connected() -> bool {
+    CONNECTED.load(Ordering::Relaxed)
+}
+
 /// 停止监听服务
 pub async fn stop() -> Result<()> {
     Ok(global_val().tx.send(EvtData::Quit)?)
@@ -199,10 +213,9 @@ pub fn publish_async(channel: String, message: String) {
 
 /// redis异步消息处理函数
 async fn msg_loop(mut rx: UnboundedReceiver<EvtData>, url: String) {
-    let mut pubsub = {
-        let client = Client::open(url).dot().expect("创建redis连接失败");
-        client.get_async_pubsub().await.dot().expect("获取redis消息订阅流失败")
-    };
+    let client = Client::open(url).dot().expect("创建redis连接失败");
+    let mut pubsub = client.get_async_pubsub().await.dot().expect("获取redis消息订阅流失败");
+    CONNECTED.store(true, Ordering::Relaxed);
     let mut all_func_map = AllFuncMap {
         funcs: HashMap::new(),
         pfuncs: HashMap::new(),
@@ -225,15 +238,22 @@ async fn msg_loop(mut rx: UnboundedReceiver<EvtData>, url: String) {
 
         // 获取redis事件订阅对象
         let mut pstream = pubsub.on_message();
+        let mut disconnected = false;
 
         loop {
             tokio::select! {
-                // 收到订阅消息, 调用相应的消息处理函数
-                Some(msg) = pstream.next() => {
-                    if let Err(e) = on_message(&all_func_map, msg).await {
-                        log::error!("[redis:on_message] 发生错误: {e:?}");
+                // 收到订阅消息, 调用相应的消息处理函数, 消息流结束表示连接已断开
+                msg = pstream.next() => match msg {
+                    Some(msg) => {
+                        if let Err(e) = on_message(&all_func_map, msg).await {
+                            log::error!("[redis:on_message] 发生错误: {e:?}");
+                        }
                     }
-                }
+                    None => {
+                        disconnected = true;
+                        break;
+                    }
+                },
 
                 // 收到更新订阅消息，需要退出内层循环才能获取pubsub变量进行操作
                 Some(data) = rx.recv() => {
@@ -242,6 +262,40 @@ async fn msg_loop(mut rx: UnboundedReceiver<EvtData>, url: String) {
                 }
             };
         }
+
+        drop(pstream);
+        if disconnected {
+            CONNECTED.store(false, Ordering::Relaxed);
+            log::error!("redis消息订阅连接已断开, 正在重新连接...");
+            pubsub = reconnect(&client, &all_func_map).await;
+            CONNECTED.store(true, Ordering::Relaxed);
+            log::info!("redis消息订阅连接已恢复");
+        }
+    }
+}
+
+/// 重新建立消息订阅连接并恢复所有已订阅的频道, 失败时间隔一段时间后重试
+async fn reconnect(client: &Client, all_func_map: &AllFuncMap) -> PubSub {
+    'retry: loop {
+        tokio::time::sleep(Duration::from_secs(RECONNECT_INTERVAL)).await;
+
+        let mut pubsub = match client.get_async_pubsub().await {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("重新连接redis消息订阅失败: {e:?}");
+                continue;
+            }
+        };
+
+        let chans = all_func_map.funcs.keys().chain(all_func_map.pfuncs.keys());
+        for chan in chans {
+            if let Err(e) = pubsub.subscribe(chan.as_str()).await {
+                log::error!("重新订阅频道失败: chan = {chan}, error = {e:?}");
+                continue 'retry;
+            }
+        }
+
+        return pubsub;
     }
 }
 
@@ -252,6 +306,7 @@ fn subscribe_to_map(func_map: &mut AllFuncMap, id: u32, chan: String, func: OnMs
         &mut func_map.funcs
     };
     let func_item = FuncItem { id, func };
+    SUB_COUNT.fetch_add(1, Ordering::Relaxed);
 
     match funcs.entry(chan) {
         Entry::Occupied(mut v) => {
@@ -309,6 +364,7 @@ fn remove_from_map(func_map: &mut FuncMap, id: u32) -> String {
 
         if let Some(index) = index {
             vec.remove(index);
+            SUB_COUNT.fetch_sub(1, Ordering::Relaxed);
             if vec.is_empty() {
                 chan.push_str(k);
             }
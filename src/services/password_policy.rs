@@ -0,0 +1,186 @@
+//! 口令策略服务, 策略参数保存在系统配置表中, 修改后通过gmc缓存通知自动生效
+use anyhow_ext::Result;
+use httpserver::http_bail;
+use localtime::LocalTime;
+use serde::Serialize;
+
+use crate::{
+    entities::{
+        sys_config::SysConfig, sys_password_history::SysPasswordHistory, sys_user::SysUser,
+    },
+    utils::{consts::cfg, password, time::unix_timestamp},
+};
+
+/// 口令最小长度的缺省值
+const DEFAULT_MIN_LENGTH: u32 = 6;
+const SECS_PER_DAY: u64 = 86400;
+
+/// 口令策略
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordPolicy {
+    /// 口令最小长度
+    pub min_length: u32,
+    /// 口令至少需要包含的字符类别数(小写字母/大写字母/数字/特殊字符)
+    pub char_classes: u32,
+    /// 禁止重复使用最近几次的口令, 0表示不限制
+    pub history_count: u32,
+    /// 口令最长有效天数, 超过后登录时需要修改口令, 0表示不限制
+    pub max_age_days: u32,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: DEFAULT_MIN_LENGTH,
+            char_classes: 1,
+            history_count: 0,
+            max_age_days: 0,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// 从系统配置表中加载口令策略, 未配置的项使用缺省值
+    pub async fn load() -> Result<Self> {
+        let def = Self::default();
+
+        Ok(Self {
+            min_length: SysConfig::get_u32(cfg::CK_PWD_MIN_LENGTH, def.min_length)
+                .await?
+                .max(1),
+            char_classes: SysConfig::get_u32(cfg::CK_PWD_CHAR_CLASSES, def.char_classes)
+                .await?
+                .min(4),
+            history_count: SysConfig::get_u32(cfg::CK_PWD_HISTORY_COUNT, def.history_count).await?,
+            max_age_days: SysConfig::get_u32(cfg::CK_PWD_MAX_AGE_DAYS, def.max_age_days).await?,
+        })
+    }
+
+    /// 校验口令强度(长度及字符类别)
+    pub fn check_strength(&self, pwd: &str) -> Result<()> {
+        let len = pwd.chars().count() as u32;
+        if len < self.min_length {
+            http_bail!("口令长度不能少于{}位", self.min_length);
+        }
+
+        let (mut lower, mut upper, mut digit, mut other) = (0, 0, 0, 0);
+        for c in pwd.chars() {
+            match c {
+                'a'..='z' => lower = 1,
+                'A'..='Z' => upper = 1,
+                '0'..='9' => digit = 1,
+                _ => other = 1,
+            }
+        }
+        if lower + upper + digit + other < self.char_classes {
+            http_bail!(
+                "口令需要包含小写字母、大写字母、数字、特殊字符中的至少{}种",
+                self.char_classes
+            );
+        }
+
+        Ok(())
+    }
+
+    /// 校验口令是否与当前口令或最近使用过的口令重复
+    pub async fn check_history(&self, user_id: u32, pwd: &str) -> Result<()> {
+        // 新口令不能与当前口令相同, 避免不修改口令而重置口令有效期
+        if let Some(hash) = SysUser::select_by_id(user_id)
+            .await?
+            .and_then(|v| v.password)
+        {
+            if password::verify_async(pwd, &hash).await.unwrap_or(false) {
+                http_bail!("新口令不能与当前口令相同");
+            }
+        }
+
+        if self.history_count == 0 {
+            return Ok(());
+        }
+
+        let list = SysPasswordHistory::select_recent(user_id, self.history_count).await?;
+        for item in list.iter() {
+            if let Some(hash) = &item.password {
+                if password::verify_async(pwd, hash).await.unwrap_or(false) {
+                    http_bail!("不能使用最近{}次使用过的口令", self.history_count);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 校验口令强度及历史记录
+    pub async fn check(&self, user_id: u32, pwd: &str) -> Result<()> {
+        self.check_strength(pwd)?;
+        self.check_history(user_id, pwd).await
+    }
+
+    /// 判断用户口令是否已过期, 管理员设置的缺省口令视为已过期
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: 用户id
+    /// * `created_time`: 用户创建时间, 没有口令修改记录时使用该时间计算
+    ///
+    pub async fn is_expired(&self, user_id: u32, created_time: Option<&LocalTime>) -> Result<bool> {
+        let last = SysPasswordHistory::select_last(user_id).await?;
+        if last.as_ref().is_some_and(|v| v.expired == Some(1)) {
+            return Ok(true);
+        }
+        if self.max_age_days == 0 {
+            return Ok(false);
+        }
+
+        let changed = match last.and_then(|v| v.created_time) {
+            Some(t) => t.timestamp(),
+            None => match created_time {
+                Some(t) => t.timestamp(),
+                None => return Ok(false),
+            },
+        };
+
+        let age = unix_timestamp().saturating_sub(changed.max(0) as u64);
+        Ok(age > self.max_age_days as u64 * SECS_PER_DAY)
+    }
+
+    /// 校验新口令后修改用户口令并记录修改历史, 返回写入数据库的用户记录
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: 用户id
+    /// * `pwd`: 新口令明文
+    ///
+    pub async fn change(&self, user_id: u32, pwd: &str) -> Result<SysUser> {
+        self.check(user_id, pwd).await?;
+        let pw_hash = match password::encrypt_async(pwd).await {
+            Ok(v) => v,
+            Err(_) => http_bail!("无法生成加密口令"),
+        };
+
+        let sys_user = SysUser {
+            user_id: Some(user_id),
+            password: Some(pw_hash.clone()),
+            updated_time: Some(LocalTime::now()),
+            ..Default::default()
+        };
+        sys_user.clone().update_by_id().await?;
+        self.save_history(user_id, pw_hash, false).await?;
+
+        Ok(sys_user)
+    }
+
+    /// 记录口令修改历史
+    ///
+    /// Arguments:
+    ///
+    /// * `user_id`: 用户id
+    /// * `pw_hash`: 加密后的口令
+    /// * `expired`: 口令是否需要在登录后立即修改(管理员设置的缺省口令)
+    ///
+    pub async fn save_history(&self, user_id: u32, pw_hash: String, expired: bool) -> Result<()> {
+        SysPasswordHistory::append(user_id, pw_hash, expired, self.history_count).await?;
+        Ok(())
+    }
+}
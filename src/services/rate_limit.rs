@@ -0,0 +1,419 @@
+//! 接口限流中间件, 按客户端ip、登录用户或接口路径前缀限制请求频率,
+//! 限流规则保存在系统配置表中, 修改后通过配置变更通知实时生效
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow_ext::{bail, Context, Result};
+use arc_swap::ArcSwap;
+use dashmap::DashMap;
+use deadpool_redis::redis;
+use httpserver::{log_debug, HttpContext, HttpResponse, Next, Resp};
+use hyper::{
+    header::{HeaderName, HeaderValue, RETRY_AFTER},
+    HeaderMap, StatusCode,
+};
+use serde::Deserialize;
+
+use crate::{
+    entities::sys_config::SysConfig,
+    services::{gmc, mq, tenant, uri},
+    utils::{
+        consts::{self, cfg},
+        ip::ip_bucket,
+        rate_limiter::{
+            sliding_window_decision, token_bucket_decision, Decision, SlidingWindow, TokenBucket,
+        },
+    },
+};
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// 系统配置表中未配置限流规则时使用的缺省规则, 只限制暴露在公网上的登录、鉴权、
+/// 令牌刷新接口及可被用于暴力破解或消耗短信/邮件额度的接口
+const DEFAULT_RULES: &str = r#"[
+    {"prefix": "/api/sys/login", "key": "ip", "algorithm": "slidingWindow", "limit": 30, "window": 60},
+    {"prefix": "/api/sys/mfaVerify", "key": "ip", "algorithm": "slidingWindow", "limit": 30, "window": 60},
+    {"prefix": "/api/sys/changeExpiredPassword", "key": "ip", "algorithm": "slidingWindow", "limit": 10, "window": 60},
+    {"prefix": "/api/sys/refreshToken", "key": "ip", "algorithm": "tokenBucket", "limit": 300, "window": 60, "burst": 60},
+    {"prefix": "/api/sys/account/sendMobileCode", "key": "uid", "algorithm": "slidingWindow", "limit": 5, "window": 60},
+    {"prefix": "/api/sys/account/sendEmailCode", "key": "uid", "algorithm": "slidingWindow", "limit": 5, "window": 60},
+    {"prefix": "/api/sys/authenticate", "key": "ip", "algorithm": "tokenBucket", "limit": 1200, "window": 60, "burst": 200}
+]"#;
+
+/// 令牌桶脚本, 使用redis服务器时间以避免多个实例的时钟误差,
+/// 返回 [是否允许, 剩余令牌数 * 1000]
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local t = redis.call('TIME')
+local now = t[1] * 1000 + math.floor(t[2] / 1000)
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local data = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(data[1]) or capacity
+local ts = tonumber(data[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * rate)
+local allowed = 0
+if tokens >= 1 then
+    allowed = 1
+    tokens = tokens - 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', tostring(now))
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / rate))
+return {allowed, math.floor(tokens * 1000)}
+"#;
+
+/// 滑动窗口计数器脚本, 每个固定窗口使用独立的计数键,
+/// 返回 [是否允许, 上一窗口请求数, 当前窗口请求数, 当前窗口已经过的毫秒数]
+const SLIDING_WINDOW_SCRIPT: &str = r#"
+local t = redis.call('TIME')
+local now = t[1] * 1000 + math.floor(t[2] / 1000)
+local window = tonumber(ARGV[1])
+local limit = tonumber(ARGV[2])
+local start = now - now % window
+local cur_key = KEYS[1] .. ':' .. string.format('%d', start)
+local prev_key = KEYS[1] .. ':' .. string.format('%d', start - window)
+local prev = tonumber(redis.call('GET', prev_key) or '0')
+local count = tonumber(redis.call('GET', cur_key) or '0')
+local elapsed = now - start
+local allowed = 0
+if prev * (window - elapsed) / window + count < limit then
+    allowed = 1
+    count = redis.call('INCR', cur_key)
+    redis.call('PEXPIRE', cur_key, window * 2)
+end
+return {allowed, prev, count, elapsed}
+"#;
+
+/// 限流计数的存储位置
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// 进程内存, 适用于单实例部署
+    Memory,
+    /// redis, 多个实例共享计数, 适用于集群部署
+    Redis,
+}
+
+/// 接口限流中间件, 需要注册在权限校验中间件之后, 以便按登录用户限流
+pub struct RateLimit {
+    backend: Backend,
+    key_prefix: String,
+    rules: ArcSwap<Vec<Rule>>,
+    memory: DashMap<String, Entry>,
+    /// 不限流的路径前缀, 优先于限流规则
+    skip_paths: Vec<String>,
+}
+
+/// 限流规则, 请求路径匹配多条规则时需同时满足
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    /// 请求路径前缀, 包含上下文路径, 如 `/api/sys/login`
+    prefix: String,
+    /// 限流对象
+    #[serde(default)]
+    key: KeyType,
+    /// 限流算法
+    #[serde(default)]
+    algorithm: Algorithm,
+    /// 周期内允许的请求数
+    limit: u32,
+    /// 周期时长(单位: 秒)
+    window: u32,
+    /// 令牌桶容量, 即允许的突发请求数, 缺省与limit相同
+    burst: Option<u32>,
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+enum KeyType {
+    /// 按客户端ip, ipv6地址按/64网段
+    #[default]
+    Ip,
+    /// 按登录用户, 未登录时按客户端ip
+    Uid,
+    /// 按接口路径前缀, 所有客户端共享配额
+    Route,
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+enum Algorithm {
+    #[default]
+    SlidingWindow,
+    TokenBucket,
+}
+
+struct Entry {
+    state: State,
+    expire_at: u64,
+}
+
+enum State {
+    Bucket(TokenBucket),
+    Window(SlidingWindow),
+}
+
+#[async_trait::async_trait]
+impl httpserver::HttpMiddleware for RateLimit {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        let path = ctx.req.uri().path();
+        if self.skip_paths.iter().any(|p| path.starts_with(p.as_str())) {
+            return next.run(ctx).await;
+        }
+
+        let rules = self.rules.load_full();
+        // 多条规则同时匹配时, 回复头部使用剩余请求数最少的规则
+        let mut current: Option<Decision> = None;
+
+        for (i, rule) in rules.iter().enumerate() {
+            if !path.starts_with(&rule.prefix) {
+                continue;
+            }
+
+            let key = format!("{}:{i}:{}", self.key_prefix, rule.subject(&ctx));
+            let decision = match self.backend {
+                Backend::Memory => self.acquire_memory(rule, key),
+                // redis不可用时不限流, 避免影响正常的业务请求
+                Backend::Redis => match acquire_redis(rule, &key).await {
+                    Some(d) => d,
+                    None => continue,
+                },
+            };
+
+            if !decision.allowed {
+                log_debug!(
+                    ctx.id,
+                    "请求过于频繁, 已限流: path = {}, key = {}",
+                    path,
+                    key
+                );
+                let mut res = Resp::fail_with_status(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    StatusCode::TOO_MANY_REQUESTS.as_u16() as u32,
+                    "Too Many Requests",
+                )?;
+                let headers = res.headers_mut();
+                set_headers(headers, &decision);
+                headers.insert(RETRY_AFTER, HeaderValue::from(decision.retry_after));
+                return Ok(res);
+            }
+
+            if !current.is_some_and(|c| c.remaining <= decision.remaining) {
+                current = Some(decision);
+            }
+        }
+
+        let res = next.run(ctx).await;
+        match current {
+            Some(d) => res.map(|mut r| {
+                set_headers(r.headers_mut(), &d);
+                r
+            }),
+            None => res,
+        }
+    }
+}
+
+impl RateLimit {
+    /// 创建限流中间件并从系统配置表中加载限流规则
+    ///
+    /// Arguments:
+    ///
+    /// * `backend`: 限流计数的存储位置
+    /// * `key_prefix`: redis中限流计数的键名前缀
+    ///
+    pub async fn new(backend: Backend, key_prefix: String) -> Result<Self> {
+        let limiter = Self {
+            backend,
+            key_prefix,
+            rules: ArcSwap::from_pointee(Vec::new()),
+            memory: DashMap::new(),
+            skip_paths: Vec::new(),
+        };
+        limiter.reload().await?;
+        Ok(limiter)
+    }
+
+    /// 设置不限流的路径前缀, 即使限流规则的前缀覆盖了这些路径也不限流
+    ///
+    /// Arguments:
+    ///
+    /// * `paths`: 路径前缀列表, 包含上下文路径, 如 `/api/health/`
+    ///
+    pub fn skip_paths(mut self, paths: Vec<String>) -> Self {
+        self.skip_paths = paths;
+        self
+    }
+
+    /// 重新加载限流规则, 规则格式错误时保留原有规则
+    pub async fn reload(&self) -> Result<()> {
+        let value = SysConfig::get_value(cfg::CK_RATE_LIMIT_RULES).await?;
+        let rules = parse_rules(value.as_ref().map_or(DEFAULT_RULES, |v| v.as_str()))?;
+        log::debug!("加载限流规则: {rules:?}");
+
+        self.rules.store(Arc::new(rules));
+        // 规则变化后按序号生成的计数键不再对应原有规则
+        self.memory.clear();
+        Ok(())
+    }
+
+    /// 订阅系统配置变更消息, 限流规则变更后重新加载
+    ///
+    /// Arguments:
+    ///
+    /// * `config_chan`: 系统配置变更的消息频道
+    ///
+    pub async fn start_listen(self: &Arc<Self>, config_chan: String) -> Result<()> {
+        let that = self.clone();
+        mq::subscribe(config_chan, move |msg: Arc<mq::Msg>| {
+            let that = that.clone();
+            async move {
+                // 限流规则是平台级配置, 租户配置项的变更消息带有租户键名, 无需处理
+                if !msg.get_payload().is_empty() {
+                    return Ok(());
+                }
+                log::debug!("收到系统配置变动消息，正在重新加载限流规则...");
+                // 先删除缓存, 避免先于缓存失效处理而读取到旧的配置
+                let cache_key = tenant::cache_key(cfg::CK_RATE_LIMIT_RULES);
+                gmc::get_cache()
+                    .del(consts::gmc::SYS_CONFIG, &cache_key)
+                    .await;
+                that.reload().await
+            }
+        })
+        .await
+        .dot()?;
+
+        Ok(())
+    }
+
+    /// 启动定时清理过期内存计数的任务
+    pub fn start_recycle_task(self: &Arc<Self>, task_interval: u64) {
+        if self.backend != Backend::Memory {
+            return;
+        }
+
+        let mut interval = tokio::time::interval(Duration::from_secs(task_interval));
+        let that = self.clone();
+        tokio::spawn(async move {
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let now = now_millis();
+                that.memory.retain(|_, v| v.expire_at > now);
+            }
+        });
+    }
+
+    fn acquire_memory(&self, rule: &Rule, key: String) -> Decision {
+        let now = now_millis();
+        let mut entry = self.memory.entry(key).or_insert_with(|| Entry {
+            state: match rule.algorithm {
+                Algorithm::TokenBucket => State::Bucket(TokenBucket::new(rule.capacity(), now)),
+                Algorithm::SlidingWindow => {
+                    State::Window(SlidingWindow::new(rule.window_ms(), now))
+                }
+            },
+            expire_at: 0,
+        });
+
+        let (decision, ttl) = match &mut entry.state {
+            State::Bucket(tb) => (
+                tb.acquire(rule.capacity(), rule.rate(), now),
+                (rule.capacity() as f64 / rule.rate()).ceil() as u64,
+            ),
+            State::Window(sw) => (
+                sw.acquire(rule.limit, rule.window_ms(), now),
+                rule.window_ms() * 2,
+            ),
+        };
+        entry.expire_at = now + ttl;
+
+        decision
+    }
+}
+
+impl Rule {
+    /// 限流对象的标识
+    fn subject(&self, ctx: &HttpContext) -> String {
+        match self.key {
+            KeyType::Uid if !ctx.uid.is_empty() => format!("uid:{}", ctx.uid),
+            KeyType::Ip | KeyType::Uid => format!("ip:{}", ip_bucket(&ctx.ip)),
+            KeyType::Route => String::from("route"),
+        }
+    }
+
+    fn window_ms(&self) -> u64 {
+        self.window as u64 * 1000
+    }
+
+    fn capacity(&self) -> u32 {
+        self.burst.unwrap_or(self.limit)
+    }
+
+    /// 令牌桶每毫秒补充的令牌数
+    fn rate(&self) -> f64 {
+        self.limit as f64 / self.window_ms() as f64
+    }
+}
+
+async fn acquire_redis(rule: &Rule, key: &str) -> Option<Decision> {
+    let mut cmd = redis::cmd("EVAL");
+    match rule.algorithm {
+        Algorithm::TokenBucket => {
+            cmd.arg(TOKEN_BUCKET_SCRIPT)
+                .arg(1)
+                .arg(key)
+                .arg(rule.capacity())
+                .arg(rule.rate());
+            let (allowed, tokens): (i64, i64) = uri::cmd(&cmd).await?;
+            Some(token_bucket_decision(
+                allowed == 1,
+                rule.capacity(),
+                rule.rate(),
+                tokens as f64 / 1000.0,
+            ))
+        }
+        Algorithm::SlidingWindow => {
+            cmd.arg(SLIDING_WINDOW_SCRIPT)
+                .arg(1)
+                .arg(key)
+                .arg(rule.window_ms())
+                .arg(rule.limit);
+            let (allowed, prev, count, elapsed): (i64, u32, u32, u64) = uri::cmd(&cmd).await?;
+            Some(sliding_window_decision(
+                allowed == 1,
+                rule.limit,
+                rule.window_ms(),
+                prev,
+                count,
+                elapsed,
+            ))
+        }
+    }
+}
+
+fn parse_rules(json: &str) -> Result<Vec<Rule>> {
+    let rules: Vec<Rule> = serde_json::from_str(json).context("限流规则格式错误")?;
+    for rule in rules.iter() {
+        if rule.limit == 0 || rule.window == 0 || rule.burst == Some(0) {
+            bail!("限流规则[{}]的limit/window/burst必须大于0", rule.prefix);
+        }
+    }
+    Ok(rules)
+}
+
+fn set_headers(headers: &mut HeaderMap, decision: &Decision) {
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(decision.limit));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    headers.insert(RATELIMIT_RESET, HeaderValue::from(decision.reset));
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
@@ -0,0 +1,159 @@
+//! 刷新令牌服务
+//!
+//! 刷新令牌是保存在redis中的随机字符串, 每次使用后即轮换为新的刷新令牌.
+//! 同一次登录产生的所有刷新令牌属于同一个令牌族, 已使用过的刷新令牌被再次使用时,
+//! 视为令牌泄露, 整个令牌族立即失效
+use anyhow_ext::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use httpserver::http_bail;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entities::sys_user,
+    services::{mq, uri},
+    utils::consts,
+    AppConf, AppGlobal,
+};
+
+const CK_REFRESH: &str = "refresh";
+const CK_REFRESH_FAMILY: &str = "refreshFamily";
+/// 刷新令牌使用次数的键名后缀
+const USED_SUFFIX: &str = "used";
+/// 随机令牌的字节长度
+const TOKEN_BYTES: usize = 32;
+
+/// 单个刷新令牌的信息
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenInfo {
+    user_id: u32,
+    family: String,
+}
+
+/// 令牌族信息
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FamilyInfo {
+    /// 当前有效的刷新令牌(摘要)
+    current: String,
+    /// 最后签发的访问令牌
+    access_token: String,
+}
+
+/// 刷新令牌轮换成功后返回的信息
+pub struct Rotated {
+    pub user_id: u32,
+    pub family: String,
+    /// 上一次签发的访问令牌
+    pub access_token: String,
+}
+
+/// 签发刷新令牌, 返回令牌明文
+///
+/// Arguments:
+///
+/// * `user_id`: 用户id
+/// * `family`: 令牌族id, 为None时创建新的令牌族
+/// * `access_token`: 同时签发的访问令牌
+///
+pub async fn issue(user_id: u32, family: Option<String>, access_token: &str) -> (String, String) {
+    let token = gen_random();
+    let family = family.unwrap_or_else(gen_random);
+    let hash = token_hash(&token);
+    let ttl = AppGlobal::get().refresh_ttl as u64;
+
+    let info = TokenInfo {
+        user_id,
+        family: family.clone(),
+    };
+    uri::set_json(&token_key(&hash), &info, ttl, false).await;
+
+    let finfo = FamilyInfo {
+        current: hash,
+        access_token: access_token.to_owned(),
+    };
+    uri::set_json(&family_key(&family), &finfo, ttl, false).await;
+
+    (token, family)
+}
+
+/// 使用刷新令牌, 成功后该令牌作废, 已使用过的令牌再次使用时整个令牌族失效
+pub async fn rotate(token: &str) -> Result<Rotated> {
+    let hash = token_hash(token);
+    let key = token_key(&hash);
+    let info: TokenInfo = match uri::get_json(&key, false).await {
+        Some(v) => v,
+        None => http_bail!("刷新令牌已失效, 请重新登录"),
+    };
+
+    // 原子地递增使用次数, 并发使用同一令牌时只有1个请求能成功, 其余请求视为重复使用
+    let claimed = claim(&key).await?;
+    let finfo: Option<FamilyInfo> = uri::get_json(&family_key(&info.family), false).await;
+    match finfo {
+        Some(v) if claimed && v.current == hash => Ok(Rotated {
+            user_id: info.user_id,
+            family: info.family,
+            access_token: v.access_token,
+        }),
+        // 令牌族已被注销或已过期
+        None => http_bail!("刷新令牌已失效, 请重新登录"),
+        Some(_) => {
+            log::warn!(
+                "用户[{}]的刷新令牌被重复使用, 注销令牌族{}",
+                info.user_id,
+                info.family
+            );
+            revoke_family(&info.family).await;
+            http_bail!("刷新令牌已失效, 请重新登录");
+        }
+    }
+}
+
+/// 注销令牌族, 令牌族中所有的刷新令牌失效, 最后签发的访问令牌通过退出登录消息失效
+pub async fn revoke_family(family: &str) {
+    let key = family_key(family);
+    if let Some(finfo) = uri::get_json::<FamilyInfo>(&key, false).await {
+        let chan = format!("{}:{}", AppConf::get().redis_pre, consts::CC_LOGOUT);
+        mq::publish_async(chan, finfo.access_token);
+    }
+    uri::del(&key).await;
+}
+
+/// 递增刷新令牌的使用次数, 首次使用时返回true, 计数保留到令牌过期之后,
+/// 用于检测重复使用, redis异常时拒绝本次刷新
+async fn claim(key: &str) -> Result<bool> {
+    let used_key = format!("{key}:{USED_SUFFIX}");
+    match uri::incr(&used_key, 1).await {
+        Some(1) => {
+            uri::expire(&used_key, AppGlobal::get().refresh_ttl as i64).await;
+            Ok(true)
+        }
+        Some(_) => Ok(false),
+        None => http_bail!("刷新令牌校验失败, 请稍后重试"),
+    }
+}
+
+fn gen_random() -> String {
+    let mut buf = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut buf);
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+/// redis中只保存刷新令牌的摘要, 避免缓存泄露时令牌被直接使用
+fn token_hash(token: &str) -> String {
+    sys_user::create_refresh_token(token, &AppConf::get().jwt_refresh)
+}
+
+fn token_key(hash: &str) -> String {
+    format!("{}:{}:{}", AppConf::get().redis_pre, CK_REFRESH, hash)
+}
+
+fn family_key(family: &str) -> String {
+    format!(
+        "{}:{}:{}",
+        AppConf::get().redis_pre,
+        CK_REFRESH_FAMILY,
+        family
+    )
+}
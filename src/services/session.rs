@@ -0,0 +1,185 @@
+//! 用户会话登记服务, 记录每个已签发的令牌, 支持查看及注销用户的会话
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use deadpool_redis::redis::{self, Cmd};
+use localtime::LocalTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    services::{mq, refresh_token, uri},
+    utils::{consts, time::unix_timestamp},
+    AppConf, AppGlobal,
+};
+
+const CK_SESSION: &str = "session";
+
+/// 会话仍存在时才更新会话信息, 避免与注销会话并发时把已删除的会话重新写回
+const TOUCH_SCRIPT: &str = r#"
+if redis.call('HEXISTS', KEYS[1], ARGV[1]) == 1 then
+    redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+    return 1
+end
+return 0
+"#;
+
+/// 用户会话信息
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    /// 会话id
+    pub session_id: String,
+    /// 登录ip
+    pub ip: String,
+    /// 客户端标识
+    pub user_agent: String,
+    /// 创建时间
+    pub created_time: LocalTime,
+    /// 最后访问时间
+    pub last_seen: LocalTime,
+    /// 令牌过期时间(unix时间戳)
+    pub exp: u64,
+    /// 会话对应的令牌, 不返回给客户端
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub token: String,
+    /// 会话对应的刷新令牌族, 不返回给客户端
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub family: String,
+}
+
+/// 根据令牌生成会话id(令牌签名的摘要), 令牌格式错误时返回None
+pub fn session_id(token: &str) -> Option<String> {
+    let sign = jwt::get_sign(token)?;
+    Some(URL_SAFE_NO_PAD.encode(&md5::compute(sign)[..]))
+}
+
+/// 登记新签发的令牌
+///
+/// Arguments:
+///
+/// * `user_id`: 用户id
+/// * `token`: 签发的令牌
+/// * `family`: 同时签发的刷新令牌族
+/// * `ip`: 客户端ip
+/// * `user_agent`: 客户端标识
+///
+pub async fn create(user_id: u32, token: &str, family: &str, ip: &str, user_agent: &str) {
+    let sid = match session_id(token) {
+        Some(v) => v,
+        None => return,
+    };
+    let ttl = AppGlobal::get().jwt_ttl as u64;
+    let info = SessionInfo {
+        session_id: sid,
+        ip: ip.to_owned(),
+        user_agent: user_agent.to_owned(),
+        created_time: LocalTime::now(),
+        last_seen: LocalTime::now(),
+        exp: unix_timestamp() + ttl,
+        token: token.to_owned(),
+        family: family.to_owned(),
+    };
+
+    let key = session_key(user_id);
+    save(&key, &info).await;
+    // 会话表的存活时间与最后签发的令牌一致
+    uri::expire(&key, ttl as i64).await;
+}
+
+/// 更新会话的最后访问时间, 会话不存在时忽略
+pub async fn touch(user_id: u32, token: &str) {
+    let sid = match session_id(token) {
+        Some(v) => v,
+        None => return,
+    };
+    let key = session_key(user_id);
+    let mut info = match load(&key, &sid).await {
+        Some(v) => v,
+        None => return,
+    };
+    info.last_seen = LocalTime::now();
+    let value = match serde_json::to_string(&info) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("json编码失败: {e:?}");
+            return;
+        }
+    };
+
+    let mut cmd = redis::cmd("EVAL");
+    cmd.arg(TOUCH_SCRIPT).arg(1).arg(&key).arg(&sid).arg(value);
+    uri::cmd::<u64>(&cmd).await;
+}
+
+/// 获取用户所有有效的会话, 已过期的会话将被清除
+pub async fn list(user_id: u32) -> Vec<SessionInfo> {
+    let key = session_key(user_id);
+    let values: HashMap<String, String> = uri::cmd(&Cmd::hgetall(&key)).await.unwrap_or_default();
+    let now = unix_timestamp();
+    let mut result = Vec::with_capacity(values.len());
+
+    for (sid, value) in values {
+        match serde_json::from_str::<SessionInfo>(&value) {
+            Ok(info) if info.exp > now => result.push(info),
+            _ => {
+                uri::cmd::<u64>(&Cmd::hdel(&key, &sid)).await;
+            }
+        }
+    }
+    // 按签发时间倒序排列
+    result.sort_by(|a, b| b.exp.cmp(&a.exp));
+
+    result
+}
+
+/// 删除会话登记, 返回被删除的会话
+pub async fn remove(user_id: u32, token: &str) -> Option<SessionInfo> {
+    let sid = session_id(token)?;
+    let key = session_key(user_id);
+    let info = load(&key, &sid).await;
+    uri::cmd::<u64>(&Cmd::hdel(&key, sid)).await;
+    info
+}
+
+/// 注销用户的会话, 令牌通过退出登录消息通知所有服务失效,
+/// 会话对应的刷新令牌族同时失效, 返回注销的会话数量
+///
+/// Arguments:
+///
+/// * `user_id`: 用户id
+/// * `filter`: 过滤函数, 返回true的会话将被注销
+///
+pub async fn revoke<F: Fn(&SessionInfo) -> bool>(user_id: u32, filter: F) -> usize {
+    let key = session_key(user_id);
+    let chan = format!("{}:{}", AppConf::get().redis_pre, consts::CC_LOGOUT);
+    let mut count = 0;
+
+    for info in list(user_id).await.iter().filter(|v| filter(v)) {
+        uri::cmd::<u64>(&Cmd::hdel(&key, &info.session_id)).await;
+        mq::publish_async(chan.clone(), info.token.clone());
+        if !info.family.is_empty() {
+            refresh_token::revoke_family(&info.family).await;
+        }
+        count += 1;
+    }
+
+    count
+}
+
+async fn load(key: &str, sid: &str) -> Option<SessionInfo> {
+    let value: String = uri::cmd::<Option<String>>(&Cmd::hget(key, sid)).await??;
+    serde_json::from_str(&value).ok()
+}
+
+async fn save(key: &str, info: &SessionInfo) {
+    match serde_json::to_string(info) {
+        Ok(value) => {
+            uri::cmd::<u64>(&Cmd::hset(key, &info.session_id, value)).await;
+        }
+        Err(e) => log::error!("json编码失败: {e:?}"),
+    }
+}
+
+fn session_key(user_id: u32) -> String {
+    format!("{}:{}:{}", AppConf::get().redis_pre, CK_SESSION, user_id)
+}
@@ -0,0 +1,107 @@
+//! 多租户服务, 请求所属的租户保存在任务上下文中, 数据访问层据此自动过滤及写入租户id
+//!
+//! 租户id优先从令牌中获取, 未登录时从请求头部获取, 均未指定时为平台租户.
+//! 不在请求上下文中执行的任务(如启动时的数据加载、消息订阅处理)不做租户过滤
+use std::future::Future;
+
+use anyhow_ext::Result;
+use gensql::{GeneratorSql, SqlFilter, WhereSql};
+use httpserver::{http_bail, HttpContext};
+
+use crate::entities::sys_tenant::SysTenant;
+
+/// 平台租户id, 只有平台租户的用户可以管理租户
+pub const PLATFORM_TENANT_ID: u32 = 0;
+/// 未登录时用于指定租户的请求头部
+pub const TENANT_HEADER: &str = "X-Tenant-Id";
+/// 租户id在请求上下文属性中的名称
+pub const ATTR_TENANT_ID: &str = "tenantId";
+/// 数据表中的租户id字段名
+const TENANT_ID: &str = "tenant_id";
+
+tokio::task_local! {
+    static CURRENT_TENANT: u32;
+}
+
+/// 在指定租户的上下文中执行
+pub async fn scope<F: Future>(tenant_id: u32, f: F) -> F::Output {
+    CURRENT_TENANT.scope(tenant_id, f).await
+}
+
+/// 获取当前上下文的租户id, 不在请求上下文中时返回None
+pub fn current() -> Option<u32> {
+    CURRENT_TENANT.try_with(|v| *v).ok()
+}
+
+/// 获取当前上下文的租户id, 不在请求上下文中时返回平台租户id
+pub fn current_or_platform() -> u32 {
+    current().unwrap_or(PLATFORM_TENANT_ID)
+}
+
+/// 生成按租户区分的缓存键名
+pub fn cache_key(key: &str) -> String {
+    format!("{}:{key}", current_or_platform())
+}
+
+/// 获取请求上下文属性中的租户id
+pub fn from_ctx(ctx: &HttpContext) -> u32 {
+    match ctx.attr(ATTR_TENANT_ID).and_then(|v| v.as_u64()) {
+        Some(v) => v as u32,
+        None => PLATFORM_TENANT_ID,
+    }
+}
+
+/// 从请求头部解析租户id, 未指定时返回None
+pub fn from_header(ctx: &HttpContext) -> Result<Option<u32>> {
+    match ctx.header(TENANT_HEADER) {
+        Some(v) => match v.trim().parse() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => http_bail!("租户id格式错误: {}", v),
+        },
+        None => Ok(None),
+    }
+}
+
+/// 校验记录是否属于当前租户, 不在请求上下文中时不做校验
+///
+/// Arguments:
+///
+/// * `tenant_id`: 记录的租户id
+///
+pub fn check_owner(tenant_id: Option<u32>) -> Result<()> {
+    if let Some(tid) = current() {
+        if tenant_id.unwrap_or(PLATFORM_TENANT_ID) != tid {
+            http_bail!("记录不存在");
+        }
+    }
+    Ok(())
+}
+
+/// 校验当前租户是否为平台租户
+pub fn check_platform() -> Result<()> {
+    if current_or_platform() != PLATFORM_TENANT_ID {
+        http_bail!("只有平台管理员可以执行该操作");
+    }
+    Ok(())
+}
+
+/// 判断租户是否存在且未被禁用, 平台租户始终可用
+pub async fn is_enabled(tenant_id: u32) -> Result<bool> {
+    if tenant_id == PLATFORM_TENANT_ID {
+        return Ok(true);
+    }
+    let tenant = SysTenant::select_by_id_with_cache(tenant_id).await?;
+    Ok(tenant.is_some_and(|v| v.disabled == Some(0)))
+}
+
+/// 租户过滤条件, 要求过滤的表包含tenant_id字段
+pub struct TenantFilter;
+
+impl SqlFilter for TenantFilter {
+    fn apply<T: GeneratorSql>(&self, w: WhereSql<T>, table: &str) -> WhereSql<T> {
+        match current() {
+            Some(tid) => w.eq(table, TENANT_ID, tid),
+            None => w,
+        }
+    }
+}
@@ -196,6 +196,16 @@ pub fn try_connect(url: &str) -> Result<()> {
     Ok(())
 }
 
+/// 使用连接池中的连接执行PING命令, 用于服务运行期间检测redis是否可用
+pub async fn ping() -> Result<()> {
+    let val: String = query_async(&redis::cmd("PING")).await?;
+    if val != "PONG" {
+        anyhow_ext::bail!(format!("redis PING返回了错误的结果: {val}"));
+    }
+
+    Ok(())
+}
+
 async fn query_async<T: FromRedisValue>(cmd: &Cmd) -> Result<T> {
     let mut conn = get_conn().await.context("获取redis连接失败").dot()?;
     cmd.query_async(&mut conn).await.context("执行redis命令失败").dot()
@@ -315,6 +325,33 @@ pub async fn keys(key: &str) -> Option<Vec<String>> {
     }
 }
 
+/// 使用SCAN命令逐批查询匹配的键, 避免KEYS命令阻塞redis服务器
+pub async fn scan(key: &str) -> Option<Vec<String>> {
+    let mut cursor: u64 = 0;
+    let mut result = Vec::new();
+    loop {
+        let mut cmd = redis::cmd("SCAN");
+        cmd.arg(cursor).arg("MATCH").arg(key).arg("COUNT").arg(1024);
+
+        let (c, ret_keys): (u64, Vec<String>) = match query_async(&cmd).await.dot() {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("执行redis scan命令错误: {e:?}");
+                return None;
+            }
+        };
+        result.extend(ret_keys);
+
+        if c == 0 {
+            // 遍历期间键有变化时SCAN可能返回重复的键
+            result.sort_unstable();
+            result.dedup();
+            break Some(result);
+        }
+        cursor = c;
+    }
+}
+
 pub async fn incr(key: &str, delta: u64) -> Option<u64> {
     match query_async(&Cmd::incr(key, delta)).await.dot() {
         Ok(v) => v,
@@ -2,10 +2,11 @@ use anyhow_ext::{Context, Result};
 use serde::Serialize;
 use serde_json::{Map, Value};
 
-use crate::entities::sys_log::SysLog;
+use crate::{entities::sys_log::SysLog, services::tenant};
 
 // #region 审计类型常量定义
 pub const LOGIN: &str = "登录";
+pub const LOGIN_FAIL: &str = "登录失败";
 pub const LOGOUT: &str = "登出";
 pub const REFRESH: &str = "更新令牌";
 
@@ -13,12 +14,21 @@ pub const ACCOUNT_UPD: &str = "登录用户:修改";
 pub const ACCOUNT_UPD_PASSWORD: &str = "登录用户:改密";
 pub const ACCOUNT_UPD_MOBILE: &str = "登录用户:改手机";
 pub const ACCOUNT_UPD_EMAIL: &str = "登录用户:改邮箱";
+pub const ACCOUNT_MFA_ENABLE: &str = "登录用户:启用双因子认证";
+pub const ACCOUNT_MFA_DISABLE: &str = "登录用户:停用双因子认证";
+pub const ACCOUNT_REVOKE_SESSION: &str = "登录用户:注销会话";
 
 pub const USER_ADD: &str = "用户:添加";
 pub const USER_UPD: &str = "用户:修改";
 pub const USER_DEL: &str = "用户:删除";
+pub const USER_RESTORE: &str = "用户:恢复";
+pub const USER_PURGE: &str = "用户:彻底删除";
 pub const USER_UPD_STATUS: &str = "用户:改状态";
 pub const USER_UPD_PASSWORD: &str = "用户:改密";
+pub const USER_UPD_ROLES: &str = "用户:分配角色";
+pub const USER_REVOKE_SESSION: &str = "用户:注销会话";
+pub const USER_UNLOCK: &str = "用户:解除登录锁定";
+pub const USER_IMPORT: &str = "用户:批量导入";
 
 pub const CONFIG_ADD: &str = "配置:添加";
 pub const CONFIG_UPD: &str = "配置:修改";
@@ -47,6 +57,15 @@ pub const MENU_REARRANGE: &str = "菜单:排序";
 pub const ROLE_ADD: &str = "角色:添加";
 pub const ROLE_UPD: &str = "角色:修改";
 pub const ROLE_DEL: &str = "角色:删除";
+
+pub const DEPT_ADD: &str = "部门:添加";
+pub const DEPT_UPD: &str = "部门:修改";
+pub const DEPT_DEL: &str = "部门:删除";
+pub const DEPT_REARRANGE: &str = "部门:排序";
+
+pub const TENANT_ADD: &str = "租户:添加";
+pub const TENANT_UPD: &str = "租户:修改";
+pub const TENANT_UPD_STATUS: &str = "租户:改状态";
 // #endregion
 
 type Attrs<'a> = &'a [&'a str];
@@ -228,8 +247,10 @@ fn _diff(src: &str, dst: &str, require: Attrs, ignore: Attrs) -> Result<String>
 /// 尝试将日志信息追加到系统日志中，如果出现错误则记录错误信息
 fn _log(category: &str, user_id: u32, value: String) {
     let category = category.to_string();
+    // 异步任务中无法获取请求所属的租户, 需要提前获取
+    let tenant_id = tenant::current_or_platform();
     tokio::spawn(async move {
-        if let Err(e) = SysLog::append(category, user_id, value).await.dot() {
+        if let Err(e) = SysLog::append(tenant_id, category, user_id, value).await.dot() {
             log::error!("audit::log 写入审计日志错误: {e:?}");
         }
     });
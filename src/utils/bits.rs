@@ -332,6 +332,24 @@ pub fn string_to_bools(hex: &str) -> Vec<bool> {
     ret
 }
 
+/// 将16进制字符串`src`按位或到`dst`中, `dst`长度不足时后面补'0'
+///
+/// Arguments:
+///
+/// * `dst`: 目标16进制字符串
+/// * `src`: 源16进制字符串
+pub fn or(dst: &mut String, src: &str) {
+    let dst_vec = unsafe { dst.as_mut_vec() };
+    if src.len() > dst_vec.len() {
+        dst_vec.resize(src.len(), b'0');
+    }
+
+    for (d, s) in dst_vec.iter_mut().zip(src.as_bytes()) {
+        let b = c2b(*d) | c2b(*s);
+        *d = if b < 10 { 48 + b } else { 87 + b };
+    }
+}
+
 fn find_in_byte(b: u8, val: bool, off: usize, len: usize) -> Option<usize> {
     for i in off..(off + len) {
         let c = b & (8u8 >> i);
@@ -436,6 +454,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bits_or() {
+        let mut hex = String::new();
+        super::or(&mut hex, "1240");
+        assert_eq!("1240", hex);
+        super::or(&mut hex, "0a");
+        assert_eq!("1a40", hex);
+        super::or(&mut hex, "000081");
+        assert_eq!("1a4081", hex);
+    }
+
     #[test]
     fn test_bits_bools_to_string() {
         assert_eq!(
@@ -0,0 +1,119 @@
+//! 层级代码(部门代码、菜单代码等)相关的工具函数,
+//! 层级代码由各级固定长度的数字代码依次拼接而成, 同级节点按代码排序
+
+use anyhow_ext::{Context, Result};
+use httpserver::http_bail;
+
+/// 每一级代码的长度
+pub const CODE_LEN: usize = 2;
+/// 每一级允许的最大下级节点数量
+pub const MAX_CHILDREN: u32 = 99;
+
+/// 获取上级代码, 顶级节点返回空字符串
+pub fn parent_code(code: &str) -> &str {
+    &code[..code.len().saturating_sub(CODE_LEN)]
+}
+
+/// 生成上级代码下指定序号(从1开始)的下级代码
+pub fn child_code(parent_code: &str, n: u32) -> String {
+    format!("{parent_code}{n:0CODE_LEN$}")
+}
+
+/// 根据已有的最大下级代码生成新的下级代码
+///
+/// Arguments:
+///
+/// * `parent_code`: 上级代码
+/// * `max_code`: 上级代码下已有的最大下级代码, 没有下级节点时为None
+/// * `name`: 下级节点名称, 用于错误信息
+///
+pub fn next_code(parent_code: &str, max_code: Option<&str>, name: &str) -> Result<String> {
+    let n = match max_code {
+        Some(v) => {
+            v[v.len().saturating_sub(CODE_LEN)..]
+                .parse::<u32>()
+                .with_context(|| format!("{name}代码{v}格式错误"))?
+                + 1
+        }
+        None => 1,
+    };
+    check_children(n as usize, name)?;
+
+    Ok(child_code(parent_code, n))
+}
+
+/// 递归调用, 将树形结构转换为列表结构, 按节点在树中的位置重新生成代码
+///
+/// Arguments:
+///
+/// * `list`: 转换结果
+/// * `parent_code`: 上级代码
+/// * `nodes`: 同级节点
+/// * `name`: 下级节点名称, 用于错误信息
+/// * `children`: 获取节点的下级节点
+/// * `to_item`: 根据节点及其新代码生成列表项
+///
+pub fn tree_to_list<T, R>(
+    list: &mut Vec<R>,
+    parent_code: &str,
+    nodes: &[T],
+    name: &str,
+    children: &impl Fn(&T) -> Option<&[T]>,
+    to_item: &impl Fn(&T, String) -> Result<R>,
+) -> Result<()> {
+    check_children(nodes.len(), name)?;
+
+    for (i, node) in nodes.iter().enumerate() {
+        let code = child_code(parent_code, i as u32 + 1);
+        list.push(to_item(node, code.clone())?);
+
+        if let Some(sub_nodes) = children(node) {
+            tree_to_list(list, &code, sub_nodes, name, children, to_item)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 校验下级节点数量是否超出限制
+fn check_children(count: usize, name: &str) -> Result<()> {
+    if count > MAX_CHILDREN as usize {
+        http_bail!("{}数量不能超过{}", name, MAX_CHILDREN);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Node(Vec<Node>);
+
+    #[test]
+    fn test_code() {
+        assert_eq!(parent_code("0102"), "01");
+        assert_eq!(parent_code("01"), "");
+        assert_eq!(parent_code(""), "");
+        assert_eq!(child_code("01", 3), "0103");
+        assert_eq!(next_code("01", None, "下级部门").unwrap(), "0101");
+        assert_eq!(next_code("01", Some("0109"), "下级部门").unwrap(), "0110");
+        assert!(next_code("01", Some("0199"), "下级部门").is_err());
+        assert!(next_code("01", Some("01ab"), "下级部门").is_err());
+    }
+
+    #[test]
+    fn test_tree_to_list() {
+        let tree = [Node(vec![Node(vec![]), Node(vec![])]), Node(vec![])];
+        let mut list = Vec::new();
+        tree_to_list(
+            &mut list,
+            "",
+            &tree,
+            "下级部门",
+            &|v: &Node| Some(&v.0[..]),
+            &|_, code| Ok(code),
+        )
+        .unwrap();
+        assert_eq!(list, ["01", "0101", "0102", "02"]);
+    }
+}
@@ -2,11 +2,23 @@ pub const DEFAULT_PASSWORD: &str = "sysapi123456";
 pub const TOKEN_KEY: &str = "token";
 pub const INVALID_TOKEN_KEY: &str = "invalid:token";
 pub const CK_LOGIN_FAIL: &str = "login:fail";
+pub const CK_LOGIN_FAIL_IP: &str = "login:failIp";
+pub const CK_MFA_FAIL: &str = "login:mfaFail";
+pub const CK_RATE_LIMIT: &str = "rate:limit";
 pub const CC_LOGIN: &str = "login";
 pub const CC_LOGOUT: &str = "logout";
 
 pub mod cfg {
     pub const CK_DEFAULT_PASSWORD: &str = "缺省口令";
+    pub const CK_PWD_MIN_LENGTH: &str = "口令最小长度";
+    pub const CK_PWD_CHAR_CLASSES: &str = "口令字符类别数";
+    pub const CK_PWD_HISTORY_COUNT: &str = "口令历史次数";
+    pub const CK_PWD_MAX_AGE_DAYS: &str = "口令有效天数";
+    pub const CK_MFA_REQUIRED_ROLE_TYPES: &str = "强制双因子认证角色类型";
+    pub const CK_LOGIN_MAX_FAIL: &str = "登录失败锁定次数";
+    pub const CK_LOGIN_LOCK_SECS: &str = "登录锁定时长";
+    pub const CK_LOGIN_IP_MAX_FAIL: &str = "ip登录失败锁定次数";
+    pub const CK_RATE_LIMIT_RULES: &str = "接口限流规则";
     pub const MEM_CACHE_EXPIRE: u64 = 300;
     pub const REDIS_EXPIRE: u64 = 600;
 }
@@ -16,9 +28,11 @@ pub mod gmc {
     pub const TABLE_KEY: &str = "table";
     pub const SYS_API: &str = "sys:api";
     pub const SYS_CONFIG: &str = "sys:config";
+    pub const SYS_DEPT: &str = "sys:dept";
     pub const SYS_DICT: &str = "sys:dict";
     pub const SYS_MENU: &str = "sys:menu";
     pub const SYS_PERMISSION: &str = "sys:permission";
     pub const SYS_ROLE: &str = "sys:role";
+    pub const SYS_TENANT: &str = "sys:tenant";
     pub const SYS_USER: &str = "sys:user";
 }
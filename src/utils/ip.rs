@@ -0,0 +1,60 @@
+//! 客户端ip相关的工具函数
+
+use std::net::{IpAddr, Ipv6Addr};
+
+/// ipv6地址按前缀分组时保留的位数, 通常分配给单个用户的最小网段为/64
+const IPV6_PREFIX_LEN: u32 = 64;
+
+/// 生成按客户端ip计数(限流、登录锁定等)时使用的标识,
+/// ipv4地址使用地址本身, ipv6地址使用其所在的/64网段,
+/// 避免同一用户通过更换网段内的地址绕过限制
+///
+/// Arguments:
+///
+/// * `ip`: 客户端ip
+///
+pub fn ip_bucket(ip: &IpAddr) -> String {
+    match ip.to_canonical() {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX << (128 - IPV6_PREFIX_LEN);
+            let net = Ipv6Addr::from(u128::from(v6) & mask);
+            format!("{net}/{IPV6_PREFIX_LEN}")
+        }
+    }
+}
+
+/// 将文本格式的ip转换为计数标识, 无法解析时(如已经是网段格式)原样返回
+pub fn ip_bucket_str(ip: &str) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(v) => ip_bucket(&v),
+        Err(_) => ip.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_bucket() {
+        let ip: IpAddr = "192.168.1.10".parse().unwrap();
+        assert_eq!(ip_bucket(&ip), "192.168.1.10");
+
+        let ip: IpAddr = "2001:db8:1:2:3:4:5:6".parse().unwrap();
+        assert_eq!(ip_bucket(&ip), "2001:db8:1:2::/64");
+        let ip: IpAddr = "2001:db8:1:2:ffff::1".parse().unwrap();
+        assert_eq!(ip_bucket(&ip), "2001:db8:1:2::/64");
+
+        // ipv4映射的ipv6地址按ipv4处理
+        let ip: IpAddr = "::ffff:10.0.0.1".parse().unwrap();
+        assert_eq!(ip_bucket(&ip), "10.0.0.1");
+    }
+
+    #[test]
+    fn test_ip_bucket_str() {
+        assert_eq!(ip_bucket_str("2001:db8::1"), "2001:db8::/64");
+        assert_eq!(ip_bucket_str("2001:db8::/64"), "2001:db8::/64");
+        assert_eq!(ip_bucket_str("10.0.0.1"), "10.0.0.1");
+    }
+}
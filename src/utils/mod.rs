@@ -4,19 +4,26 @@
 pub mod audit;
 #[allow(dead_code)]
 pub mod bits;
+pub mod code_tree;
 pub mod consts;
+pub mod ip;
 pub mod kv;
 #[allow(dead_code)]
 pub mod md5_crypt;
 pub mod multi_cache;
+pub mod password;
+pub mod rate_limiter;
 // #[allow(dead_code)]
 // pub mod multi_level;
 #[allow(dead_code)]
 pub mod rcall;
+pub mod route_matcher;
+pub mod sheet;
 #[allow(dead_code)]
 pub mod staticmut;
 // #[allow(dead_code)]
 pub mod time;
+pub mod totp;
 pub mod uni_redis;
 
 pub use kv::*;
@@ -7,7 +7,7 @@ use anyhow_ext::{bail, Result};
 use mini_moka::sync::Iter;
 use serde::{de::DeserializeOwned, Serialize};
 
-use std::{hash::{Hash, RandomState}, sync::Arc, time::Duration};
+use std::{hash::{Hash, RandomState}, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Duration};
 
 use super::uni_redis::UniRedis;
 
@@ -18,6 +18,15 @@ pub struct MultiCache<K, V, R: UniRedis> {
     redis_cache: R,
     redis_prefix: String,
     redis_expire: Duration,
+    stats: CacheStats,
+}
+
+/// 缓存命中统计, 记录内存缓存命中、redis缓存命中及两级缓存均未命中的次数
+#[derive(Default)]
+pub struct CacheStats {
+    memory_hits: AtomicU64,
+    redis_hits: AtomicU64,
+    misses: AtomicU64,
 }
 
 pub struct Builder<R> {
@@ -61,6 +70,7 @@ where
             redis_cache,
             redis_prefix,
             redis_expire,
+            stats: CacheStats::default(),
         }
     }
 
@@ -87,11 +97,13 @@ where
     /// * `use_decompress`: 是否进行解压缩
     ///
     pub async fn get_with(&self, key: &K, use_lz4: bool) -> Option<V> {
-        let mut value = self.memory_cache.get(key);
-        if value.is_none() {
-            let key = self.redis_key(key.as_ref());
-            value = self.redis_cache.get_json(&key, use_lz4).await
+        if let Some(value) = self.memory_cache.get(key) {
+            self.stats.hit_memory();
+            return Some(value);
         }
+        let key = self.redis_key(key.as_ref());
+        let value = self.redis_cache.get_json(&key, use_lz4).await;
+        self.stats.record_redis(value.is_some());
         value
     }
 
@@ -143,6 +155,11 @@ where
         self.memory_cache.iter()
     }
 
+    /// 缓存命中统计
+    pub fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
     fn redis_key(&self, key: &str) -> String {
         let mut result = self.redis_prefix.clone();
         if !self.redis_prefix.is_empty() {
@@ -166,11 +183,13 @@ where
     /// * `key`: 缓存键
     ///
     pub async fn get_str(&self, key: &K) -> Option<Arc<String>> {
-        let mut value = self.memory_cache.get(key);
-        if value.is_none() {
-            let key = self.redis_key(key.as_ref());
-            value = self.redis_cache.get(&key).await;
+        if let Some(value) = self.memory_cache.get(key) {
+            self.stats.hit_memory();
+            return Some(value);
         }
+        let key = self.redis_key(key.as_ref());
+        let value = self.redis_cache.get(&key).await;
+        self.stats.record_redis(value.is_some());
         value
     }
 
@@ -190,6 +209,33 @@ where
 
 }
 
+impl CacheStats {
+    /// 记录1次内存缓存命中
+    pub fn hit_memory(&self) {
+        self.memory_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录1次内存缓存未命中后的redis缓存读取结果
+    pub fn record_redis(&self, hit: bool) {
+        let counter = if hit { &self.redis_hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 内存缓存命中次数
+    pub fn memory_hits(&self) -> u64 {
+        self.memory_hits.load(Ordering::Relaxed)
+    }
+
+    /// redis缓存命中次数
+    pub fn redis_hits(&self) -> u64 {
+        self.redis_hits.load(Ordering::Relaxed)
+    }
+
+    /// 内存缓存及redis缓存均未命中的次数
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
 
 impl<R> Builder<R> {
     pub fn new() -> Self {
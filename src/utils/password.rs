@@ -0,0 +1,163 @@
+//! 口令加密格式注册表
+//!
+//! 加密后的口令以`$标识$`开头, 根据前缀查找对应的算法进行校验,
+//! 新生成的口令统一使用缺省算法(Argon2id)
+use anyhow_ext::{anyhow, bail, Result};
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+
+use super::md5_crypt;
+
+/// 口令加密算法接口
+pub trait HashFormat: Send + Sync {
+    /// 算法前缀, 格式为`$标识$`
+    fn prefix(&self) -> &'static str;
+
+    /// 口令加密
+    fn encrypt(&self, password: &str) -> Result<String>;
+
+    /// 口令校验
+    fn verify(&self, pw_plain: &str, pw_encrypt: &str) -> Result<bool>;
+}
+
+/// 旧版自定义MD5加密算法, 仅用于校验历史口令
+pub struct Md5Crypt;
+
+/// Argon2id加密算法, 采用PHC字符串格式保存
+pub struct Argon2id;
+
+/// 已注册的加密算法, 第一个为缺省算法
+static FORMATS: [&dyn HashFormat; 2] = [&Argon2id, &Md5Crypt];
+
+impl HashFormat for Md5Crypt {
+    fn prefix(&self) -> &'static str {
+        "$74$"
+    }
+
+    fn encrypt(&self, password: &str) -> Result<String> {
+        md5_crypt::encrypt(password)
+    }
+
+    fn verify(&self, pw_plain: &str, pw_encrypt: &str) -> Result<bool> {
+        md5_crypt::verify(pw_plain, pw_encrypt)
+    }
+}
+
+impl HashFormat for Argon2id {
+    fn prefix(&self) -> &'static str {
+        "$argon2id$"
+    }
+
+    fn encrypt(&self, password: &str) -> Result<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| anyhow!("argon2加密口令失败: {e}"))?;
+
+        Ok(hash.to_string())
+    }
+
+    fn verify(&self, pw_plain: &str, pw_encrypt: &str) -> Result<bool> {
+        let hash = PasswordHash::new(pw_encrypt).map_err(|e| anyhow!("密码格式错误: {e}"))?;
+
+        Ok(Argon2::default()
+            .verify_password(pw_plain.as_bytes(), &hash)
+            .is_ok())
+    }
+}
+
+/// 使用缺省算法加密口令
+pub fn encrypt(password: &str) -> Result<String> {
+    default_format().encrypt(password)
+}
+
+/// 根据加密口令的前缀选择对应的算法进行校验
+pub fn verify(pw_plain: &str, pw_encrypt: &str) -> Result<bool> {
+    match find_format(pw_encrypt) {
+        Some(f) => f.verify(pw_plain, pw_encrypt),
+        None => bail!("不支持的密码格式"),
+    }
+}
+
+/// 在阻塞线程池中加密口令, 避免耗时的加密运算阻塞异步运行时
+pub async fn encrypt_async(password: &str) -> Result<String> {
+    let password = password.to_owned();
+    tokio::task::spawn_blocking(move || encrypt(&password))
+        .await
+        .map_err(|e| anyhow!("加密口令任务执行失败: {e}"))?
+}
+
+/// 在阻塞线程池中校验口令, 避免耗时的校验运算阻塞异步运行时
+pub async fn verify_async(pw_plain: &str, pw_encrypt: &str) -> Result<bool> {
+    let (pw_plain, pw_encrypt) = (pw_plain.to_owned(), pw_encrypt.to_owned());
+    tokio::task::spawn_blocking(move || verify(&pw_plain, &pw_encrypt))
+        .await
+        .map_err(|e| anyhow!("校验口令任务执行失败: {e}"))?
+}
+
+/// 在阻塞线程池中批量加密口令, 用于批量导入等需要加密大量口令的场景
+pub async fn encrypt_batch_async(passwords: Vec<String>) -> Result<Vec<String>> {
+    tokio::task::spawn_blocking(move || passwords.iter().map(|v| encrypt(v)).collect())
+        .await
+        .map_err(|e| anyhow!("加密口令任务执行失败: {e}"))?
+}
+
+/// 在阻塞线程池中逐个校验加密口令列表, 返回第一个与明文匹配的口令的索引
+pub async fn position_async(pw_plain: &str, pw_encrypts: Vec<String>) -> Result<Option<usize>> {
+    let pw_plain = pw_plain.to_owned();
+    tokio::task::spawn_blocking(move || {
+        pw_encrypts
+            .iter()
+            .position(|v| verify(&pw_plain, v).unwrap_or(false))
+    })
+    .await
+    .map_err(|e| anyhow!("校验口令任务执行失败: {e}"))
+}
+
+/// 判断加密口令是否需要使用缺省算法重新加密
+pub fn need_rehash(pw_encrypt: &str) -> bool {
+    get_prefix(pw_encrypt) != Some(default_format().prefix())
+}
+
+fn default_format() -> &'static dyn HashFormat {
+    FORMATS[0]
+}
+
+fn find_format(pw_encrypt: &str) -> Option<&'static dyn HashFormat> {
+    let prefix = get_prefix(pw_encrypt)?;
+    FORMATS.iter().find(|f| f.prefix() == prefix).copied()
+}
+
+/// 获取`$标识$`格式的前缀
+fn get_prefix(pw_encrypt: &str) -> Option<&str> {
+    if !pw_encrypt.starts_with('$') {
+        return None;
+    }
+    pw_encrypt[1..].find('$').map(|pos| &pw_encrypt[..pos + 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encrypt, get_prefix, need_rehash, verify};
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!(Some("$74$"), get_prefix("$74$AtXyaPfN$72lU8lC7chwBrLucD4ZYD."));
+        assert_eq!(Some("$argon2id$"), get_prefix("$argon2id$v=19$m=19456,t=2,p=1$abc$def"));
+        assert_eq!(None, get_prefix("password"));
+    }
+
+    #[test]
+    fn test_verify() {
+        const ENC: &str = "$74$AtXyaPfN$72lU8lC7chwBrLucD4ZYD.";
+        assert!(verify("password", ENC).unwrap());
+        assert!(need_rehash(ENC));
+
+        let enc = encrypt("password").unwrap();
+        assert!(verify("password", &enc).unwrap());
+        assert!(!verify("Password", &enc).unwrap());
+        assert!(!need_rehash(&enc));
+    }
+}
@@ -0,0 +1,255 @@
+//! 限流算法, 包括令牌桶及滑动窗口计数器, 时间单位统一为毫秒
+//!
+//! 状态的更新与判定结果的计算分离, 以便redis后端在脚本中更新状态后复用相同的计算逻辑
+
+/// 限流判定结果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decision {
+    /// 是否允许本次请求
+    pub allowed: bool,
+    /// 周期内允许的请求数
+    pub limit: u32,
+    /// 剩余可用的请求数
+    pub remaining: u32,
+    /// 配额恢复所需的时长(单位: 秒)
+    pub reset: u64,
+    /// 请求被拒绝时, 距离下次允许请求的时长(单位: 秒), 允许时为0
+    pub retry_after: u64,
+}
+
+/// 令牌桶, 以固定速率补充令牌, 桶满时允许突发请求
+#[derive(Clone, Copy, Debug)]
+pub struct TokenBucket {
+    tokens: f64,
+    updated: u64,
+}
+
+/// 滑动窗口计数器, 用上一个固定窗口的计数按时间比例加权估算滑动窗口内的请求数
+#[derive(Clone, Copy, Debug)]
+pub struct SlidingWindow {
+    start: u64,
+    prev: u32,
+    count: u32,
+}
+
+impl TokenBucket {
+    /// 创建装满令牌的令牌桶
+    ///
+    /// Arguments:
+    ///
+    /// * `capacity`: 桶容量, 即允许的突发请求数
+    /// * `now`: 当前时间
+    ///
+    pub fn new(capacity: u32, now: u64) -> Self {
+        Self {
+            tokens: capacity as f64,
+            updated: now,
+        }
+    }
+
+    /// 补充令牌后尝试获取1个令牌
+    ///
+    /// Arguments:
+    ///
+    /// * `capacity`: 桶容量
+    /// * `rate`: 每毫秒补充的令牌数
+    /// * `now`: 当前时间
+    ///
+    pub fn acquire(&mut self, capacity: u32, rate: f64, now: u64) -> Decision {
+        let elapsed = now.saturating_sub(self.updated) as f64;
+        let tokens = (self.tokens + elapsed * rate).min(capacity as f64);
+        let allowed = tokens >= 1.0;
+
+        self.tokens = if allowed { tokens - 1.0 } else { tokens };
+        self.updated = self.updated.max(now);
+
+        token_bucket_decision(allowed, capacity, rate, self.tokens)
+    }
+}
+
+impl SlidingWindow {
+    /// 创建空的计数器
+    ///
+    /// Arguments:
+    ///
+    /// * `window`: 窗口时长
+    /// * `now`: 当前时间
+    ///
+    pub fn new(window: u64, now: u64) -> Self {
+        Self {
+            start: now - now % window,
+            prev: 0,
+            count: 0,
+        }
+    }
+
+    /// 估算的请求数未达到限制值时计数加1
+    ///
+    /// Arguments:
+    ///
+    /// * `limit`: 窗口内允许的请求数
+    /// * `window`: 窗口时长
+    /// * `now`: 当前时间
+    ///
+    pub fn acquire(&mut self, limit: u32, window: u64, now: u64) -> Decision {
+        let start = now - now % window;
+        if start > self.start {
+            // 紧邻的上一个窗口的计数参与加权, 间隔超过1个窗口时清零
+            self.prev = if start - self.start == window {
+                self.count
+            } else {
+                0
+            };
+            self.count = 0;
+            self.start = start;
+        }
+
+        let elapsed = now.saturating_sub(self.start);
+        let allowed = weighted_count(self.prev, self.count, elapsed, window) < limit as f64;
+        if allowed {
+            self.count += 1;
+        }
+
+        sliding_window_decision(allowed, limit, window, self.prev, self.count, elapsed)
+    }
+}
+
+/// 根据令牌桶中剩余的令牌数生成判定结果
+///
+/// Arguments:
+///
+/// * `allowed`: 是否成功获取令牌
+/// * `capacity`: 桶容量
+/// * `rate`: 每毫秒补充的令牌数
+/// * `tokens`: 本次请求后剩余的令牌数
+///
+pub fn token_bucket_decision(allowed: bool, capacity: u32, rate: f64, tokens: f64) -> Decision {
+    let retry_after = if allowed {
+        0
+    } else {
+        ms_to_secs((1.0 - tokens) / rate)
+    };
+
+    Decision {
+        allowed,
+        limit: capacity,
+        remaining: tokens.max(0.0) as u32,
+        reset: ms_to_secs((capacity as f64 - tokens) / rate),
+        retry_after,
+    }
+}
+
+/// 根据滑动窗口的计数生成判定结果
+///
+/// Arguments:
+///
+/// * `allowed`: 是否允许本次请求
+/// * `limit`: 窗口内允许的请求数
+/// * `window`: 窗口时长
+/// * `prev`: 上一个窗口的请求数
+/// * `count`: 当前窗口的请求数(包含本次允许的请求)
+/// * `elapsed`: 当前窗口已经过的时长
+///
+pub fn sliding_window_decision(
+    allowed: bool,
+    limit: u32,
+    window: u64,
+    prev: u32,
+    count: u32,
+    elapsed: u64,
+) -> Decision {
+    let used = weighted_count(prev, count, elapsed, window);
+    let (limit_f, window_f, elapsed_f) = (limit as f64, window as f64, elapsed as f64);
+
+    let retry_after = if allowed {
+        0
+    } else if count < limit {
+        // 上一窗口的权重降低到足以容纳1个请求的时间点
+        let at = window_f - (limit_f - count as f64) * window_f / prev as f64;
+        ms_to_secs(at - elapsed_f)
+    } else {
+        // 当前窗口已满, 需等到下一窗口中当前窗口的权重足够低
+        let at = window_f + window_f * (1.0 - limit_f / count as f64);
+        ms_to_secs(at - elapsed_f)
+    };
+
+    Decision {
+        allowed,
+        limit,
+        remaining: (limit_f - used).max(0.0) as u32,
+        reset: ms_to_secs(window_f - elapsed_f),
+        retry_after,
+    }
+}
+
+/// 加权后的滑动窗口请求数
+fn weighted_count(prev: u32, count: u32, elapsed: u64, window: u64) -> f64 {
+    let elapsed = elapsed.min(window);
+    prev as f64 * (window - elapsed) as f64 / window as f64 + count as f64
+}
+
+/// 毫秒转换为秒, 向上取整且最少为1秒
+fn ms_to_secs(ms: f64) -> u64 {
+    ((ms / 1000.0).ceil() as u64).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        // 容量3, 每秒补充1个令牌
+        let rate = 1.0 / 1000.0;
+        let mut tb = TokenBucket::new(3, 0);
+        for i in 0..3 {
+            let d = tb.acquire(3, rate, 0);
+            assert!(d.allowed);
+            assert_eq!(d.remaining, 2 - i);
+        }
+
+        let d = tb.acquire(3, rate, 0);
+        assert!(!d.allowed);
+        assert_eq!(d.retry_after, 1);
+        assert_eq!(d.reset, 3);
+
+        let d = tb.acquire(3, rate, 1500);
+        assert!(d.allowed);
+        assert_eq!(d.remaining, 0);
+        assert!(!tb.acquire(3, rate, 1600).allowed);
+
+        // 长时间空闲后令牌数不超过桶容量
+        let d = tb.acquire(3, rate, 100_000);
+        assert!(d.allowed);
+        assert_eq!(d.remaining, 2);
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        // 每10秒允许4个请求
+        let mut sw = SlidingWindow::new(10_000, 0);
+        for _ in 0..4 {
+            assert!(sw.acquire(4, 10_000, 1000).allowed);
+        }
+        let d = sw.acquire(4, 10_000, 2000);
+        assert!(!d.allowed);
+        assert_eq!(d.remaining, 0);
+        assert_eq!(d.reset, 8);
+        // 当前窗口已满, 需等到下一窗口
+        assert_eq!(d.retry_after, 8);
+
+        // 下一窗口经过1/5时, 上一窗口的4个请求加权后为3.2
+        let d = sw.acquire(4, 10_000, 12_000);
+        assert!(d.allowed);
+        assert_eq!(d.remaining, 0);
+        let d = sw.acquire(4, 10_000, 12_500);
+        assert!(!d.allowed);
+        assert_eq!(d.retry_after, 1);
+        assert!(sw.acquire(4, 10_000, 13_000).allowed);
+
+        // 间隔超过1个窗口时计数清零
+        let d = sw.acquire(4, 10_000, 35_000);
+        assert!(d.allowed);
+        assert_eq!(d.remaining, 3);
+    }
+}
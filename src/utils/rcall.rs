@@ -1,7 +1,7 @@
 //! 远程调用服务
 use anyhow_ext::{Context, Result};
 use http_body_util::{BodyExt, Full};
-use httpserver::{ApiResult, Bytes};
+use httpserver::{trace, ApiResult, Bytes};
 use hyper::{body::Buf, Method, Request};
 use hyper_util::{
     client::legacy::{connect::HttpConnector, Client},
@@ -9,7 +9,10 @@ use hyper_util::{
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use std::time::Duration;
+use std::{
+    sync::atomic::{AtomicBool, AtomicI64, Ordering},
+    time::Duration,
+};
 
 use crate::{AppConf, AppGlobal};
 
@@ -22,6 +25,8 @@ const CFG_ACTION: &str = "向网关服务器获取配置信息";
 const TOKEN_ACTION: &str = "请求网关服务器生成token";
 
 static SERVICE_PATHS: [&str; 1] = ["/api/sys"];
+static REG_OK: AtomicBool = AtomicBool::new(false); // 最近1次注册是否成功
+static REG_LAST_SUCCESS: AtomicI64 = AtomicI64::new(0); // 最近1次注册成功的时间
 
 #[derive(Deserialize)]
 pub struct CfgItem {
@@ -65,7 +70,7 @@ pub async fn reg_to_gateway() -> Result<()> {
         paths: &'a [&'a str],
     }
 
-    let _: Option<()> = post(
+    let res: Result<Option<()>> = post(
         REG_PATH,
         &Req {
             endpoint: &AppConf::get().listen,
@@ -73,11 +78,26 @@ pub async fn reg_to_gateway() -> Result<()> {
         },
     )
     .await
-    .context(REG_ACTION)?;
+    .context(REG_ACTION);
+
+    REG_OK.store(res.is_ok(), Ordering::Relaxed);
+    if res.is_ok() {
+        let now = crate::utils::time::unix_timestamp() as i64;
+        REG_LAST_SUCCESS.store(now, Ordering::Relaxed);
+    }
+    res?;
 
     Ok(())
 }
 
+/// 获取最近1次向网关注册服务的结果, 返回(是否成功, 最近1次成功的时间), 从未成功时时间为0
+pub fn reg_status() -> (bool, i64) {
+    (
+        REG_OK.load(Ordering::Relaxed),
+        REG_LAST_SUCCESS.load(Ordering::Relaxed),
+    )
+}
+
 /// 远程调用网关服务器的token生成服务
 pub async fn gen_token<T: Serialize>(claim: &T) -> Result<String> {
     #[derive(Serialize)]
@@ -135,9 +155,18 @@ where
     T: Serialize,
     R: DeserializeOwned,
 {
-    let req = Request::builder()
+    let mut builder = Request::builder()
         .method(Method::POST)
-        .uri(format!("http://{}{}", AppConf::get().gateway, path))
+        .uri(format!("http://{}{}", AppConf::get().gateway, path));
+
+    // 在http请求处理过程中发起的调用, 将追踪信息传递给网关
+    if let Some(tc) = trace::current() {
+        builder = builder
+            .header(trace::TRACEPARENT, tc.traceparent())
+            .header(trace::X_REQUEST_ID, tc.request_id);
+    }
+
+    let req = builder
         .body(Full::<Bytes>::from(serde_json::to_vec(param)?))
         .with_context(|| format!("远程调用{path}, 构建请求体对象失败"))?;
 
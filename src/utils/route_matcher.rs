@@ -0,0 +1,279 @@
+//! 接口路径匹配器, 将接口权限规则编译为路径模式, 多条规则同时匹配时具体程度最高的规则生效
+//!
+//! 规则格式: `[METHOD[,METHOD...] ]/path/...`, 未指定请求方法时匹配所有方法, 路径中每一段支持:
+//! * `name`: 字面量, 精确匹配
+//! * `*` 或 `{param}`: 匹配任意一段
+//! * `**`: 匹配任意多段(包括0段)
+//!
+//! 为兼容按目录逐级匹配的规则, 每条规则同时匹配其所有的下级路径, 如`/sys/user`匹配`/sys/user/list`.
+//! 规则的具体程度从第一段开始逐段比较: 字面量 > 单段通配 > 多段通配, 段数多者优先, 最后指定了请求方法者优先
+use std::{cmp::Ordering, collections::HashMap, sync::Arc};
+
+const ANY: &str = "*";
+const ANY_MULTI: &str = "**";
+
+/// 编译时用于合并相同模式的规则: (请求方法, 路径段) => (原始规则, 规则值列表)
+type RuleMap<V> = HashMap<(Vec<String>, Vec<Segment>), (String, Vec<V>)>;
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Any,
+    AnyMulti,
+}
+
+impl Segment {
+    fn parse(s: &str) -> Self {
+        if s == ANY_MULTI {
+            Segment::AnyMulti
+        } else if s == ANY || (s.len() > 2 && s.starts_with('{') && s.ends_with('}')) {
+            Segment::Any
+        } else {
+            Segment::Literal(s.to_owned())
+        }
+    }
+
+    /// 匹配优先级, 数值越大越具体
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Literal(_) => 3,
+            Segment::Any => 2,
+            Segment::AnyMulti => 1,
+        }
+    }
+}
+
+/// 编译后的路由规则
+struct Route<V> {
+    /// 允许的请求方法(大写), 为空表示匹配所有方法
+    methods: Vec<String>,
+    segments: Vec<Segment>,
+    /// 原始规则, 同一模式有多条规则时取第一条
+    pattern: String,
+    values: Arc<Vec<V>>,
+}
+
+impl<V> Route<V> {
+    fn is_match(&self, method: &str, path: &[&str]) -> bool {
+        (self.methods.is_empty() || self.methods.iter().any(|m| m.eq_ignore_ascii_case(method)))
+            && match_segments(&self.segments, path)
+    }
+
+    /// 比较两条规则的具体程度, 更具体的规则排在前面
+    fn specificity_cmp(&self, other: &Self) -> Ordering {
+        let ranks = self.segments.iter().map(Segment::rank);
+        let other_ranks = other.segments.iter().map(Segment::rank);
+        other_ranks
+            .cmp(ranks)
+            .then_with(|| self.methods.is_empty().cmp(&other.methods.is_empty()))
+    }
+}
+
+/// 接口路径匹配器
+pub struct RouteMatcher<V> {
+    /// 按具体程度从高到低排列的规则
+    routes: Vec<Route<V>>,
+}
+
+impl<V> RouteMatcher<V> {
+    /// 编译规则列表, 模式相同(忽略参数名称及末尾的多段通配)的规则合并为1条, 规则值合并为列表
+    ///
+    /// Arguments:
+    ///
+    /// * `rules`: 规则及其对应的值
+    ///
+    pub fn compile<S: AsRef<str>, I: IntoIterator<Item = (S, V)>>(rules: I) -> Self {
+        let mut map: RuleMap<V> = HashMap::new();
+
+        for (pattern, value) in rules {
+            let pattern = pattern.as_ref().trim();
+            let (methods, segments) = parse_pattern(pattern);
+            let entry = map
+                .entry((methods, segments))
+                .or_insert_with(|| (pattern.to_owned(), Vec::new()));
+            if entry.0 != pattern {
+                log::debug!("接口规则[{pattern}]与[{}]的模式相同, 合并处理", entry.0);
+            }
+            entry.1.push(value);
+        }
+
+        let mut routes: Vec<_> = map
+            .into_iter()
+            .map(|((methods, segments), (pattern, values))| Route {
+                methods,
+                segments,
+                pattern,
+                values: Arc::new(values),
+            })
+            .collect();
+        // 具体程度相同时按规则文本排序, 确保结果稳定
+        routes.sort_by(|a, b| a.specificity_cmp(b).then_with(|| a.pattern.cmp(&b.pattern)));
+
+        Self { routes }
+    }
+
+    /// 查找与请求最匹配的规则, 返回规则文本及规则值
+    ///
+    /// Arguments:
+    ///
+    /// * `method`: 请求方法, 为空时只匹配未指定请求方法的规则
+    /// * `path`: 请求路径
+    ///
+    pub fn find(&self, method: &str, path: &str) -> Option<(&str, &Arc<Vec<V>>)> {
+        let path: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        self.routes
+            .iter()
+            .find(|r| r.is_match(method, &path))
+            .map(|r| (r.pattern.as_str(), &r.values))
+    }
+
+    /// 规则数量
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+impl<V: std::fmt::Debug> std::fmt::Debug for RouteMatcher<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.routes.iter().map(|r| (&r.pattern, &r.values)))
+            .finish()
+    }
+}
+
+/// 解析规则文本, 返回请求方法列表及路径段
+fn parse_pattern(pattern: &str) -> (Vec<String>, Vec<Segment>) {
+    let (methods, path) = match pattern.split_once(char::is_whitespace) {
+        Some((m, p)) if !m.starts_with('/') => (m, p.trim()),
+        _ => ("", pattern),
+    };
+
+    let mut methods: Vec<String> = methods
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_ascii_uppercase)
+        .collect();
+    methods.sort();
+    methods.dedup();
+
+    let mut segments: Vec<Segment> = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(Segment::parse)
+        .collect();
+    // 末尾的多段通配与规则隐含的下级路径匹配等价
+    while segments.last() == Some(&Segment::AnyMulti) {
+        segments.pop();
+    }
+
+    (methods, segments)
+}
+
+fn match_segments(pattern: &[Segment], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        // 规则已匹配完毕, 剩余的路径视为下级路径
+        None => true,
+        Some((Segment::AnyMulti, rest)) => {
+            (0..=path.len()).any(|i| match_segments(rest, &path[i..]))
+        }
+        Some((seg, rest)) => match path.split_first() {
+            Some((p, path_rest)) => {
+                let ok = match seg {
+                    Segment::Literal(s) => s == p,
+                    _ => true,
+                };
+                ok && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RouteMatcher;
+
+    fn find<'a>(m: &'a RouteMatcher<i16>, method: &str, path: &str) -> Option<&'a str> {
+        m.find(method, path).map(|(p, _)| p)
+    }
+
+    #[test]
+    fn test_prefix_match() {
+        let m = RouteMatcher::compile([("/", 0), ("/sys", 1), ("/sys/user", 2)]);
+        assert_eq!(Some("/sys/user"), find(&m, "GET", "/sys/user/list"));
+        assert_eq!(Some("/sys/user"), find(&m, "GET", "/sys/user/"));
+        assert_eq!(Some("/sys"), find(&m, "GET", "/sys/role/list"));
+        assert_eq!(Some("/"), find(&m, "GET", "/other"));
+
+        let m = RouteMatcher::compile([("/sys/user", 2)]);
+        assert_eq!(None, find(&m, "GET", "/sys/users"));
+    }
+
+    #[test]
+    fn test_wildcard() {
+        let m = RouteMatcher::compile([
+            ("/sys/user", 0),
+            ("/sys/user/*/reset", 1),
+            ("/sys/user/{id}", 2),
+            ("/sys/**/export", 3),
+        ]);
+        assert_eq!(
+            Some("/sys/user/*/reset"),
+            find(&m, "POST", "/sys/user/12/reset")
+        );
+        assert_eq!(Some("/sys/user/{id}"), find(&m, "POST", "/sys/user/12"));
+        assert_eq!(
+            Some("/sys/user/{id}"),
+            find(&m, "POST", "/sys/user/12/other")
+        );
+        assert_eq!(
+            Some("/sys/**/export"),
+            find(&m, "POST", "/sys/dict/type/export")
+        );
+        assert_eq!(Some("/sys/**/export"), find(&m, "POST", "/sys/export"));
+        assert_eq!(None, find(&m, "POST", "/api/export"));
+    }
+
+    #[test]
+    fn test_priority() {
+        let m = RouteMatcher::compile([("/sys/*/list", 0), ("/sys/user/*", 1)]);
+        assert_eq!(Some("/sys/user/*"), find(&m, "GET", "/sys/user/list"));
+        assert_eq!(Some("/sys/*/list"), find(&m, "GET", "/sys/role/list"));
+
+        let m = RouteMatcher::compile([("/sys/**", 0), ("/sys/*", 1)]);
+        assert_eq!(Some("/sys/*"), find(&m, "GET", "/sys/user"));
+    }
+
+    #[test]
+    fn test_method() {
+        let m = RouteMatcher::compile([
+            ("/rest/user", 0),
+            ("GET /rest/user/{id}", 1),
+            ("put,delete /rest/user/{id}", 2),
+        ]);
+        assert_eq!(Some("GET /rest/user/{id}"), find(&m, "get", "/rest/user/1"));
+        assert_eq!(
+            Some("put,delete /rest/user/{id}"),
+            find(&m, "DELETE", "/rest/user/1")
+        );
+        assert_eq!(Some("/rest/user"), find(&m, "POST", "/rest/user/1"));
+        assert_eq!(Some("/rest/user"), find(&m, "", "/rest/user/1"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let m = RouteMatcher::compile([
+            ("/sys/user/{id}", 1),
+            ("/sys/user/*", 2),
+            ("/sys/user/*/**", 3),
+        ]);
+        assert_eq!(1, m.len());
+        let (_, values) = m.find("GET", "/sys/user/1").unwrap();
+        assert_eq!(&[1, 2, 3], values.as_slice());
+    }
+}
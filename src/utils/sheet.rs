@@ -0,0 +1,294 @@
+//! 表格文件(csv/xlsx)读写, 用于数据的批量导入导出
+use anyhow_ext::{anyhow, bail, Context, Result};
+use calamine::Reader;
+use rust_xlsxwriter::{Workbook, Worksheet};
+use std::io::Cursor;
+
+pub const CSV_CONTENT_TYPE: &str = "text/csv;charset=UTF-8";
+pub const XLSX_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+/// 以这些字符开头的单元格内容会被excel等表格软件当作公式执行
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+/// 写入csv文件时在公式内容前添加的转义字符, 读取时去除
+const FORMULA_ESCAPE: char = '\'';
+
+/// 表格文件格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SheetFormat {
+    Csv,
+    Xlsx,
+}
+
+impl SheetFormat {
+    /// 根据格式名称(csv/xlsx)获取文件格式, 名称不区分大小写
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("csv") {
+            Some(Self::Csv)
+        } else if name.eq_ignore_ascii_case("xlsx") {
+            Some(Self::Xlsx)
+        } else {
+            None
+        }
+    }
+
+    /// 根据http请求的Content-Type获取文件格式
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        if mime.eq_ignore_ascii_case("text/csv") {
+            Some(Self::Csv)
+        } else if mime.eq_ignore_ascii_case(XLSX_CONTENT_TYPE) {
+            Some(Self::Xlsx)
+        } else {
+            None
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => CSV_CONTENT_TYPE,
+            Self::Xlsx => XLSX_CONTENT_TYPE,
+        }
+    }
+
+    pub fn ext(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+        }
+    }
+}
+
+/// 读取表格文件的所有行, xlsx格式只读取第一个工作表, 单元格内容会去除首尾空白
+///
+/// Arguments:
+///
+/// * `format`: 文件格式
+/// * `data`: 文件内容
+///
+pub fn read(format: SheetFormat, data: &[u8]) -> Result<Vec<Vec<String>>> {
+    match format {
+        SheetFormat::Csv => read_csv(data),
+        SheetFormat::Xlsx => read_xlsx(data),
+    }
+}
+
+/// 判断行是否为空行(所有单元格内容均为空)
+pub fn is_empty_row(row: &[String]) -> bool {
+    row.iter().all(|v| v.is_empty())
+}
+
+fn read_csv(data: &[u8]) -> Result<Vec<Vec<String>>> {
+    let data = data.strip_prefix(UTF8_BOM).unwrap_or(data);
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data);
+
+    let mut rows = Vec::new();
+    for rec in reader.records() {
+        let rec = rec.context("csv文件格式错误")?;
+        rows.push(
+            rec.iter()
+                .map(|v| unescape_formula(v.trim()).to_owned())
+                .collect(),
+        );
+    }
+
+    Ok(rows)
+}
+
+fn read_xlsx(data: &[u8]) -> Result<Vec<Vec<String>>> {
+    let mut wb = calamine::Xlsx::new(Cursor::new(data)).context("xlsx文件格式错误")?;
+    let range = wb
+        .worksheet_range_at(0)
+        .ok_or_else(|| anyhow!("xlsx文件中没有工作表"))?
+        .context("读取xlsx工作表失败")?;
+
+    let rows = range
+        .rows()
+        .map(|row| {
+            row.iter()
+                .map(|v| v.to_string().trim().to_owned())
+                .collect()
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+/// 表格文件生成器, 逐行写入数据, 最后调用finish获取文件内容
+pub enum SheetWriter {
+    Csv(Vec<u8>),
+    Xlsx(Box<Worksheet>, u32),
+}
+
+impl SheetWriter {
+    pub fn new(format: SheetFormat) -> Self {
+        match format {
+            // 写入utf8 bom, 避免excel打开时中文乱码
+            SheetFormat::Csv => Self::Csv(UTF8_BOM.to_vec()),
+            SheetFormat::Xlsx => Self::Xlsx(Box::new(Worksheet::new()), 0),
+        }
+    }
+
+    /// 写入一行数据
+    pub fn write_row<S: AsRef<str>>(&mut self, row: &[S]) -> Result<()> {
+        match self {
+            Self::Csv(buf) => {
+                for (i, v) in row.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(b',');
+                    }
+                    write_csv_field(buf, v.as_ref());
+                }
+                buf.extend_from_slice(b"\r\n");
+            }
+            Self::Xlsx(ws, row_num) => {
+                for (i, v) in row.iter().enumerate() {
+                    if i > u16::MAX as usize {
+                        bail!("xlsx文件的列数超出限制");
+                    }
+                    ws.write_string(*row_num, i as u16, v.as_ref())?;
+                }
+                *row_num += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// 取出已生成的内容, 用于分段输出, xlsx格式需要在结束时才能生成, 返回空
+    pub fn take(&mut self) -> Vec<u8> {
+        match self {
+            Self::Csv(buf) => std::mem::take(buf),
+            Self::Xlsx(..) => Vec::new(),
+        }
+    }
+
+    /// 结束写入, 返回生成的文件内容(已取出的内容除外)
+    pub fn finish(self) -> Result<Vec<u8>> {
+        match self {
+            Self::Csv(buf) => Ok(buf),
+            Self::Xlsx(ws, _) => {
+                let mut wb = Workbook::new();
+                wb.push_worksheet(*ws);
+                wb.save_to_buffer().context("生成xlsx文件失败")
+            }
+        }
+    }
+}
+
+/// 写入csv字段, 包含逗号、引号或换行符时使用双引号包裹并转义,
+/// 以公式字符开头的内容前面添加单引号, 避免打开文件时被当作公式执行(csv注入)
+pub fn write_csv_field(buf: &mut Vec<u8>, value: &str) {
+    let escaped;
+    let value = if value.starts_with(FORMULA_PREFIXES) {
+        escaped = format!("{FORMULA_ESCAPE}{value}");
+        escaped.as_str()
+    } else {
+        value
+    };
+
+    if value.contains([',', '"', '\r', '\n']) {
+        buf.push(b'"');
+        for b in value.bytes() {
+            if b == b'"' {
+                buf.push(b'"');
+            }
+            buf.push(b);
+        }
+        buf.push(b'"');
+    } else {
+        buf.extend_from_slice(value.as_bytes());
+    }
+}
+
+/// 去除写入csv文件时添加在公式内容前的单引号
+fn unescape_formula(value: &str) -> &str {
+    match value.strip_prefix(FORMULA_ESCAPE) {
+        Some(v) if v.starts_with(FORMULA_PREFIXES) => v,
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<Vec<String>> {
+        vec![
+            vec!["用户名".to_owned(), "昵称".to_owned()],
+            vec!["kiven".to_owned(), "a,\"b\"".to_owned()],
+            vec!["13800138000".to_owned(), String::new()],
+        ]
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut w = SheetWriter::new(SheetFormat::Csv);
+        for row in sample().iter() {
+            w.write_row(row).unwrap();
+        }
+        w.write_row(&[" ", ""]).unwrap();
+        let data = w.finish().unwrap();
+        assert!(data.starts_with(UTF8_BOM));
+        let rows = read(SheetFormat::Csv, &data).unwrap();
+        assert_eq!(&rows[..3], &sample()[..]);
+        assert!(is_empty_row(&rows[3]));
+    }
+
+    #[test]
+    fn test_csv_formula() {
+        let mut buf = Vec::new();
+        write_csv_field(&mut buf, "=HYPERLINK(\"http://a\")");
+        assert_eq!(buf, b"\"'=HYPERLINK(\"\"http://a\"\")\"");
+
+        let mut w = SheetWriter::new(SheetFormat::Csv);
+        let row = ["-1", "@sum", "'abc", "a=b"];
+        w.write_row(&row).unwrap();
+        let data = w.finish().unwrap();
+        assert!(data.ends_with(b"'-1,'@sum,'abc,a=b\r\n"));
+        assert_eq!(read(SheetFormat::Csv, &data).unwrap(), vec![row.to_vec()]);
+    }
+
+    #[test]
+    fn test_take() {
+        let mut w = SheetWriter::new(SheetFormat::Csv);
+        let mut data = Vec::new();
+        for row in sample().iter() {
+            w.write_row(row).unwrap();
+            data.extend(w.take());
+        }
+        data.extend(w.finish().unwrap());
+        assert_eq!(read(SheetFormat::Csv, &data).unwrap(), sample());
+
+        let mut w = SheetWriter::new(SheetFormat::Xlsx);
+        w.write_row(&sample()[0]).unwrap();
+        assert!(w.take().is_empty());
+    }
+
+    #[test]
+    fn test_xlsx() {
+        let mut w = SheetWriter::new(SheetFormat::Xlsx);
+        for row in sample().iter() {
+            w.write_row(row).unwrap();
+        }
+        let data = w.finish().unwrap();
+        assert_eq!(read(SheetFormat::Xlsx, &data).unwrap(), sample());
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(SheetFormat::from_name("XLSX"), Some(SheetFormat::Xlsx));
+        assert_eq!(SheetFormat::from_name("xls"), None);
+        assert_eq!(
+            SheetFormat::from_content_type("text/csv; charset=utf-8"),
+            Some(SheetFormat::Csv)
+        );
+        assert_eq!(
+            SheetFormat::from_content_type(XLSX_CONTENT_TYPE),
+            Some(SheetFormat::Xlsx)
+        );
+    }
+}
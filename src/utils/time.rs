@@ -8,6 +8,13 @@ pub fn unix_timestamp() -> u64 {
         .as_secs()
 }
 
+/// 将yyyy-mm-dd格式的日期转换为该日期UTC零点的unix时间戳
+pub fn date_to_timestamp(date: &str) -> Option<u64> {
+    let format = time::macros::format_description!("[year]-[month]-[day]");
+    let date = time::Date::parse(date, &format).ok()?;
+    u64::try_from(date.midnight().assume_utc().unix_timestamp()).ok()
+}
+
 /// 秒值转换为基于友好时间表示的时间字符串
 pub fn gen_time_desc(mut timestamp_secs: u32) -> String {
     let mut num_buf = itoa::Buffer::new();
@@ -0,0 +1,190 @@
+//! 基于时间的一次性口令(RFC 6238 TOTP)
+use rand::RngCore;
+use sha1::{Digest, Sha1};
+
+/// 口令的有效时间步长(单位: 秒)
+pub const TIME_STEP: u64 = 30;
+/// 口令的长度
+pub const DIGITS: u32 = 6;
+/// 密钥的字节长度
+const SECRET_LEN: usize = 20;
+/// HMAC-SHA1的块长度
+const BLOCK_SIZE: usize = 64;
+const BASE32_CHARS: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// 生成随机的密钥, 返回base32编码的字符串
+pub fn gen_secret() -> String {
+    let mut buf = [0u8; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut buf);
+    base32_encode(&buf)
+}
+
+/// 生成认证器app使用的otpauth链接
+///
+/// Arguments:
+///
+/// * `issuer`: 发行方名称
+/// * `account`: 用户账号
+/// * `secret`: base32编码的密钥
+///
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let enc = |s: &str| form_urlencoded::byte_serialize(s.as_bytes()).collect::<String>();
+    let issuer = enc(issuer).replace('+', "%20");
+    let account = enc(account).replace('+', "%20");
+
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}\
+            &algorithm=SHA1&digits={DIGITS}&period={TIME_STEP}"
+    )
+}
+
+/// 计算指定时间步的口令
+///
+/// Arguments:
+///
+/// * `secret`: base32编码的密钥
+/// * `step`: 时间步, 即unix时间戳除以时间步长
+///
+pub fn gen_code(secret: &str, step: u64) -> Option<String> {
+    let key = base32_decode(secret)?;
+    let hash = hmac_sha1(&key, &step.to_be_bytes());
+
+    // 动态截断
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let bin = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    Some(format!(
+        "{:0width$}",
+        bin % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// 校验口令, 允许前后偏差skew个时间步, 成功返回匹配的时间步
+///
+/// Arguments:
+///
+/// * `secret`: base32编码的密钥
+/// * `code`: 用户输入的口令
+/// * `now`: 当前的unix时间戳(单位: 秒)
+/// * `skew`: 允许的时间步偏差
+///
+pub fn verify(secret: &str, code: &str, now: u64, skew: u64) -> Option<u64> {
+    if code.len() != DIGITS as usize || !code.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let step = now / TIME_STEP;
+    for s in step.saturating_sub(skew)..=step + skew {
+        if gen_code(secret, s)? == code {
+            return Some(s);
+        }
+    }
+
+    None
+}
+
+fn hmac_sha1(key: &[u8], data: &[u8]) -> [u8; 20] {
+    let mut k = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        k[..20].copy_from_slice(&Sha1::digest(key));
+    } else {
+        k[..key.len()].copy_from_slice(key);
+    }
+
+    let mut ipad = [0x36u8; BLOCK_SIZE];
+    let mut opad = [0x5cu8; BLOCK_SIZE];
+    for i in 0..BLOCK_SIZE {
+        ipad[i] ^= k[i];
+        opad[i] ^= k[i];
+    }
+
+    let inner = Sha1::new().chain_update(ipad).chain_update(data).finalize();
+    let outer = Sha1::new()
+        .chain_update(opad)
+        .chain_update(inner)
+        .finalize();
+
+    outer.into()
+}
+
+/// base32编码(RFC 4648, 不带填充字符)
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() * 8).div_ceil(5));
+    let (mut buf, mut bits) = (0u32, 0);
+
+    for b in data {
+        buf = (buf << 8) | *b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_CHARS[((buf >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_CHARS[((buf << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    out
+}
+
+/// base32解码, 忽略大小写、空格及填充字符
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut buf, mut bits) = (0u32, 0);
+
+    for c in s.bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a',
+            b'2'..=b'7' => c - b'2' + 26,
+            b'=' | b' ' | b'-' => continue,
+            _ => return None,
+        };
+        buf = (buf << 5) | v as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buf >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 附录B的测试密钥 "12345678901234567890"
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(base32_encode(b"12345678901234567890"), SECRET);
+        assert_eq!(base32_decode(SECRET).unwrap(), b"12345678901234567890");
+        assert_eq!(base32_encode(b"f"), "MY");
+        assert_eq!(base32_decode("my======").unwrap(), b"f");
+    }
+
+    #[test]
+    fn test_totp() {
+        assert_eq!(gen_code(SECRET, 59 / TIME_STEP).unwrap(), "287082");
+        assert_eq!(gen_code(SECRET, 1111111109 / TIME_STEP).unwrap(), "081804");
+        assert_eq!(gen_code(SECRET, 1234567890 / TIME_STEP).unwrap(), "005924");
+        assert_eq!(
+            verify(SECRET, "081804", 1111111109 + TIME_STEP, 1),
+            Some(1111111109 / TIME_STEP)
+        );
+        assert_eq!(
+            verify(SECRET, "081804", 1111111109 + TIME_STEP * 2, 1),
+            None
+        );
+        assert_eq!(verify(SECRET, "08180", 1111111109, 1), None);
+    }
+}